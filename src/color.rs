@@ -0,0 +1,74 @@
+// Just enough ANSI escape codes to color the output, without pulling in a whole terminal crate
+
+use std::io::IsTerminal;
+
+use regex::Regex;
+
+const RESET: &str = "\x1b[0m";
+pub const DIM: &str = "\x1b[2m";
+pub const GREEN: &str = "\x1b[32m";
+pub const BOLD_RED: &str = "\x1b[1;31m";
+// Clear the whole screen and move the cursor to the top left corner
+pub const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ColorMode {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorMode {
+    // Colors in a file or pipe only get in the way, so "auto" only colors when a human is looking at the terminal.
+    // See https://no-color.org for NO_COLOR
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+            ColorMode::Auto => {
+                std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+                    && std::io::stdout().is_terminal()
+            }
+        }
+    }
+}
+
+pub fn paint(text: &str, style: &str) -> String {
+    format!("{}{}{}", style, text, RESET)
+}
+
+// Paint every match of the expression. Empty matches are skipped, since there would be nothing to see
+pub fn highlight(text: &str, regex: &Regex, style: &str) -> String {
+    let mut highlighted = String::with_capacity(text.len());
+    let mut end_of_last_match = 0;
+
+    for found in regex
+        .find_iter(text)
+        .filter(|found| !found.as_str().is_empty())
+    {
+        highlighted.push_str(&text[end_of_last_match..found.start()]);
+        highlighted.push_str(&paint(found.as_str(), style));
+        end_of_last_match = found.end();
+    }
+    highlighted.push_str(&text[end_of_last_match..]);
+
+    highlighted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_highlight() {
+        let regex = Regex::new("o+").unwrap();
+        assert_eq!(
+            highlight("foo bar boo", &regex, BOLD_RED),
+            "f\x1b[1;31moo\x1b[0m bar b\x1b[1;31moo\x1b[0m"
+        );
+        assert_eq!(highlight("bar", &regex, BOLD_RED), "bar");
+
+        let regex = Regex::new("x*").unwrap();
+        assert_eq!(highlight("bar", &regex, BOLD_RED), "bar");
+    }
+}
@@ -0,0 +1,165 @@
+// Defaults for the options that are the same every time, e.g. the rate or the colors, read from a TOML file like
+//
+//     rate = 30
+//     color = "always"
+//     timestamp = "%H:%M:%S"
+//
+// Every key is the long name of an option. The options end up in front of the ones given on the command line, unless those
+// already say otherwise
+
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+// Only options that change how things look or how often they happen. Which files are read, or how many lines, differs too much
+// from one time to the next to have a default
+const KEYS: &[&str] = &[
+    "rate",
+    "delay",
+    "color",
+    "timestamp",
+    "separator",
+    "number-format",
+    "prefix",
+    "format",
+    "encoding",
+];
+
+#[derive(Debug, PartialEq)]
+pub struct ConfigOption {
+    key: &'static str,
+    value: Option<String>, // Flags don't have any
+}
+
+impl ConfigOption {
+    // Always with an equals sign, since that's the only way --timestamp takes a format, and values might start with a dash
+    fn to_argument(&self) -> OsString {
+        match &self.value {
+            Some(value) => format!("--{}={}", self.key, value).into(),
+            None => format!("--{}", self.key).into(),
+        }
+    }
+}
+
+// Given with --config, or where config files usually are, if there is one
+pub fn path(arguments: &[OsString]) -> Option<PathBuf> {
+    let mut arguments = arguments.iter().skip(1);
+    while let Some(argument) = arguments.next() {
+        if argument == "--config" {
+            return arguments.next().map(PathBuf::from);
+        }
+        if let Some(path) = argument
+            .to_str()
+            .and_then(|argument| argument.strip_prefix("--config="))
+        {
+            return Some(PathBuf::from(path));
+        }
+    }
+
+    let directory = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(directory) if !directory.is_empty() => PathBuf::from(directory),
+        _ if cfg!(windows) => PathBuf::from(std::env::var_os("APPDATA")?),
+        _ => Path::new(&std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(directory.join("tail").join("config.toml")).filter(|path| path.is_file())
+}
+
+pub fn options(path: &Path) -> Result<Vec<ConfigOption>> {
+    let text = std::fs::read_to_string(path).context("Unable to read it")?;
+    let table = text.parse::<toml::Table>().context("It isn't valid TOML")?;
+
+    let mut options = vec![];
+    for (key, value) in table {
+        let key = KEYS
+            .iter()
+            .find(|known| **known == key)
+            .ok_or_else(|| anyhow!("\"{}\" isn't an option that can be set there", key))?;
+        let value = match value {
+            toml::Value::String(text) => Some(text),
+            toml::Value::Integer(number) => Some(number.to_string()),
+            toml::Value::Float(number) => Some(number.to_string()),
+            toml::Value::Boolean(true) => None,
+            toml::Value::Boolean(false) => continue,
+            _ => bail!("\"{}\" should be a string, a number or true", key),
+        };
+        options.push(ConfigOption { key, value });
+    }
+    Ok(options)
+}
+
+// The options of the config file go right behind the name of the program, so that they can't be mistaken for the values of
+// the given ones. Options that have been given anyway are left out, since those win
+pub fn merge(options: &[ConfigOption], arguments: &[OsString]) -> Vec<OsString> {
+    let is_given = |key: &str| {
+        arguments.iter().skip(1).any(|argument| {
+            argument.to_str().is_some_and(|argument| {
+                argument
+                    .strip_prefix("--")
+                    .and_then(|argument| argument.strip_prefix(key))
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('='))
+            })
+        })
+    };
+
+    let mut merged: Vec<OsString> = arguments.iter().take(1).cloned().collect();
+    merged.extend(
+        options
+            .iter()
+            .filter(|option| !is_given(option.key))
+            .map(ConfigOption::to_argument),
+    );
+    merged.extend(arguments.iter().skip(1).cloned());
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_arguments(arguments: &[&str]) -> Vec<OsString> {
+        arguments.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn test_config_file() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let path = directory.path().join("config.toml");
+        std::fs::write(
+            &path,
+            "rate = 30\ndelay = \"250ms\"\ntimestamp = \"%H:%M:%S\"\nseparator = \" - \"\ncolor = false\n",
+        )?;
+
+        let arguments = to_arguments(&[
+            "tail",
+            "--config",
+            path.to_str().unwrap(),
+            "--rate=5",
+            "app.log",
+        ]);
+        assert_eq!(super::path(&arguments), Some(path.clone()));
+        let options = super::options(&path)?;
+        assert_eq!(options.len(), 4);
+
+        // The rate given on the command line wins, the rest comes from the file
+        let matches = crate::app().get_matches_from_safe(merge(&options, &arguments))?;
+        assert_eq!(matches.value_of("rate"), Some("5"));
+        assert_eq!(matches.value_of("delay"), Some("250ms"));
+        assert_eq!(matches.value_of("timestamp"), Some("%H:%M:%S"));
+        assert_eq!(matches.value_of("separator"), Some(" - "));
+        assert_eq!(matches.value_of("color"), None);
+        assert_eq!(matches.value_of("file"), Some("app.log"));
+
+        // Options that aren't known, or can't be set there, make the file useless
+        std::fs::write(&path, "n = 5\n")?;
+        assert!(super::options(&path).is_err());
+        std::fs::write(&path, "rate = [30]\n")?;
+        assert!(super::options(&path).is_err());
+        std::fs::write(&path, "rate = \n")?;
+        assert!(super::options(&path).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,3455 @@
+//! Reading lines from the end of a file, and following it while it grows. The `tail` binary is built on top of this,
+//! but other programs can use it, too, without having to run `tail` and parse its output:
+//!
+//! ```
+//! use std::fs::OpenOptions;
+//! use std::io::Write;
+//! use std::sync::mpsc;
+//!
+//! # let directory = tempfile::tempdir()?;
+//! let path = directory.path().join("embedded.log");
+//! std::fs::write(&path, "first\nsecond\nthird\n")?;
+//!
+//! // Append another line once the last lines have been passed along, while the file is being followed
+//! let (send_ready, ready) = mpsc::channel();
+//! let appended_path = path.clone();
+//! std::thread::spawn(move || -> std::io::Result<()> {
+//!     ready.recv().ok();
+//!     writeln!(OpenOptions::new().append(true).open(appended_path)?, "fourth")
+//! });
+//!
+//! let mut options = tail::TailOptions::new(&path);
+//! options.line_count = 2;
+//!
+//! let mut received = vec![];
+//! tail::follow(options, |(line_number, line)| {
+//!     received.push(format!("{}: {}", line_number, line.trim_end()));
+//!     if received.len() == 2 {
+//!         send_ready.send(()).ok();
+//!     }
+//!     received.len() < 3 // Keep following until the new line has shown up
+//! })?;
+//! assert_eq!(received, ["2: second", "3: third", "4: fourth"]);
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use std::{
+    collections::VecDeque,
+    convert::TryFrom,
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Context, Result};
+use chrono::{
+    format::{parse_and_remainder, Parsed, StrftimeItems},
+    Datelike, NaiveDateTime, NaiveTime,
+};
+use crossbeam_channel::Receiver;
+use encoding_rs::Encoding;
+use encoding_rs_io::{DecodeReaderBytes, DecodeReaderBytesBuilder};
+use regex::Regex;
+use thiserror::Error;
+
+pub mod monitor;
+
+/// A line along with its number, counted from 1 at the beginning of the data. The line ending is kept
+pub type Line = (u64, String);
+
+/// What went wrong with a file, telling the path or the line where it happened
+#[derive(Debug, Error)]
+pub enum FileError {
+    #[error("Unable to access file: \"{path}\"")]
+    Access {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Unable to read line: {error_line}")]
+    Read {
+        valid_reads: Vec<Line>,
+        error_line: u64,
+        source: std::io::Error,
+    },
+    #[error("Unable to read bytes at offset: {offset}")]
+    ReadBytes { offset: u64, source: std::io::Error },
+    #[error("The path {path:?} points to a directory. It should point to a file")]
+    Directory { path: PathBuf },
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+/// Which way lines are collected. Bottom to top puts the last line of the file first
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ReadingDirection {
+    TopToBottom,
+    BottomToTop,
+}
+
+/// A line (or byte) counted from the beginning or from the end of the data, like `-n +N` and `-n N`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Position {
+    FromBegin(u64),
+    FromEnd(u64),
+}
+
+/// What happens to line numbers that grow beyond the digits they may have
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum OverflowPolicy {
+    Saturate,
+    Reset,
+}
+
+/// Keeps line numbers from silently wrapping around when following a file for a very long time
+#[derive(Debug, Clone)]
+pub struct LineNumbering {
+    limit: u64,
+    policy: OverflowPolicy,
+    overflowed: bool, // Since the last report
+    warned: bool,
+}
+
+impl LineNumbering {
+    /// Without `max_digits`, line numbers only run out at `u64::MAX`
+    pub fn new(max_digits: Option<u32>, policy: OverflowPolicy) -> Self {
+        let limit = max_digits
+            .and_then(|digits| 10u64.checked_pow(digits))
+            .map_or(u64::MAX, |power| power - 1);
+
+        Self {
+            limit,
+            policy,
+            overflowed: false,
+            warned: false,
+        }
+    }
+
+    // Shift a line number by the given offset, applying the overflow policy if the result becomes too large
+    fn offset(&mut self, line_number: u64, offset: u64) -> u64 {
+        match line_number.checked_add(offset) {
+            Some(number) if number <= self.limit => number,
+            _ => {
+                self.overflowed = true;
+                match self.policy {
+                    OverflowPolicy::Saturate => self.limit,
+                    OverflowPolicy::Reset => {
+                        // Wrap around to 1 instead of 0. u128 can't overflow here, since both numbers are at most u64::MAX
+                        let number = line_number as u128 + offset as u128;
+                        ((number - 1) % self.limit as u128 + 1) as u64
+                    }
+                }
+            }
+        }
+    }
+
+    // Notify about overflows that happened since the last call
+    fn report_overflow(&mut self) {
+        if !self.overflowed {
+            return;
+        }
+        self.overflowed = false;
+
+        match self.policy {
+            OverflowPolicy::Saturate => {
+                if !self.warned {
+                    eprintln!(
+                        "tail: line numbers exceeded {}. Further lines will all be numbered {}",
+                        self.limit, self.limit
+                    );
+                    self.warned = true;
+                }
+            }
+            OverflowPolicy::Reset => {
+                eprintln!(
+                    "tail: line numbers exceeded {} and have been restarted from 1",
+                    self.limit
+                );
+            }
+        }
+    }
+}
+
+/// Read the lines between `start` and `stop`, numbered from the beginning of the data
+pub fn read_lines<Readable: Read>(
+    data: Readable,
+    start: Position,
+    stop: Position,
+    direction: ReadingDirection,
+    line_format: LineFormat,
+) -> std::result::Result<Vec<Line>, FileError> {
+    read_matching_lines(data, start, stop, direction, line_format, None)
+}
+
+/// Same as `read_lines`, but reading also stops at the first line from `start` on for which `stop_at` returns true, e.g. at a
+/// marker. That line is still included. Lines are handed to `stop_at` in the order of the data, whichever the direction
+pub fn read_lines_until<Readable: Read>(
+    data: Readable,
+    start: Position,
+    stop: Position,
+    direction: ReadingDirection,
+    line_format: LineFormat,
+    stop_at: impl FnMut(&Line) -> bool,
+) -> std::result::Result<Vec<Line>, FileError> {
+    read_lines_filtered(data, start, stop, direction, line_format, None, stop_at)
+}
+
+/// Same as read_lines, but only lines that pass the filter are kept. Filtering while reading makes "-n 5" mean the last 5 matching
+/// lines, instead of those of the last 5 lines that happen to match. Line numbers still count every line, though
+pub fn read_matching_lines<Readable: Read>(
+    data: Readable,
+    start: Position,
+    stop: Position,
+    direction: ReadingDirection,
+    line_format: LineFormat,
+    line_filter: Option<&LineFilter>,
+) -> std::result::Result<Vec<Line>, FileError> {
+    read_lines_filtered(
+        data,
+        start,
+        stop,
+        direction,
+        line_format,
+        line_filter,
+        |_| false,
+    )
+}
+
+// Where all the ways of reading lines at once end up. Positions only say where to stop in advance, stop_at can decide on the way
+fn read_lines_filtered<Readable: Read>(
+    data: Readable,
+    mut start: Position,
+    mut stop: Position,
+    direction: ReadingDirection,
+    line_format: LineFormat,
+    line_filter: Option<&LineFilter>,
+    mut stop_at: impl FnMut(&Line) -> bool,
+) -> std::result::Result<Vec<Line>, FileError> {
+    match direction {
+        ReadingDirection::TopToBottom => match (start, stop) {
+            (Position::FromBegin(a), Position::FromBegin(b)) => {
+                if a >= b {
+                    return Ok(vec![]);
+                }
+            }
+            (Position::FromBegin(_), Position::FromEnd(_)) => {}
+            (Position::FromEnd(_), Position::FromBegin(_)) => {}
+            (Position::FromEnd(a), Position::FromEnd(b)) => {
+                if a <= b {
+                    return Ok(vec![]);
+                }
+            }
+        },
+        ReadingDirection::BottomToTop => match (start, stop) {
+            (Position::FromBegin(a), Position::FromBegin(b)) => {
+                if a <= b {
+                    return Ok(vec![]);
+                } else {
+                    (start, stop) = (stop, start);
+                }
+            }
+            (Position::FromBegin(_), Position::FromEnd(_)) => (start, stop) = (stop, start),
+            (Position::FromEnd(_), Position::FromBegin(_)) => (start, stop) = (stop, start),
+            (Position::FromEnd(a), Position::FromEnd(b)) => {
+                if a >= b {
+                    return Ok(vec![]);
+                } else {
+                    (start, stop) = (stop, start);
+                }
+            }
+        },
+    }
+
+    let mut reader = BufReader::new(decoding_reader(data, line_format.encoding));
+
+    let mut lines = VecDeque::new();
+    let mut line_count = 0;
+    let mut line_buffer = vec![];
+    let mut context = ContextState::default();
+    let mut selected = vec![];
+    let mut buffered_bytes = 0;
+    let mut dropped_count = 0u64; // Lines that didn't fit into line_format.max_buffer_bytes
+    let mut skipped_in_a_row = 0;
+    let reading_first_lines = matches!(
+        (start, stop),
+        (Position::FromBegin(_), Position::FromBegin(_))
+    );
+
+    // Keep on reading
+    'reading: loop {
+        // When to store line?
+        // -> If start is FromBegin(pos) and line_count >= pos
+        // -> If start is FromEnd (since we don't know the total line count before hand)
+        // When to stop?
+        // -> If stop is FromBegin(pos) and line_count >= pos
+        // -> If end of file has been reached
+
+        // Check for stop condition. Reading from the beginning, enough lines have been found once the window is full
+        match (start, stop) {
+            (Position::FromBegin(a), Position::FromBegin(b)) if lines.len() as u64 >= b - a => {
+                break
+            }
+            (Position::FromEnd(_), Position::FromBegin(pos)) if line_count >= pos => break,
+            _ => {}
+        }
+
+        let line = read_line(&mut reader, line_format, &mut line_buffer);
+        line_count += 1;
+
+        let line = match line {
+            Ok(line) => {
+                if line.is_empty() {
+                    // End of file reached
+                    break;
+                }
+                skipped_in_a_row = 0;
+                line
+            }
+            Err(error) if skip_line(line_format, line_count, &error, &mut skipped_in_a_row) => {
+                continue
+            }
+            Err(error) => {
+                return Err(FileError::Read {
+                    valid_reads: match direction {
+                        ReadingDirection::TopToBottom => lines.into(),
+                        ReadingDirection::BottomToTop => {
+                            lines.into_iter().rev().collect::<Vec<Line>>()
+                        }
+                    },
+                    error_line: line_count,
+                    source: error,
+                })
+            }
+        };
+
+        // Don't store line if wanted starting position hasn't been reached
+        if let Position::FromBegin(pos) = start {
+            if line_count <= pos {
+                continue;
+            }
+        }
+
+        let line = (line_count, line);
+        let stopping = stop_at(&line);
+
+        // Context lines count just like matches, so "-n 5" means the last 5 lines grep would print
+        match line_filter {
+            Some(line_filter) => line_filter.select_line(line, &mut context, &mut selected),
+            None => selected.push(line),
+        }
+
+        for line in selected.drain(..) {
+            buffered_bytes += line.1.len();
+            lines.push_back(line);
+
+            // Drop lines making the container larger than wanted
+            match (start, stop) {
+                (Position::FromBegin(a), Position::FromBegin(b)) => {
+                    if lines.len() as u64 > b - a {
+                        pop_oldest(&mut lines, &mut buffered_bytes);
+                    }
+                }
+                (Position::FromBegin(_), Position::FromEnd(_)) => {}
+                (Position::FromEnd(a), Position::FromBegin(_)) => {
+                    if lines.len() as u64 > a {
+                        pop_oldest(&mut lines, &mut buffered_bytes);
+                    }
+                }
+                (Position::FromEnd(a), Position::FromEnd(_)) => {
+                    if lines.len() as u64 > a {
+                        pop_oldest(&mut lines, &mut buffered_bytes);
+                    }
+                }
+            }
+
+            // The latest line is always kept, so there is something to show, no matter how long it is
+            if let Some(max_buffer_bytes) = line_format.max_buffer_bytes {
+                if reading_first_lines && buffered_bytes > max_buffer_bytes && lines.len() > 1 {
+                    lines.pop_back();
+                    eprintln!(
+                        "tail: stopped after {} lines, since more wouldn't fit into {} bytes",
+                        lines.len(),
+                        max_buffer_bytes
+                    );
+                    break 'reading;
+                }
+                while buffered_bytes > max_buffer_bytes && lines.len() > 1 {
+                    pop_oldest(&mut lines, &mut buffered_bytes);
+                    dropped_count += 1;
+                }
+            }
+        }
+
+        if stopping {
+            break;
+        }
+    }
+    if dropped_count > 0 {
+        eprintln!(
+            "tail: left out {} of the wanted lines, since they didn't fit into {} bytes",
+            dropped_count,
+            line_format.max_buffer_bytes.unwrap_or_default()
+        );
+    }
+
+    // Remove lines towards end of file that shouldn't be included
+    if let Position::FromEnd(n) = stop {
+        // Never more than the number of lines, so it fits into a usize again
+        let kept = (lines.len() as u64).saturating_sub(n) as usize;
+        lines.drain(kept..);
+    }
+
+    match direction {
+        ReadingDirection::TopToBottom => Ok(lines.into()),
+        ReadingDirection::BottomToTop => Ok(lines.into_iter().rev().collect::<Vec<Line>>()),
+    }
+}
+
+// Lines handed over at once while streaming. Few enough to start printing right away, and many enough to not flush every line
+const STREAM_BATCH_SIZE: usize = 1000;
+
+/// Hand the lines from `start` on over to `handle_lines` while reading, a batch at a time, instead of collecting all of them
+/// first. That only works top to bottom, where no line has to wait for the ones after it. Without `stop`, reading goes on to
+/// the end. Returns how many lines have been handed over
+pub fn stream_lines<Readable: Read>(
+    data: Readable,
+    start: u64,
+    stop: Option<u64>,
+    line_format: LineFormat,
+    line_filter: Option<&LineFilter>,
+    mut handle_lines: impl FnMut(Vec<Line>) -> Result<()>,
+) -> Result<u64> {
+    let mut reader = BufReader::new(decoding_reader(data, line_format.encoding));
+    let mut line_count = 0;
+    let mut line_buffer = vec![];
+    let mut context = ContextState::default();
+    let mut batch = vec![];
+    let mut handed_over = 0;
+    let mut skipped_in_a_row = 0;
+
+    while stop.is_none_or(|stop| start + handed_over + (batch.len() as u64) < stop) {
+        let line = read_line(&mut reader, line_format, &mut line_buffer);
+        line_count += 1;
+
+        let line = match line {
+            Ok(line) if line.is_empty() => break,
+            Ok(line) => {
+                skipped_in_a_row = 0;
+                line
+            }
+            Err(error) if skip_line(line_format, line_count, &error, &mut skipped_in_a_row) => {
+                continue
+            }
+            Err(error) => {
+                // What has been read so far is fine
+                handle_lines(batch)?;
+                return Err(FileError::Read {
+                    valid_reads: vec![],
+                    error_line: line_count,
+                    source: error,
+                }
+                .into());
+            }
+        };
+        if line_count <= start {
+            continue;
+        }
+
+        match line_filter {
+            Some(line_filter) => {
+                line_filter.select_line((line_count, line), &mut context, &mut batch)
+            }
+            None => batch.push((line_count, line)),
+        }
+        // Context lines might have pushed the batch beyond the stop
+        if let Some(stop) = stop {
+            batch.truncate(stop.saturating_sub(start + handed_over) as usize);
+        }
+
+        if batch.len() >= STREAM_BATCH_SIZE {
+            handed_over += batch.len() as u64;
+            handle_lines(std::mem::take(&mut batch))?;
+        }
+    }
+
+    handed_over += batch.len() as u64;
+    if !batch.is_empty() {
+        handle_lines(batch)?;
+    }
+    Ok(handed_over)
+}
+
+/// Hand all lines over to `handle_lines` from the last one to the first one, a batch at a time, like tac. The data is read
+/// backwards in blocks, so that only a batch of lines is held in memory, no matter how large it is. Lines are still numbered
+/// from the top. Encodings other than UTF-8 might not end their lines with a single byte, so then all of the data is read and
+/// turned around instead. Returns how many lines have been handed over
+pub fn stream_lines_reversed<Readable: Read + Seek>(
+    mut data: Readable,
+    line_format: LineFormat,
+    mut handle_lines: impl FnMut(Vec<Line>) -> Result<()>,
+) -> Result<u64> {
+    if sniff_encoding(&mut data, line_format.encoding)? != encoding_rs::UTF_8 {
+        data.seek(SeekFrom::Start(0))?;
+        let mut lines = read_lines(
+            data,
+            Position::FromBegin(0),
+            Position::FromEnd(0),
+            ReadingDirection::TopToBottom,
+            line_format,
+        )?;
+        lines.reverse();
+        let count = lines.len() as u64;
+        if !lines.is_empty() {
+            handle_lines(lines)?;
+        }
+        return Ok(count);
+    }
+
+    // The last line is numbered first. Whatever is written after measuring the data is neither counted nor read
+    let length = data.seek(SeekFrom::End(0))?;
+    let mut line_number = count_lines(
+        &mut Bounded {
+            inner: &mut data,
+            length,
+        },
+        line_format.delimiter,
+    )?;
+    let mut buffer = vec![0; SEEK_BLOCK_SIZE];
+    // The end of the line that reaches into the next block, in pieces from the back to the front
+    let mut pending: Vec<Vec<u8>> = vec![];
+    let mut line_buffer = vec![];
+    let mut batch = vec![];
+    let mut handed_over = 0;
+
+    let mut push_line = |bytes: &[u8], batch: &mut Vec<Line>| -> Result<()> {
+        let line = read_line(&mut &*bytes, line_format, &mut line_buffer)?;
+        batch.push((line_number, line));
+        line_number -= 1;
+        if batch.len() >= STREAM_BATCH_SIZE {
+            handed_over += batch.len() as u64;
+            handle_lines(std::mem::take(batch))?;
+        }
+        Ok(())
+    };
+
+    let mut block_end = length;
+    while block_end > 0 {
+        let block_start = block_end.saturating_sub(SEEK_BLOCK_SIZE as u64);
+        let block = &mut buffer[..(block_end - block_start) as usize];
+        data.seek(SeekFrom::Start(block_start))?;
+        data.read_exact(block)?;
+
+        // Every delimiter completes the line after it, except for the one at the very end of the data
+        let mut line_end = block.len();
+        for index in (0..block.len()).rev() {
+            if block[index] != line_format.delimiter || block_start + index as u64 + 1 == length {
+                continue;
+            }
+            let mut line = block[index + 1..line_end].to_vec();
+            line.extend(pending.drain(..).rev().flatten());
+            push_line(&line, &mut batch)?;
+            line_end = index + 1;
+        }
+        pending.push(block[..line_end].to_vec());
+        block_end = block_start;
+    }
+    // The first line has no delimiter in front of it
+    let first_line = pending.into_iter().rev().flatten().collect::<Vec<_>>();
+    if !first_line.is_empty() {
+        push_line(&first_line, &mut batch)?;
+    }
+
+    handed_over += batch.len() as u64;
+    if !batch.is_empty() {
+        handle_lines(batch)?;
+    }
+    Ok(handed_over)
+}
+
+// With line_format.skip_errors, the line is left out, and reading goes on with the next one
+fn skip_line(
+    line_format: LineFormat,
+    line_number: u64,
+    error: &io::Error,
+    skipped_in_a_row: &mut u32,
+) -> bool {
+    if !line_format.skip_errors || *skipped_in_a_row >= MAX_SKIPPED_LINES_IN_A_ROW {
+        return false;
+    }
+    *skipped_in_a_row += 1;
+    eprintln!(
+        "tail: warning: skipped line {}, since it couldn't be read: {}",
+        line_number, error
+    );
+    true
+}
+
+fn pop_oldest(lines: &mut VecDeque<Line>, buffered_bytes: &mut usize) {
+    if let Some((_, line)) = lines.pop_front() {
+        *buffered_bytes -= line.len();
+    }
+}
+
+/// How the bytes of the data are turned into lines
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct LineFormat {
+    pub delimiter: u8,
+    pub encoding: &'static Encoding,
+    /// Characters kept per line. Whatever is beyond that is skipped while reading, and replaced by an ellipsis
+    pub max_line_length: Option<usize>,
+    /// Bytes of lines held in memory while reading. Looking for the last lines, the oldest are dropped beyond that. Looking for
+    /// the first lines, reading stops there
+    pub max_buffer_bytes: Option<usize>,
+    /// Lines that can't be read, e.g. because of an I/O glitch, are reported and left out, instead of ending the reading
+    pub skip_errors: bool,
+    /// ANSI escape sequences, like colors, are removed while reading, so that filters only see the text
+    pub strip_ansi: bool,
+}
+
+impl Default for LineFormat {
+    fn default() -> Self {
+        Self {
+            delimiter: b'\n',
+            encoding: encoding_rs::UTF_8,
+            max_line_length: None,
+            max_buffer_bytes: None,
+            skip_errors: false,
+            strip_ansi: false,
+        }
+    }
+}
+
+// Turn the data into UTF-8 before splitting it into lines, since a newline isn't always a single byte, e.g. in UTF-16.
+// A byte order mark at the beginning of the data wins over the given encoding. UTF-8 is passed through as it is
+fn decoding_reader<Readable: Read>(
+    data: Readable,
+    encoding: &'static Encoding,
+) -> DecodeReaderBytes<Readable, Vec<u8>> {
+    DecodeReaderBytesBuilder::new()
+        .encoding(Some(encoding).filter(|encoding| *encoding != encoding_rs::UTF_8))
+        .utf8_passthru(true)
+        .build(data)
+}
+
+/// A byte order mark at the beginning of the data wins over the given encoding
+pub fn sniff_encoding<Readable: Read + Seek>(
+    data: &mut Readable,
+    encoding: &'static Encoding,
+) -> io::Result<&'static Encoding> {
+    let position = data.stream_position()?;
+    data.seek(SeekFrom::Start(0))?;
+    let mut beginning = vec![];
+    data.take(3).read_to_end(&mut beginning)?;
+    data.seek(SeekFrom::Start(position))?;
+
+    Ok(Encoding::for_bom(&beginning).map_or(encoding, |(encoding, _)| encoding))
+}
+
+/// The compression formats that are recognized by their first bytes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+    Bzip2,
+}
+
+impl Compression {
+    // Checked against the first bytes of the data. Bzip2 also carries the block size, so plain text starting with "BZh" isn't
+    // mistaken for it
+    fn detect(beginning: &[u8]) -> Option<Compression> {
+        match beginning {
+            [0x1f, 0x8b, ..] => Some(Compression::Gzip),
+            [0x28, 0xb5, 0x2f, 0xfd, ..] => Some(Compression::Zstd),
+            [b'B', b'Z', b'h', b'1'..=b'9', ..] => Some(Compression::Bzip2),
+            _ => None,
+        }
+    }
+}
+
+const COMPRESSION_MAGIC_LENGTH: u64 = 4;
+
+/// Like the byte order mark, the magic bytes of a compressed file are at its very beginning. Afterwards, the data is where it
+/// has been before
+pub fn sniff_compression<Readable: Read + Seek>(
+    data: &mut Readable,
+) -> io::Result<Option<Compression>> {
+    let position = data.stream_position()?;
+    data.seek(SeekFrom::Start(0))?;
+    let mut beginning = vec![];
+    data.take(COMPRESSION_MAGIC_LENGTH)
+        .read_to_end(&mut beginning)?;
+    data.seek(SeekFrom::Start(position))?;
+
+    Ok(Compression::detect(&beginning))
+}
+
+/// Reads the data decompressed, from its beginning to its end
+pub fn decompressing_reader<'a, Readable: Read + 'a>(
+    data: Readable,
+    compression: Compression,
+) -> io::Result<Box<dyn Read + 'a>> {
+    Ok(match compression {
+        Compression::Gzip => Box::new(flate2::read::GzDecoder::new(data)),
+        Compression::Zstd => Box::new(zstd::stream::read::Decoder::new(data)?),
+        Compression::Bzip2 => Box::new(bzip2::read::BzDecoder::new(data)),
+    })
+}
+
+/// For data that can't seek back, like stdin. The peeked bytes are put back in front of the rest, so nothing is lost if the data
+/// turns out not to be compressed
+pub fn auto_decompress<'a, Readable: Read + 'a>(
+    mut data: Readable,
+) -> io::Result<Box<dyn Read + 'a>> {
+    let mut beginning = vec![];
+    (&mut data)
+        .take(COMPRESSION_MAGIC_LENGTH)
+        .read_to_end(&mut beginning)?;
+    let compression = Compression::detect(&beginning);
+    let data = io::Cursor::new(beginning).chain(data);
+
+    match compression {
+        Some(compression) => decompressing_reader(data, compression),
+        None => Ok(Box::new(data)),
+    }
+}
+
+/// Classic Mac files end their lines with a lone CR. If there's no LF anywhere near the beginning, but a CR, that's what lines
+/// end with. Otherwise it's LF, which also covers CRLF
+pub fn sniff_delimiter<Readable: Read + Seek>(data: &mut Readable) -> io::Result<u8> {
+    let position = data.stream_position()?;
+    data.seek(SeekFrom::Start(0))?;
+    let mut beginning = vec![];
+    data.take(COUNT_BLOCK_SIZE as u64)
+        .read_to_end(&mut beginning)?;
+    data.seek(SeekFrom::Start(position))?;
+
+    if !beginning.contains(&b'\n') && beginning.contains(&b'\r') {
+        Ok(b'\r')
+    } else {
+        Ok(b'\n')
+    }
+}
+
+// Logs often contain a stray byte or two that isn't valid UTF-8. That's no reason to stop reading, so these become replacement characters
+fn decode_line(bytes: &[u8], line_format: LineFormat) -> String {
+    let line = String::from_utf8_lossy(bytes);
+    if line_format.strip_ansi && line.contains(ESCAPE) {
+        strip_ansi(&line)
+    } else {
+        line.into_owned()
+    }
+}
+
+const ESCAPE: char = '\x1b';
+
+/// The text without its ANSI escape sequences: Control sequences like colors (ESC [ ... letter), operating system commands like
+/// window titles (ESC ] ... BEL or ESC \\) and the short ones (ESC letter, or ESC ( letter and the like)
+pub fn strip_ansi(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut characters = text.chars().peekable();
+
+    while let Some(character) = characters.next() {
+        if character != ESCAPE {
+            stripped.push(character);
+            continue;
+        }
+        match characters.next() {
+            // Parameters and intermediate bytes, up to the final byte
+            Some('[') => {
+                while characters
+                    .next_if(|character| ('\x20'..='\x3f').contains(character))
+                    .is_some()
+                {}
+                characters.next_if(|character| ('\x40'..='\x7e').contains(character));
+            }
+            Some(']') => {
+                while let Some(character) = characters.next() {
+                    if character == '\x07' {
+                        break;
+                    }
+                    if character == ESCAPE {
+                        characters.next_if_eq(&'\\');
+                        break;
+                    }
+                }
+            }
+            // E.g. choosing a character set, like ESC ( B
+            Some('\x20'..='\x2f') => {
+                while characters
+                    .next_if(|character| ('\x20'..='\x2f').contains(character))
+                    .is_some()
+                {}
+                characters.next();
+            }
+            Some(_) | None => {}
+        }
+    }
+    stripped
+}
+
+/// Marks the end of a line that has been cut short
+pub const TRUNCATION_MARKER: char = '…';
+
+// Read the next line, including its delimiter. An empty line means that the end of the data has been reached.
+// With a maximum line length, no more than the beginning of a long line is kept in memory, and the rest is skipped.
+// The line still ends at its delimiter, though, so that the following lines are counted as usual
+fn read_line<Readable: BufRead>(
+    reader: &mut Readable,
+    line_format: LineFormat,
+    line_buffer: &mut Vec<u8>,
+) -> io::Result<String> {
+    line_buffer.clear();
+    let max_length = match line_format.max_line_length {
+        Some(max_length) => max_length,
+        None => {
+            reader.read_until(line_format.delimiter, line_buffer)?;
+            return Ok(decode_line(line_buffer, line_format));
+        }
+    };
+
+    // A character takes up to four bytes in UTF-8
+    let max_bytes = max_length.saturating_mul(4);
+    let mut skipped = false;
+    let mut terminated = false;
+    while !terminated {
+        let available = match reader.fill_buf() {
+            Ok(available) => available,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error),
+        };
+        if available.is_empty() {
+            break; // End of data
+        }
+
+        let (chunk, used) = match available
+            .iter()
+            .position(|&byte| byte == line_format.delimiter)
+        {
+            Some(index) => {
+                terminated = true;
+                (&available[..index], index + 1)
+            }
+            None => (available, available.len()),
+        };
+        let room = max_bytes - line_buffer.len();
+        skipped |= chunk.len() > room;
+        line_buffer.extend_from_slice(&chunk[..chunk.len().min(room)]);
+        reader.consume(used);
+    }
+
+    let mut line = decode_line(line_buffer, line_format);
+    if let Some((end, _)) = line.char_indices().nth(max_length) {
+        line.truncate(end);
+        skipped = true;
+    }
+    if skipped {
+        line.push(TRUNCATION_MARKER);
+    }
+    if terminated {
+        line.push(char::from(line_format.delimiter));
+    }
+    Ok(line)
+}
+
+const SEEK_BLOCK_SIZE: usize = 8 * 1024;
+
+const COUNT_BLOCK_SIZE: usize = 64 * 1024;
+
+// Skipping unreadable lines gives up once this many of them come in a row, since the data most likely can't be read at all
+const MAX_SKIPPED_LINES_IN_A_ROW: u32 = 10;
+
+// Keeps track of how far the data has been read
+struct ByteCounter<Readable: Read> {
+    inner: Readable,
+    count: u64,
+}
+
+impl<Readable: Read> Read for ByteCounter<Readable> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buffer)?;
+        self.count += read as u64;
+        Ok(read)
+    }
+}
+
+// Only the data up to the given length, as if the rest hadn't been written yet
+struct Bounded<Readable: Read + Seek> {
+    inner: Readable,
+    length: u64,
+}
+
+impl<Readable: Read + Seek> Read for Bounded<Readable> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.length.saturating_sub(self.inner.stream_position()?);
+        let count = buffer
+            .len()
+            .min(usize::try_from(remaining).unwrap_or(usize::MAX));
+        self.inner.read(&mut buffer[..count])
+    }
+}
+
+impl<Readable: Read + Seek> Seek for Bounded<Readable> {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        match position {
+            SeekFrom::End(offset) => match self.length.checked_add_signed(offset) {
+                Some(position) => self.inner.seek(SeekFrom::Start(position)),
+                None => Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "seeking before the start of the data",
+                )),
+            },
+            position => self.inner.seek(position),
+        }
+    }
+}
+
+/// Where following a file continues from. Keeping the byte offset ourselves, instead of relying on where the file has been left,
+/// means that nothing depends on how far earlier reads happened to get
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReadCursor {
+    pub offset: u64,
+    pub line_number: u64, // Of the last read line, so that numbering continues from there
+    pub unfinished_line: Option<String>, // The last read line, if its delimiter hasn't been written yet
+    pub context: ContextState,
+}
+
+impl ReadCursor {
+    /// Behind the given line, which ends at the given offset
+    pub fn after(line: Option<&Line>, offset: u64, delimiter: u8) -> Self {
+        Self {
+            offset,
+            line_number: line.map_or(0, |(number, _)| *number),
+            unfinished_line: line
+                .filter(|(_, content)| !content.ends_with(char::from(delimiter)))
+                .map(|(_, content)| content.clone()),
+            context: ContextState::default(),
+        }
+    }
+}
+
+/// Where following continues once the data has been read up to `end`: behind the last line before it. Whatever has been written
+/// after `end` in the meantime is left for following, so that it's neither skipped nor read twice
+pub fn cursor_at<Readable: Read + Seek>(
+    data: Readable,
+    end: u64,
+    line_format: LineFormat,
+) -> std::result::Result<ReadCursor, FileError> {
+    let last_line = read_lines_seekable(
+        Bounded {
+            inner: data,
+            length: end,
+        },
+        Position::FromEnd(0),
+        Position::FromEnd(1),
+        ReadingDirection::BottomToTop,
+        line_format,
+    )?
+    .first()
+    .cloned();
+    Ok(ReadCursor::after(
+        last_line.as_ref(),
+        end,
+        line_format.delimiter,
+    ))
+}
+
+/// Read the lines that have been added since the last read, continuing the numbering from the last read line
+pub fn read_new_lines<Readable: Read + Seek>(
+    mut data: Readable,
+    cursor: &mut ReadCursor,
+    reading_direction: ReadingDirection,
+    line_numbering: &mut LineNumbering,
+    line_filter: Option<&LineFilter>,
+    line_format: LineFormat,
+) -> std::result::Result<Vec<Line>, FileError> {
+    let read_error = |error| FileError::Read {
+        valid_reads: vec![],
+        error_line: cursor.line_number + 1,
+        source: error,
+    };
+
+    // Everything from the cursor to the end is new. It's read straight from the file, instead of in one piece,
+    // so that a huge line only takes up as much memory as is kept of it
+    data.seek(SeekFrom::Start(cursor.offset))
+        .map_err(read_error)?;
+    let mut new_data = ByteCounter {
+        inner: &mut data,
+        count: 0,
+    };
+
+    let mut lines = read_lines(
+        &mut new_data,
+        Position::FromBegin(0),
+        Position::FromEnd(0),
+        ReadingDirection::TopToBottom,
+        line_format,
+    )?;
+
+    // The unfinished last line, before and after completing it
+    let mut completed_line = None;
+    let mut completed_count = 0; // The first new line doesn't count if it completes the last line
+    if let Some(fragment) = cursor.unfinished_line.take() {
+        if lines
+            .first()
+            .is_some_and(|(line_number, _)| *line_number == 1)
+        {
+            // The first new line is the rest of the previous last line
+            let (_, rest) = lines.remove(0);
+            let line = (cursor.line_number, format!("{}{}", fragment, rest));
+            completed_line = Some((fragment, line));
+            completed_count = 1;
+        } else {
+            cursor.unfinished_line = Some(fragment);
+        }
+    }
+
+    // Skipped lines still count
+    for (line_number, _) in lines.iter_mut() {
+        *line_number = line_numbering.offset(*line_number - completed_count, cursor.line_number);
+    }
+    line_numbering.report_overflow();
+
+    let last_line = lines
+        .last()
+        .or(completed_line.as_ref().map(|(_, line)| line));
+    if last_line.is_some() {
+        *cursor = ReadCursor {
+            context: std::mem::take(&mut cursor.context),
+            ..ReadCursor::after(last_line, cursor.offset, line_format.delimiter)
+        };
+    }
+    cursor.offset += new_data.count;
+
+    if let Some(line_filter) = line_filter {
+        lines = line_filter.select(lines, &mut cursor.context);
+
+        // Lines are checked as a whole, so the completed line might match now, even if its unfinished part didn't
+        if let Some((fragment, line)) = completed_line {
+            if !line_filter.matches(&fragment) && line_filter.matches(&line.1) {
+                lines.insert(0, line);
+            }
+        }
+    }
+
+    if reading_direction == ReadingDirection::BottomToTop {
+        lines.reverse();
+    }
+
+    Ok(lines)
+}
+
+/// Streams can't be watched for changes, so lines are handed over one by one, as soon as they arrive
+pub fn follow_stream<Readable: Read>(
+    data: Readable,
+    line_format: LineFormat,
+    interrupted: &AtomicBool,
+    mut handle_lines: impl FnMut(Vec<Line>) -> io::Result<()>,
+) -> std::result::Result<u64, FileError> {
+    let mut data = BufReader::new(decoding_reader(data, line_format.encoding));
+    let mut line_count = 0;
+    let mut line_buffer = vec![];
+    let mut skipped_in_a_row = 0;
+    let mut skipped_count = 0;
+
+    while !interrupted.load(Ordering::SeqCst) {
+        match read_line(&mut data, line_format, &mut line_buffer) {
+            Ok(line) if line.is_empty() => break, // End of stream reached
+            Ok(line) => {
+                line_count += 1;
+                skipped_in_a_row = 0;
+                handle_lines(vec![(line_count, line)]).context("Unable to write to stdout")?;
+            }
+            Err(error) if skip_line(line_format, line_count + 1, &error, &mut skipped_in_a_row) => {
+                line_count += 1;
+                skipped_count += 1;
+            }
+            Err(error) => {
+                return Err(FileError::Read {
+                    valid_reads: vec![],
+                    error_line: line_count + 1,
+                    source: error,
+                })
+            }
+        }
+    }
+
+    Ok(line_count - skipped_count)
+}
+
+/// Same as follow_stream, for bytes. They are handed over as they arrive, without waiting for a line to end. Returns how many
+/// bytes have been handed over
+pub fn follow_byte_stream<Readable: Read>(
+    mut data: Readable,
+    interrupted: &AtomicBool,
+    mut handle_bytes: impl FnMut(&[u8]) -> io::Result<()>,
+) -> io::Result<u64> {
+    let mut byte_count = 0;
+    let mut buffer = vec![0; COUNT_BLOCK_SIZE];
+
+    while !interrupted.load(Ordering::SeqCst) {
+        match data.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(count) => {
+                byte_count += count as u64;
+                handle_bytes(&buffer[..count])?;
+            }
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error),
+        }
+    }
+
+    Ok(byte_count)
+}
+
+/// A file that is smaller than what we have already read from it has been truncated, so start over from its beginning
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SizeChange {
+    Unchanged,
+    Grown,
+    Truncated,
+}
+
+/// Compare the size of the file with where reading stopped last time. Change notifications can come in twice for a single
+/// write, and then there is nothing new the second time around
+pub fn detect_size_change(file: &File, read_position: u64) -> io::Result<SizeChange> {
+    let size = file.metadata()?.len();
+    if size < read_position {
+        Ok(SizeChange::Truncated)
+    } else if size == read_position {
+        Ok(SizeChange::Unchanged)
+    } else {
+        Ok(SizeChange::Grown)
+    }
+}
+
+/// Same as read_lines, but jumps straight to the end of the data when only the last lines are wanted, instead of reading everything
+pub fn read_lines_seekable<Readable: Read + Seek>(
+    mut data: Readable,
+    start: Position,
+    stop: Position,
+    direction: ReadingDirection,
+    line_format: LineFormat,
+) -> std::result::Result<Vec<Line>, FileError> {
+    // Looking for line ends byte by byte only works if they are a single byte, like they are in ASCII
+    match sniff_encoding(&mut data, line_format.encoding) {
+        Ok(encoding) if encoding.is_ascii_compatible() => {}
+        _ => return read_lines(data, start, stop, direction, line_format),
+    }
+
+    let window = match (direction, start, stop) {
+        (ReadingDirection::TopToBottom, Position::FromEnd(a), Position::FromEnd(b)) if a > b => a,
+        (ReadingDirection::BottomToTop, Position::FromEnd(a), Position::FromEnd(b)) if a < b => b,
+        _ => return read_lines(data, start, stop, direction, line_format),
+    };
+
+    let offset = match find_offset_of_last_lines(&mut data, window, line_format.delimiter) {
+        Ok(offset) => offset,
+        Err(_) => {
+            // Not actually seekable, e.g. a pipe. Nothing has been read yet, so we can still go the slow way
+            return read_lines(data, start, stop, direction, line_format);
+        }
+    };
+
+    // Line numbers should still count from the beginning of the file, so the lines in front of the window have to be counted.
+    // This is a lot cheaper than reading them as lines, though
+    let read_error = |error| FileError::Read {
+        valid_reads: vec![],
+        error_line: 1,
+        source: error,
+    };
+    let preceding_lines =
+        count_lines_before(&mut data, offset, line_format.delimiter).map_err(read_error)?;
+    data.seek(SeekFrom::Start(offset)).map_err(read_error)?;
+
+    let renumber = |lines: &mut Vec<Line>| {
+        for (line_number, _) in lines {
+            *line_number += preceding_lines;
+        }
+    };
+
+    match read_lines(&mut data, start, stop, direction, line_format) {
+        Ok(mut lines) => {
+            renumber(&mut lines);
+            Ok(lines)
+        }
+        Err(FileError::Read {
+            mut valid_reads,
+            error_line,
+            source,
+        }) => {
+            renumber(&mut valid_reads);
+            Err(FileError::Read {
+                valid_reads,
+                error_line: error_line + preceding_lines,
+                source,
+            })
+        }
+        Err(error) => Err(error),
+    }
+}
+
+// Find where the last lines begin by reading backwards from the end in blocks
+fn find_offset_of_last_lines<Readable: Read + Seek>(
+    data: &mut Readable,
+    count: u64,
+    delimiter: u8,
+) -> io::Result<u64> {
+    let length = data.seek(SeekFrom::End(0))?;
+    if count == 0 {
+        return Ok(length);
+    }
+
+    let mut buffer = vec![0; SEEK_BLOCK_SIZE];
+    let mut newline_count = 0;
+    let mut block_end = length;
+
+    while block_end > 0 {
+        let block_start = block_end.saturating_sub(SEEK_BLOCK_SIZE as u64);
+        let block = &mut buffer[..(block_end - block_start) as usize];
+        data.seek(SeekFrom::Start(block_start))?;
+        data.read_exact(block)?;
+
+        for (index, byte) in block.iter().enumerate().rev() {
+            let position = block_start + index as u64;
+            // A newline at the very end only terminates the last line, it doesn't start a new one
+            if *byte == delimiter && position + 1 != length {
+                newline_count += 1;
+                if newline_count == count {
+                    return Ok(position + 1);
+                }
+            }
+        }
+
+        block_end = block_start;
+    }
+
+    Ok(0)
+}
+
+/// Counts the lines of whatever is read through it, so that data that can only be read once doesn't have to be read twice.
+/// A last line without a delimiter counts, too
+pub struct LineCounter<Readable> {
+    inner: Readable,
+    delimiter: u8,
+    delimiter_count: u64,
+    last_byte: Option<u8>,
+}
+
+impl<Readable: Read> LineCounter<Readable> {
+    /// Lines end with `delimiter`
+    pub fn new(inner: Readable, delimiter: u8) -> Self {
+        Self {
+            inner,
+            delimiter,
+            delimiter_count: 0,
+            last_byte: None,
+        }
+    }
+
+    /// The lines read so far
+    pub fn count(&self) -> u64 {
+        self.delimiter_count + u64::from(self.last_byte.is_some_and(|byte| byte != self.delimiter))
+    }
+}
+
+impl<Readable: Read> Read for LineCounter<Readable> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let count = self.inner.read(buffer)?;
+        let read = &buffer[..count];
+        self.delimiter_count += read.iter().filter(|byte| **byte == self.delimiter).count() as u64;
+        if let Some(byte) = read.last() {
+            self.last_byte = Some(*byte);
+        }
+        Ok(count)
+    }
+}
+
+/// All lines of the data, without keeping any of them around. Afterwards, the data is where it has been before
+pub fn count_lines<Readable: Read + Seek>(data: &mut Readable, delimiter: u8) -> io::Result<u64> {
+    let position = data.stream_position()?;
+    data.seek(SeekFrom::Start(0))?;
+    let mut counter = LineCounter::new(&mut *data, delimiter);
+    io::copy(&mut counter, &mut io::sink())?;
+    let count = counter.count();
+    data.seek(SeekFrom::Start(position))?;
+
+    Ok(count)
+}
+
+fn count_lines_before<Readable: Read + Seek>(
+    data: &mut Readable,
+    offset: u64,
+    delimiter: u8,
+) -> io::Result<u64> {
+    data.seek(SeekFrom::Start(0))?;
+
+    let mut data = data.take(offset);
+    let mut buffer = vec![0; COUNT_BLOCK_SIZE];
+    let mut newline_count = 0;
+
+    loop {
+        let count = match data.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(count) => count,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error),
+        };
+        newline_count += buffer[..count]
+            .iter()
+            .filter(|byte| **byte == delimiter)
+            .count() as u64;
+    }
+
+    Ok(newline_count)
+}
+
+/// The bytes between `start` and `stop`, in the order they appear in the data, whichever the direction
+pub fn read_bytes<Readable: Read + Seek>(
+    mut data: Readable,
+    mut start: Position,
+    mut stop: Position,
+    direction: ReadingDirection,
+) -> std::result::Result<Vec<u8>, FileError> {
+    // Unlike lines, bytes are always returned in the order they appear in the file
+    if direction == ReadingDirection::BottomToTop {
+        (start, stop) = (stop, start);
+    }
+
+    let length = data
+        .seek(SeekFrom::End(0))
+        .map_err(|error| FileError::ReadBytes {
+            offset: 0,
+            source: error,
+        })?;
+
+    let to_offset = |position| match position {
+        Position::FromBegin(count) => count.min(length),
+        Position::FromEnd(count) => length.saturating_sub(count),
+    };
+    let (start, stop) = (to_offset(start), to_offset(stop));
+
+    let mut bytes = Vec::with_capacity(stop.saturating_sub(start) as usize);
+    data.seek(SeekFrom::Start(start))
+        .and_then(|_| {
+            data.take(stop.saturating_sub(start))
+                .read_to_end(&mut bytes)
+        })
+        .map_err(|error| FileError::ReadBytes {
+            offset: start,
+            source: error,
+        })?;
+
+    Ok(bytes)
+}
+
+/// Which lines are kept, going by what they say and when they have been written
+#[derive(Debug, Clone)]
+pub struct LineFilter {
+    pub regex: Option<Regex>, // Every line matches without one, so that only the time is filtered by
+    pub invert: bool,
+    pub context: ContextLines,
+    pub since: Option<TimeFilter>,
+}
+
+/// Keeps the lines stamped at or after `since`, going by the timestamp at the start of every line. Lines without one, like those
+/// of a stack trace, belong to the last line that had one
+#[derive(Debug, Clone)]
+pub struct TimeFilter {
+    pub since: NaiveDateTime,
+    pub format: String, // strftime-style, e.g. "%Y-%m-%d %H:%M:%S"
+}
+
+/// How many lines around a match are kept along with it, like grep's `-B` and `-A`
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct ContextLines {
+    pub before: usize,
+    pub after: usize,
+}
+
+/// Where the context of the lines filtered so far leaves off, so that it carries on into the next lines of the file
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ContextState {
+    before: VecDeque<Line>, // The latest lines that haven't been kept, in case a match comes up
+    after: usize,           // How many more lines are kept after the last match
+    time: Option<NaiveDateTime>, // The timestamp of the last line that had one
+}
+
+impl LineFilter {
+    /// Whether the line matches the regex, or doesn't with `invert`. The time isn't checked
+    pub fn matches(&self, line: &str) -> bool {
+        self.regex
+            .as_ref()
+            .is_none_or(|regex| regex.is_match(strip_line_ending(line)) != self.invert)
+    }
+
+    /// Line numbers are kept, so they still tell where in the file the lines are
+    pub fn apply(&self, lines: Vec<Line>) -> Vec<Line> {
+        self.select(lines, &mut ContextState::default())
+    }
+
+    /// Same as apply, for lines that go on where the last ones left off. The lines have to be in the order they appear in the file
+    pub fn select(&self, lines: Vec<Line>, state: &mut ContextState) -> Vec<Line> {
+        let mut selected = vec![];
+        for line in lines {
+            self.select_line(line, state, &mut selected);
+        }
+        selected
+    }
+
+    fn select_line(&self, line: Line, state: &mut ContextState, selected: &mut Vec<Line>) {
+        // Lines from before that time don't even show up as context
+        if let Some(time_filter) = &self.since {
+            if !time_filter.admits(&line.1, &mut state.time) {
+                return;
+            }
+        }
+
+        if self.matches(&line.1) {
+            selected.extend(state.before.drain(..));
+            selected.push(line);
+            state.after = self.context.after;
+        } else if state.after > 0 {
+            selected.push(line);
+            state.after -= 1;
+        } else if self.context.before > 0 {
+            state.before.push_back(line);
+            if state.before.len() > self.context.before {
+                state.before.pop_front();
+            }
+        }
+    }
+}
+
+impl TimeFilter {
+    /// Formats without a year, like syslog's "%b %d %H:%M:%S", are taken to be in the year of `since`. Formats without a time
+    /// of day are taken to start at midnight
+    pub fn timestamp(&self, line: &str) -> Option<NaiveDateTime> {
+        let mut parsed = Parsed::new();
+        parse_and_remainder(&mut parsed, line, StrftimeItems::new(&self.format)).ok()?;
+        if parsed.year().is_none() && parsed.year_div_100().is_none() {
+            parsed.set_year(self.since.year().into()).ok()?;
+        }
+        let date = parsed.to_naive_date().ok()?;
+        let time = parsed.to_naive_time().unwrap_or(NaiveTime::MIN);
+        Some(date.and_time(time))
+    }
+
+    // Lines before the first timestamp can't be placed, so they are left out
+    fn admits(&self, line: &str, last_time: &mut Option<NaiveDateTime>) -> bool {
+        if let Some(time) = self.timestamp(strip_line_ending(line)) {
+            *last_time = Some(time);
+        }
+        last_time.is_some_and(|time| time >= self.since)
+    }
+}
+
+/// NUL only terminates lines with --zero-terminated, and a lone CR only with --line-ending cr, since they won't show up at the end
+/// of a line otherwise. Except for a CRLF that hasn't been written completely yet, which ends the line all the same
+pub fn strip_line_ending(line: &str) -> &str {
+    line.strip_suffix('\0')
+        .or_else(|| line.strip_suffix("\r\n"))
+        .or_else(|| line.strip_suffix('\n'))
+        .or_else(|| line.strip_suffix('\r'))
+        .unwrap_or(line)
+}
+
+/// What to follow, and how. `TailOptions::new` starts out with the same defaults as the command line
+#[derive(Debug, Clone)]
+pub struct TailOptions {
+    pub path: PathBuf,
+    /// How many of the last lines are passed along before waiting for new ones, like `-n`
+    pub line_count: u64,
+    pub line_filter: Option<LineFilter>,
+    pub line_format: LineFormat,
+    /// Look at the file every frame, instead of being told about changes by the operating system
+    pub poll: bool,
+    pub notification_delay: Duration,
+    pub frame_duration: Duration,
+    /// Following stops once this is set, e.g. from another thread
+    pub interrupted: Arc<AtomicBool>,
+    /// How fast lines are expected to be taken out of the channel of `follow_events`, like `--max-lines-per-sec`.
+    /// The channel holds a few seconds worth of them, and reading pauses while it's full
+    pub max_lines_per_sec: Option<f64>,
+}
+
+impl TailOptions {
+    /// Follows `path` by descriptor, starting with its last 10 lines
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            line_count: 10,
+            line_filter: None,
+            line_format: LineFormat::default(),
+            poll: false,
+            notification_delay: Duration::from_millis(100),
+            frame_duration: Duration::from_secs_f64(1.0 / 60.0),
+            interrupted: Arc::new(AtomicBool::new(false)),
+            max_lines_per_sec: None,
+        }
+    }
+}
+
+/// Hand the last lines of the file to `handle_line`, and then every line that is added to it, in the order they appear in the file.
+/// Following stops once `handle_line` returns false, or once `options.interrupted` is set
+pub fn follow(options: TailOptions, mut handle_line: impl FnMut(Line) -> bool) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .open(&options.path)
+        .map_err(|error| FileError::Access {
+            path: options.path.clone(),
+            source: error,
+        })?;
+
+    // Later reads start in the middle of the file, where there is no byte order mark to tell the encoding
+    let line_format = LineFormat {
+        encoding: sniff_encoding(&mut file, options.line_format.encoding)
+            .context(format!("Unable to read from {:?}", options.path))?,
+        ..options.line_format
+    };
+
+    let (start, stop) = (Position::FromEnd(options.line_count), Position::FromEnd(0));
+    let lines = match &options.line_filter {
+        Some(line_filter) => read_matching_lines(
+            &mut file,
+            start,
+            stop,
+            ReadingDirection::TopToBottom,
+            line_format,
+            Some(line_filter),
+        )?,
+        None => read_lines_seekable(
+            &mut file,
+            start,
+            stop,
+            ReadingDirection::TopToBottom,
+            line_format,
+        )?,
+    };
+    // New lines show up after the last line, which isn't necessarily among the ones that have been passed along. The lines have
+    // been read up to the end, which might have moved on since. Without any lines to pass along, nothing has been read
+    let end = if options.line_count == 0 {
+        file.seek(SeekFrom::End(0))
+    } else {
+        file.stream_position()
+    }
+    .context(format!("Unable to get read position in {:?}", options.path))?;
+    let mut cursor = cursor_at(&mut file, end, line_format)?;
+
+    for line in lines {
+        if !handle_line(line) {
+            return Ok(());
+        }
+    }
+
+    let mut file_monitor = monitor::monitor_file(
+        &options.path,
+        if options.poll {
+            monitor::WatchMode::Poll
+        } else {
+            monitor::WatchMode::File
+        },
+        options.notification_delay,
+        None,
+        None,
+    )?;
+    let mut line_numbering = LineNumbering::new(None, OverflowPolicy::Saturate);
+    let mut reread = true; // Whatever has been written before watching started isn't announced
+
+    while !options.interrupted.load(Ordering::SeqCst) {
+        if file_monitor.take_changed() || std::mem::take(&mut reread) {
+            match detect_size_change(&file, cursor.offset)
+                .context(format!("Unable to check the size of {:?}", options.path))?
+            {
+                SizeChange::Unchanged => {}
+                size_change => {
+                    if size_change == SizeChange::Truncated {
+                        cursor = ReadCursor::default();
+                    }
+
+                    let lines = read_new_lines(
+                        &mut file,
+                        &mut cursor,
+                        ReadingDirection::TopToBottom,
+                        &mut line_numbering,
+                        options.line_filter.as_ref(),
+                        line_format,
+                    )?;
+                    for line in lines {
+                        if !handle_line(line) {
+                            file_monitor.unwatch(&options.path);
+                            return Ok(());
+                        }
+                    }
+                }
+            }
+        }
+
+        thread::sleep(options.frame_duration);
+    }
+
+    file_monitor.unwatch(&options.path);
+    Ok(())
+}
+
+/// A line, along with where and when it showed up
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineEvent {
+    pub line_number: u64,
+    pub line: String,
+    pub source: PathBuf,
+    pub arrival_time: SystemTime,
+}
+
+// How many seconds worth of lines the channel holds, before reading pauses
+const EVENT_CHANNEL_SECONDS: f64 = 10.0;
+
+// Without a rate to go by, there's still a limit to how far reading may get ahead of whoever takes the lines
+const DEFAULT_EVENT_CAPACITY: usize = 1024;
+
+/// Same as `follow`, but on a thread of its own, handing the lines over through a channel instead of a callback.
+/// Following stops once the receiver has been dropped and the next line shows up, or once `options.interrupted` is set.
+/// The handle tells how following went
+pub fn follow_events(options: TailOptions) -> (Receiver<LineEvent>, JoinHandle<Result<()>>) {
+    let capacity = options
+        .max_lines_per_sec
+        .map_or(DEFAULT_EVENT_CAPACITY, |lines_per_second| {
+            (lines_per_second * EVENT_CHANNEL_SECONDS).ceil() as usize
+        })
+        .max(1); // A channel without any room would make every line wait until it's taken
+    let (sender, receiver) = crossbeam_channel::bounded(capacity);
+
+    let handle = thread::spawn(move || {
+        let source = options.path.clone();
+        follow(options, |(line_number, line)| {
+            sender
+                .send(LineEvent {
+                    line_number,
+                    line,
+                    source: source.clone(),
+                    arrival_time: SystemTime::now(),
+                })
+                .is_ok()
+        })
+    });
+
+    (receiver, handle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{io::Write, path::Path, sync::atomic::AtomicUsize};
+
+    use flate2::read::GzDecoder;
+    use monitor::FileMonitor;
+
+    #[test]
+    fn test_read_lines() -> Result<()> {
+        let file = r"In Hamburg lebten zwei Ameisen,
+        Die wollten nach Australien reisen.
+        Bei Altona auf der Chaussee
+        Da taten ihnen die Beine weh,
+        Und da verzichteten sie weise
+        Denn auf den letzten Teil der Reise.
+        
+        So will man oft und kann doch nicht
+        Und leistet dann recht gern Verzicht."
+            .to_string();
+
+        let data = file.clone();
+        let (a, b) = (0, 7);
+        let (start, stop) = (Position::FromBegin(a), Position::FromBegin(b));
+        let direction = ReadingDirection::TopToBottom;
+        let lines = read_lines(
+            data.as_bytes(),
+            start,
+            stop,
+            direction,
+            LineFormat::default(),
+        )?;
+        let expected: Vec<Line> = (a..b)
+            .map(|i| {
+                (
+                    i + 1,
+                    data.lines().nth(i as usize).unwrap().to_string() + "\n",
+                )
+            })
+            .collect();
+
+        assert_eq!(lines, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_lines_until() -> Result<()> {
+        let data = "one\ntwo\n=== END ===\nthree\n=== END ===\nfour\n";
+        let is_sentinel = |(_, line): &Line| line.contains("=== END ===");
+
+        let lines = read_lines_until(
+            data.as_bytes(),
+            Position::FromBegin(0),
+            Position::FromEnd(0),
+            ReadingDirection::TopToBottom,
+            LineFormat::default(),
+            is_sentinel,
+        )?;
+        assert_eq!(
+            lines,
+            [
+                (1, "one\n".to_string()),
+                (2, "two\n".to_string()),
+                (3, "=== END ===\n".to_string())
+            ]
+        );
+
+        // The last lines before the sentinel, and only sentinels from the start position on count
+        let lines = read_lines_until(
+            data.as_bytes(),
+            Position::FromEnd(0),
+            Position::FromEnd(2),
+            ReadingDirection::BottomToTop,
+            LineFormat::default(),
+            is_sentinel,
+        )?;
+        assert_eq!(
+            lines,
+            [(3, "=== END ===\n".to_string()), (2, "two\n".to_string())]
+        );
+        let lines = read_lines_until(
+            data.as_bytes(),
+            Position::FromBegin(3),
+            Position::FromEnd(0),
+            ReadingDirection::TopToBottom,
+            LineFormat::default(),
+            is_sentinel,
+        )?;
+        assert_eq!(
+            lines,
+            [(4, "three\n".to_string()), (5, "=== END ===\n".to_string())]
+        );
+
+        // Without a sentinel, it's the same as read_lines
+        let lines = read_lines_until(
+            data.as_bytes(),
+            Position::FromEnd(1),
+            Position::FromEnd(0),
+            ReadingDirection::TopToBottom,
+            LineFormat::default(),
+            |_| false,
+        )?;
+        assert_eq!(lines, [(6, "four\n".to_string())]);
+
+        Ok(())
+    }
+
+    // Keeps track of how much has been read, to check that seeking actually saves us some work
+    struct CountingReader<Readable> {
+        inner: Readable,
+        bytes_read: usize,
+    }
+
+    impl<Readable: Read> Read for CountingReader<Readable> {
+        fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+            let count = self.inner.read(buffer)?;
+            self.bytes_read += count;
+            Ok(count)
+        }
+    }
+
+    impl<Readable: Seek> Seek for CountingReader<Readable> {
+        fn seek(&mut self, position: SeekFrom) -> std::io::Result<u64> {
+            self.inner.seek(position)
+        }
+    }
+
+    #[test]
+    fn test_read_lines_from_line() -> Result<()> {
+        let data = (1..=8).map(|i| format!("{}\n", i)).collect::<String>();
+        let expected = |range: std::ops::RangeInclusive<u64>| {
+            range
+                .map(|i| (i, format!("{}\n", i)))
+                .collect::<Vec<Line>>()
+        };
+
+        // -n +3
+        let lines = read_lines(
+            data.as_bytes(),
+            Position::FromBegin(2),
+            Position::FromEnd(0),
+            ReadingDirection::TopToBottom,
+            LineFormat::default(),
+        )?;
+        assert_eq!(lines, expected(3..=8));
+
+        // The start position means the same with other stop positions, too
+        let lines = read_lines(
+            data.as_bytes(),
+            Position::FromBegin(2),
+            Position::FromBegin(5),
+            ReadingDirection::TopToBottom,
+            LineFormat::default(),
+        )?;
+        assert_eq!(lines, expected(3..=5));
+
+        let lines = read_lines(
+            data.as_bytes(),
+            Position::FromBegin(2),
+            Position::FromEnd(1),
+            ReadingDirection::TopToBottom,
+            LineFormat::default(),
+        )?;
+        assert_eq!(lines, expected(3..=7));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_all_but_last_lines() -> Result<()> {
+        let data = (1..=8).map(|i| format!("{}\n", i)).collect::<String>();
+        let expected = |range: std::ops::RangeInclusive<u64>| {
+            range
+                .map(|i| (i, format!("{}\n", i)))
+                .collect::<Vec<Line>>()
+        };
+
+        // --head -n -3
+        let lines = read_lines(
+            data.as_bytes(),
+            Position::FromBegin(0),
+            Position::FromEnd(3),
+            ReadingDirection::TopToBottom,
+            LineFormat::default(),
+        )?;
+        assert_eq!(lines, expected(1..=5));
+
+        // Seeking can't help here, but the result has to be the same
+        let lines = read_lines_seekable(
+            io::Cursor::new(data.as_bytes()),
+            Position::FromBegin(0),
+            Position::FromEnd(3),
+            ReadingDirection::TopToBottom,
+            LineFormat::default(),
+        )?;
+        assert_eq!(lines, expected(1..=5));
+
+        let lines = read_lines(
+            data.as_bytes(),
+            Position::FromBegin(0),
+            Position::FromEnd(0),
+            ReadingDirection::TopToBottom,
+            LineFormat::default(),
+        )?;
+        assert_eq!(lines, expected(1..=8));
+
+        let lines = read_lines(
+            data.as_bytes(),
+            Position::FromBegin(0),
+            Position::FromEnd(10),
+            ReadingDirection::TopToBottom,
+            LineFormat::default(),
+        )?;
+        assert!(lines.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_last_matching_lines() -> Result<()> {
+        // A match every 7th line, among plenty of lines that don't
+        let data = (1..=100)
+            .map(|i| match i % 7 {
+                0 => format!("ERROR {}\n", i),
+                _ => format!("ok {}\n", i),
+            })
+            .collect::<String>();
+        let line_filter = LineFilter {
+            regex: Some(Regex::new("^ERROR")?),
+            invert: false,
+            context: ContextLines::default(),
+            since: None,
+        };
+        let expected = |numbers: &[u64]| {
+            numbers
+                .iter()
+                .map(|&i| (i, format!("ERROR {}\n", i)))
+                .collect::<Vec<Line>>()
+        };
+
+        // --grep ERROR -n 5
+        let lines = read_matching_lines(
+            data.as_bytes(),
+            Position::FromEnd(0),
+            Position::FromEnd(5),
+            ReadingDirection::BottomToTop,
+            LineFormat::default(),
+            Some(&line_filter),
+        )?;
+        assert_eq!(lines, expected(&[98, 91, 84, 77, 70]));
+
+        // --grep ERROR --head -n 2
+        let lines = read_matching_lines(
+            data.as_bytes(),
+            Position::FromBegin(0),
+            Position::FromBegin(2),
+            ReadingDirection::TopToBottom,
+            LineFormat::default(),
+            Some(&line_filter),
+        )?;
+        assert_eq!(lines, expected(&[7, 14]));
+
+        Ok(())
+    }
+
+    // Hands out its chunks one read at a time. An empty chunk fails the read instead
+    struct GlitchyReader {
+        chunks: VecDeque<&'static [u8]>,
+    }
+
+    impl Read for GlitchyReader {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            match self.chunks.pop_front() {
+                Some([]) => Err(io::Error::other("glitch")),
+                Some(chunk) => {
+                    let count = chunk.len().min(buffer.len());
+                    buffer[..count].copy_from_slice(&chunk[..count]);
+                    if count < chunk.len() {
+                        self.chunks.push_front(&chunk[count..]);
+                    }
+                    Ok(count)
+                }
+                None => Ok(0),
+            }
+        }
+    }
+
+    #[test]
+    fn test_strip_ansi() -> Result<()> {
+        let data = "\x1b[32mINFO\x1b[0m started\n\x1b[1;31mERROR\x1b[0m: disk full\n\x1b]0;title\x07plain \x1b(Btext\n\x1b[38;5;208mWARN\x1b[m low\n";
+        let stripping = LineFormat {
+            strip_ansi: true,
+            ..Default::default()
+        };
+
+        let lines = read_lines(
+            data.as_bytes(),
+            Position::FromBegin(0),
+            Position::FromEnd(0),
+            ReadingDirection::TopToBottom,
+            stripping,
+        )?;
+        assert_eq!(
+            lines,
+            to_lines(&[
+                (1, "INFO started\n"),
+                (2, "ERROR: disk full\n"),
+                (3, "plain text\n"),
+                (4, "WARN low\n")
+            ])
+        );
+
+        // Patterns match the plain text, and the numbers still count every line
+        let line_filter = LineFilter {
+            regex: Some(Regex::new("^(ERROR|WARN)")?),
+            invert: false,
+            context: ContextLines::default(),
+            since: None,
+        };
+        let lines = read_matching_lines(
+            data.as_bytes(),
+            Position::FromBegin(0),
+            Position::FromEnd(0),
+            ReadingDirection::TopToBottom,
+            stripping,
+            Some(&line_filter),
+        )?;
+        assert_eq!(
+            lines,
+            to_lines(&[(2, "ERROR: disk full\n"), (4, "WARN low\n")])
+        );
+
+        // Unless asked for, escape sequences are kept
+        let lines = read_lines(
+            data.as_bytes(),
+            Position::FromEnd(1),
+            Position::FromEnd(0),
+            ReadingDirection::TopToBottom,
+            LineFormat::default(),
+        )?;
+        assert_eq!(lines, to_lines(&[(4, "\x1b[38;5;208mWARN\x1b[m low\n")]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_skip_errors() -> Result<()> {
+        // Reading the third line fails once
+        let reader = || GlitchyReader {
+            chunks: VecDeque::from([&b"one\ntwo\n"[..], b"", b"four\nfive\n"]),
+        };
+        let skipping = LineFormat {
+            skip_errors: true,
+            ..Default::default()
+        };
+
+        match read_lines(
+            reader(),
+            Position::FromBegin(0),
+            Position::FromEnd(0),
+            ReadingDirection::TopToBottom,
+            LineFormat::default(),
+        ) {
+            Err(FileError::Read {
+                valid_reads,
+                error_line,
+                ..
+            }) => {
+                assert_eq!(valid_reads, to_lines(&[(1, "one\n"), (2, "two\n")]));
+                assert_eq!(error_line, 3);
+            }
+            result => panic!("The read should have failed, not {:?}", result),
+        }
+
+        // The lines around the failed one still come through, and keep their numbers
+        let lines = read_lines(
+            reader(),
+            Position::FromBegin(0),
+            Position::FromEnd(0),
+            ReadingDirection::TopToBottom,
+            skipping,
+        )?;
+        assert_eq!(
+            lines,
+            to_lines(&[(1, "one\n"), (2, "two\n"), (4, "four\n"), (5, "five\n")])
+        );
+
+        // Also while following a stream
+        let mut received = vec![];
+        let count = follow_stream(reader(), skipping, &AtomicBool::new(false), |lines| {
+            received.extend(lines);
+            Ok(())
+        })?;
+        assert_eq!(count, 4);
+        assert_eq!(received, lines);
+
+        // And while following a file
+        let mut cursor = ReadCursor::default();
+        let lines = read_new_lines(
+            ReadSeekGlitch(reader()),
+            &mut cursor,
+            ReadingDirection::TopToBottom,
+            &mut LineNumbering::new(None, OverflowPolicy::Saturate),
+            None,
+            skipping,
+        )?;
+        assert_eq!(
+            lines,
+            to_lines(&[(1, "one\n"), (2, "two\n"), (4, "four\n"), (5, "five\n")])
+        );
+        assert_eq!(cursor.line_number, 5);
+
+        Ok(())
+    }
+
+    // Seeking only to the start, which is all that following a new file needs
+    struct ReadSeekGlitch(GlitchyReader);
+
+    impl Read for ReadSeekGlitch {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            self.0.read(buffer)
+        }
+    }
+
+    impl Seek for ReadSeekGlitch {
+        fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+            match position {
+                SeekFrom::Start(0) => Ok(0),
+                _ => Err(io::Error::other("not seekable")),
+            }
+        }
+    }
+
+    #[test]
+    fn test_max_buffer_bytes() -> Result<()> {
+        // 100 lines of 1000 bytes each
+        let data = (1..=100)
+            .map(|i| format!("{:0999}\n", i))
+            .collect::<String>();
+        let line_format = LineFormat {
+            max_buffer_bytes: Some(10_000),
+            ..Default::default()
+        };
+        let numbers = |lines: &[Line]| lines.iter().map(|(i, _)| *i).collect::<Vec<_>>();
+
+        // Only the last 10 of the 50 wanted lines fit
+        let lines = read_lines(
+            data.as_bytes(),
+            Position::FromEnd(0),
+            Position::FromEnd(50),
+            ReadingDirection::BottomToTop,
+            line_format,
+        )?;
+        assert_eq!(numbers(&lines), (91..=100).rev().collect::<Vec<_>>());
+
+        // The same when seeking
+        let lines = read_lines_seekable(
+            &mut io::Cursor::new(data.as_bytes()),
+            Position::FromEnd(0),
+            Position::FromEnd(50),
+            ReadingDirection::BottomToTop,
+            line_format,
+        )?;
+        assert_eq!(numbers(&lines), (91..=100).rev().collect::<Vec<_>>());
+
+        // Fewer wanted lines than fit aren't affected
+        let lines = read_lines(
+            data.as_bytes(),
+            Position::FromEnd(0),
+            Position::FromEnd(5),
+            ReadingDirection::BottomToTop,
+            line_format,
+        )?;
+        assert_eq!(numbers(&lines), [100, 99, 98, 97, 96]);
+
+        // Reading from the beginning, it stops once the buffer is full
+        let lines = read_lines(
+            data.as_bytes(),
+            Position::FromBegin(0),
+            Position::FromBegin(50),
+            ReadingDirection::TopToBottom,
+            line_format,
+        )?;
+        assert_eq!(numbers(&lines), (1..=10).collect::<Vec<_>>());
+
+        // A single line larger than the buffer is still kept
+        let line_format = LineFormat {
+            max_buffer_bytes: Some(10),
+            ..line_format
+        };
+        let lines = read_lines(
+            data.as_bytes(),
+            Position::FromEnd(0),
+            Position::FromEnd(50),
+            ReadingDirection::BottomToTop,
+            line_format,
+        )?;
+        assert_eq!(numbers(&lines), [100]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_context_lines() -> Result<()> {
+        let data = (1..=20)
+            .map(|i| match i {
+                5 | 7 | 15 => format!("ERROR {}\n", i),
+                _ => format!("ok {}\n", i),
+            })
+            .collect::<String>();
+        let line_filter = LineFilter {
+            regex: Some(Regex::new("^ERROR")?),
+            invert: false,
+            context: ContextLines {
+                before: 2,
+                after: 1,
+            },
+            since: None,
+        };
+        let numbers = |lines: &[Line]| lines.iter().map(|(i, _)| *i).collect::<Vec<_>>();
+
+        // The groups of the first two matches overlap
+        let lines = read_matching_lines(
+            data.as_bytes(),
+            Position::FromBegin(0),
+            Position::FromEnd(0),
+            ReadingDirection::TopToBottom,
+            LineFormat::default(),
+            Some(&line_filter),
+        )?;
+        assert_eq!(numbers(&lines), [3, 4, 5, 6, 7, 8, 13, 14, 15, 16]);
+
+        // Context lines count towards -n, too
+        let lines = read_matching_lines(
+            data.as_bytes(),
+            Position::FromEnd(0),
+            Position::FromEnd(5),
+            ReadingDirection::BottomToTop,
+            LineFormat::default(),
+            Some(&line_filter),
+        )?;
+        assert_eq!(numbers(&lines), [16, 15, 14, 13, 8]);
+
+        // Following, the context carries on from one batch of lines to the next
+        let lines = data
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index as u64 + 1, format!("{}\n", line)))
+            .collect::<Vec<Line>>();
+        let mut state = ContextState::default();
+        let mut selected = vec![];
+        for batch in lines.chunks(3) {
+            selected.extend(line_filter.select(batch.to_vec(), &mut state));
+        }
+        assert_eq!(numbers(&selected), [3, 4, 5, 6, 7, 8, 13, 14, 15, 16]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lines_since() -> Result<()> {
+        let data = "\
+starting up
+2024-01-02 02:59:58 INFO ready
+2024-01-02 02:59:59 ERROR failed
+    at parse (main.rs:10)
+2024-01-02 03:00:00 ERROR failed again
+    at parse (main.rs:10)
+    at main (main.rs:3)
+2024-01-02 03:00:01 INFO done
+";
+        let time_filter = TimeFilter {
+            since: NaiveDateTime::parse_from_str("2024-01-02 03:00:00", "%Y-%m-%d %H:%M:%S")?,
+            format: "%Y-%m-%d %H:%M:%S".to_string(),
+        };
+        let mut line_filter = LineFilter {
+            regex: None,
+            invert: false,
+            context: ContextLines::default(),
+            since: Some(time_filter.clone()),
+        };
+        let numbers = |lines: &[Line]| lines.iter().map(|(i, _)| *i).collect::<Vec<_>>();
+        let read = |line_filter: &LineFilter| {
+            read_matching_lines(
+                data.as_bytes(),
+                Position::FromBegin(0),
+                Position::FromEnd(0),
+                ReadingDirection::TopToBottom,
+                LineFormat::default(),
+                Some(line_filter),
+            )
+        };
+
+        // The stack traces go along with the lines they belong to, and the line before any timestamp is left out
+        assert_eq!(numbers(&read(&line_filter)?), [5, 6, 7, 8]);
+
+        // With --grep, only matches from that time on are left
+        line_filter.regex = Some(Regex::new("ERROR")?);
+        assert_eq!(numbers(&read(&line_filter)?), [5]);
+
+        // Following, the last timestamp carries on to the next batch of lines
+        line_filter.regex = None;
+        let lines = data
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index as u64 + 1, format!("{}\n", line)))
+            .collect::<Vec<Line>>();
+        let mut state = ContextState::default();
+        let mut selected = vec![];
+        for batch in lines.chunks(5) {
+            selected.extend(line_filter.select(batch.to_vec(), &mut state));
+        }
+        assert_eq!(numbers(&selected), [5, 6, 7, 8]);
+
+        // Without a year, the year of --since is assumed
+        let syslog = TimeFilter {
+            format: "%b %d %H:%M:%S".to_string(),
+            ..time_filter
+        };
+        assert_eq!(
+            syslog.timestamp("Jan 02 03:00:00 host app: ready"),
+            Some(time_filter.since)
+        );
+        assert_eq!(syslog.timestamp("    at main (main.rs:3)"), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_invalid_utf8() -> Result<()> {
+        let data: &[u8] = b"fine\nbroken \xff\xfe byte\nfine again\n";
+        let lines = read_lines(
+            data,
+            Position::FromBegin(0),
+            Position::FromEnd(0),
+            ReadingDirection::TopToBottom,
+            LineFormat::default(),
+        )?;
+        assert_eq!(
+            lines,
+            to_lines(&[
+                (1, "fine\n"),
+                (2, "broken \u{FFFD}\u{FFFD} byte\n"),
+                (3, "fine again\n")
+            ])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_other_encodings() -> Result<()> {
+        let expected = to_lines(&[(2, "Grüße\r\n"), (3, "naïve café")]);
+
+        let mut utf_16: Vec<u8> = vec![0xFF, 0xFE]; // Byte order mark
+        for unit in "first\r\nGrüße\r\nnaïve café".encode_utf16() {
+            utf_16.extend_from_slice(&unit.to_le_bytes());
+        }
+        // Even without being told, the byte order mark gives the encoding away
+        for encoding in [encoding_rs::UTF_16LE, encoding_rs::UTF_8] {
+            let line_format = LineFormat {
+                encoding,
+                ..LineFormat::default()
+            };
+            let lines = read_lines_seekable(
+                std::io::Cursor::new(&utf_16),
+                Position::FromEnd(2),
+                Position::FromEnd(0),
+                ReadingDirection::TopToBottom,
+                line_format,
+            )?;
+            assert_eq!(lines, expected);
+        }
+
+        let windows_1252: &[u8] = b"first\r\nGr\xFC\xDFe\r\nna\xEFve caf\xE9";
+        let line_format = LineFormat {
+            encoding: encoding_rs::WINDOWS_1252,
+            ..LineFormat::default()
+        };
+        let lines = read_lines_seekable(
+            std::io::Cursor::new(windows_1252),
+            Position::FromEnd(2),
+            Position::FromEnd(0),
+            ReadingDirection::TopToBottom,
+            line_format,
+        )?;
+        assert_eq!(lines, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_gzip() -> Result<()> {
+        let data = (1..=1000)
+            .map(|number| format!("Line {}\n", number))
+            .collect::<String>();
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(data.as_bytes())?;
+        let compressed = encoder.finish()?;
+
+        let lines = read_lines(
+            GzDecoder::new(compressed.as_slice()),
+            Position::FromEnd(0),
+            Position::FromEnd(3),
+            ReadingDirection::BottomToTop,
+            LineFormat::default(),
+        )?;
+        assert_eq!(
+            lines,
+            to_lines(&[
+                (1000, "Line 1000\n"),
+                (999, "Line 999\n"),
+                (998, "Line 998\n")
+            ])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_detect_compression() -> Result<()> {
+        let data = "First\nSecond\nThird\n";
+        let mut gzip = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        gzip.write_all(data.as_bytes())?;
+        let gzip = gzip.finish()?;
+        let zstd = zstd::encode_all(data.as_bytes(), 0)?;
+        let mut bzip2 = bzip2::write::BzEncoder::new(vec![], bzip2::Compression::default());
+        bzip2.write_all(data.as_bytes())?;
+        let bzip2 = bzip2.finish()?;
+
+        for (bytes, compression) in [
+            (gzip, Some(Compression::Gzip)),
+            (zstd, Some(Compression::Zstd)),
+            (bzip2, Some(Compression::Bzip2)),
+            (data.as_bytes().to_vec(), None),
+            (b"BZh is not a block size\n".to_vec(), None),
+        ] {
+            let mut cursor = io::Cursor::new(bytes.as_slice());
+            cursor.seek(SeekFrom::Start(2))?;
+            assert_eq!(sniff_compression(&mut cursor)?, compression);
+            assert_eq!(cursor.stream_position()?, 2);
+
+            let mut decompressed = String::new();
+            auto_decompress(bytes.as_slice())?.read_to_string(&mut decompressed)?;
+            if compression.is_some() {
+                assert_eq!(decompressed, data);
+            } else {
+                assert_eq!(decompressed.as_bytes(), bytes.as_slice());
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_zero_terminated_lines() -> Result<()> {
+        let data = "one\0two\nstill two\0\0four";
+        let expected = to_lines(&[(2, "two\nstill two\0"), (3, "\0"), (4, "four")]);
+
+        let lines = read_lines(
+            data.as_bytes(),
+            Position::FromEnd(3),
+            Position::FromEnd(0),
+            ReadingDirection::TopToBottom,
+            LineFormat {
+                delimiter: b'\0',
+                ..LineFormat::default()
+            },
+        )?;
+        assert_eq!(lines, expected);
+
+        let lines = read_lines_seekable(
+            std::io::Cursor::new(data.as_bytes()),
+            Position::FromEnd(3),
+            Position::FromEnd(0),
+            ReadingDirection::TopToBottom,
+            LineFormat {
+                delimiter: b'\0',
+                ..LineFormat::default()
+            },
+        )?;
+        assert_eq!(lines, expected);
+
+        assert_eq!(strip_line_ending("two\nstill two\0"), "two\nstill two");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_cr_terminated_lines() -> Result<()> {
+        let cr = LineFormat {
+            delimiter: b'\r',
+            ..LineFormat::default()
+        };
+
+        // Without looking for CRs, this would be a single line
+        let data = "one\rtwo\rthree\rfour";
+        let expected = to_lines(&[(3, "three\r"), (4, "four")]);
+        let lines = read_lines(
+            data.as_bytes(),
+            Position::FromEnd(2),
+            Position::FromEnd(0),
+            ReadingDirection::TopToBottom,
+            cr,
+        )?;
+        assert_eq!(lines, expected);
+        let lines = read_lines_seekable(
+            std::io::Cursor::new(data.as_bytes()),
+            Position::FromEnd(2),
+            Position::FromEnd(0),
+            ReadingDirection::TopToBottom,
+            cr,
+        )?;
+        assert_eq!(lines, expected);
+        assert_eq!(strip_line_ending("three\r"), "three");
+
+        // CRLF and LF mixed up are all counted as line ends, when going by LF
+        let data = "one\r\ntwo\nthree\r\nfour\n";
+        let lines = read_lines_seekable(
+            std::io::Cursor::new(data.as_bytes()),
+            Position::FromEnd(0),
+            Position::FromEnd(3),
+            ReadingDirection::BottomToTop,
+            LineFormat::default(),
+        )?;
+        assert_eq!(
+            lines,
+            to_lines(&[(4, "four\n"), (3, "three\r\n"), (2, "two\n")])
+        );
+
+        let sniff = |data: &str| sniff_delimiter(&mut std::io::Cursor::new(data.as_bytes()));
+        assert_eq!(sniff("one\rtwo\r")?, b'\r');
+        assert_eq!(sniff("one\r\ntwo\r\n")?, b'\n');
+        assert_eq!(sniff("one\ntwo")?, b'\n');
+        assert_eq!(sniff("")?, b'\n');
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_line_length() -> Result<()> {
+        let line_format = LineFormat {
+            max_line_length: Some(80),
+            ..LineFormat::default()
+        };
+
+        // A single line of a megabyte, with short lines around it
+        let data = format!("before\n{}\nafter\n", "x".repeat(1024 * 1024));
+        let truncated = format!("{}…\n", "x".repeat(80));
+        let lines = read_lines(
+            data.as_bytes(),
+            Position::FromBegin(0),
+            Position::FromEnd(0),
+            ReadingDirection::TopToBottom,
+            line_format,
+        )?;
+        assert_eq!(
+            lines,
+            to_lines(&[(1, "before\n"), (2, &truncated), (3, "after\n")])
+        );
+
+        // Lines of exactly the maximum length are left alone, and multibyte characters are counted as one
+        let data = format!("{}\n{}", "ä".repeat(80), "ä".repeat(81));
+        let lines = read_lines(
+            data.as_bytes(),
+            Position::FromEnd(2),
+            Position::FromEnd(0),
+            ReadingDirection::TopToBottom,
+            line_format,
+        )?;
+        let expected = [
+            format!("{}\n", "ä".repeat(80)),
+            format!("{}…", "ä".repeat(80)),
+        ];
+        assert_eq!(lines, to_lines(&[(1, &expected[0]), (2, &expected[1])]));
+
+        // Following skips over the whole line, too
+        let mut data = std::io::Cursor::new(format!("{}\nafter\n", "x".repeat(1024 * 1024)));
+        let mut cursor = ReadCursor::default();
+        let mut line_numbering = LineNumbering::new(None, OverflowPolicy::Saturate);
+        let lines = read_new_lines(
+            &mut data,
+            &mut cursor,
+            ReadingDirection::TopToBottom,
+            &mut line_numbering,
+            None,
+            line_format,
+        )?;
+        assert_eq!(lines, to_lines(&[(1, &truncated), (2, "after\n")]));
+        assert_eq!(cursor.offset, 1024 * 1024 + 7);
+        assert_eq!(cursor.line_number, 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_lines_seekable() -> Result<()> {
+        let file = std::fs::read_to_string("Ameisen.txt")?;
+        let without_last_newline = file.trim_end().to_string();
+        let with_last_newline = without_last_newline.clone() + "\n";
+
+        for data in [without_last_newline, with_last_newline].iter() {
+            for &(start, stop, direction) in [
+                (0, 3, ReadingDirection::BottomToTop),
+                (2, 5, ReadingDirection::BottomToTop),
+                (0, 100, ReadingDirection::BottomToTop),
+                (4, 1, ReadingDirection::TopToBottom),
+            ]
+            .iter()
+            {
+                let (start, stop) = (Position::FromEnd(start), Position::FromEnd(stop));
+                let expected = read_lines(
+                    data.as_bytes(),
+                    start,
+                    stop,
+                    direction,
+                    LineFormat::default(),
+                )?;
+                let lines = read_lines_seekable(
+                    std::io::Cursor::new(data.as_bytes()),
+                    start,
+                    stop,
+                    direction,
+                    LineFormat::default(),
+                )?;
+                assert_eq!(lines, expected);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_seeking_reads_less() -> Result<()> {
+        let data = (1..=100_000)
+            .map(|i| format!("This is line number {}\n", i))
+            .collect::<String>();
+        let (start, stop, direction) = (
+            Position::FromEnd(0),
+            Position::FromEnd(10),
+            ReadingDirection::BottomToTop,
+        );
+
+        let mut linear = CountingReader {
+            inner: std::io::Cursor::new(data.as_bytes()),
+            bytes_read: 0,
+        };
+        let expected = read_lines(&mut linear, start, stop, direction, LineFormat::default())?;
+
+        let mut seeking = CountingReader {
+            inner: std::io::Cursor::new(data.as_bytes()),
+            bytes_read: 0,
+        };
+        let offset = find_offset_of_last_lines(&mut seeking, 10, b'\n')?;
+        seeking.seek(SeekFrom::Start(offset))?;
+        let lines = read_lines(&mut seeking, start, stop, direction, LineFormat::default())?;
+
+        assert_eq!(linear.bytes_read, data.len());
+        assert!(seeking.bytes_read * 100 < linear.bytes_read);
+        assert_eq!(
+            lines.iter().map(|(_, line)| line).collect::<Vec<_>>(),
+            expected.iter().map(|(_, line)| line).collect::<Vec<_>>()
+        );
+
+        let lines = read_lines_seekable(
+            std::io::Cursor::new(data.as_bytes()),
+            start,
+            stop,
+            direction,
+            LineFormat::default(),
+        )?;
+        assert_eq!(lines, expected);
+        assert_eq!(lines.first().unwrap().0, 100_000);
+
+        Ok(())
+    }
+
+    fn to_lines(lines: &[(u64, &str)]) -> Vec<Line> {
+        lines
+            .iter()
+            .map(|(number, line)| (*number, line.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_follow_top_to_bottom() -> Result<()> {
+        let mut writer = tempfile::NamedTempFile::new()?;
+        write!(writer, "one\ntwo\nthree\nfour\nfive")?;
+        writer.flush()?;
+
+        let mut file = OpenOptions::new().read(true).open(writer.path())?;
+        let mut line_numbering = LineNumbering::new(None, OverflowPolicy::Saturate);
+        let direction = ReadingDirection::TopToBottom;
+
+        // Initial read of the first lines, like "--head -n 2 -f" does it
+        let lines = read_lines_seekable(
+            &mut file,
+            Position::FromBegin(0),
+            Position::FromBegin(2),
+            direction,
+            LineFormat::default(),
+        )?;
+        assert_eq!(lines, to_lines(&[(1, "one\n"), (2, "two\n")]));
+
+        let last_read_line = read_lines_seekable(
+            &mut file,
+            Position::FromEnd(0),
+            Position::FromEnd(1),
+            ReadingDirection::BottomToTop,
+            LineFormat::default(),
+        )?
+        .first()
+        .cloned();
+        assert_eq!(last_read_line, Some((5, "five".to_string())));
+        let mut cursor = ReadCursor::after(last_read_line.as_ref(), file.stream_position()?, b'\n');
+        assert_eq!(
+            cursor,
+            ReadCursor {
+                offset: 23,
+                line_number: 5,
+                unfinished_line: Some("five".to_string()),
+                ..Default::default()
+            }
+        );
+
+        // First refresh: The unfinished last line is completed, and a new one is added.
+        // Where the file has been left doesn't matter, only the cursor does
+        write!(writer, "!\nsix\n")?;
+        writer.flush()?;
+        file.seek(SeekFrom::Start(0))?;
+        let lines = read_new_lines(
+            &mut file,
+            &mut cursor,
+            direction,
+            &mut line_numbering,
+            None,
+            LineFormat::default(),
+        )?;
+        assert_eq!(lines, to_lines(&[(6, "six\n")]));
+
+        // Second refresh
+        write!(writer, "seven\neight\n")?;
+        writer.flush()?;
+        let lines = read_new_lines(
+            &mut file,
+            &mut cursor,
+            direction,
+            &mut line_numbering,
+            None,
+            LineFormat::default(),
+        )?;
+        assert_eq!(lines, to_lines(&[(7, "seven\n"), (8, "eight\n")]));
+
+        // Nothing new
+        let lines = read_new_lines(
+            &mut file,
+            &mut cursor,
+            direction,
+            &mut line_numbering,
+            None,
+            LineFormat::default(),
+        )?;
+        assert!(lines.is_empty());
+        assert_eq!(
+            cursor,
+            ReadCursor {
+                offset: 41,
+                line_number: 8,
+                unfinished_line: None,
+                ..Default::default()
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_follow_with_filter() -> Result<()> {
+        let mut writer = tempfile::NamedTempFile::new()?;
+        write!(writer, "ok\nERROR one\nok\nERR")?;
+        writer.flush()?;
+
+        let mut file = OpenOptions::new().read(true).open(writer.path())?;
+        let mut line_numbering = LineNumbering::new(None, OverflowPolicy::Saturate);
+        let direction = ReadingDirection::BottomToTop;
+        let line_filter = LineFilter {
+            regex: Some(Regex::new("^ERROR")?),
+            invert: false,
+            context: ContextLines::default(),
+            since: None,
+        };
+
+        let lines = read_lines_seekable(
+            &mut file,
+            Position::FromEnd(0),
+            Position::FromEnd(10),
+            direction,
+            LineFormat::default(),
+        )?;
+        let mut cursor = ReadCursor::after(lines.first(), file.stream_position()?, b'\n');
+        assert_eq!(line_filter.apply(lines), to_lines(&[(2, "ERROR one\n")]));
+
+        // The unfinished line only matches once it's complete
+        write!(writer, "OR two\nok\nERROR three\n")?;
+        writer.flush()?;
+        let lines = read_new_lines(
+            &mut file,
+            &mut cursor,
+            direction,
+            &mut line_numbering,
+            Some(&line_filter),
+            LineFormat::default(),
+        )?;
+        assert_eq!(lines, to_lines(&[(6, "ERROR three\n"), (4, "ERROR two\n")]));
+        assert_eq!((cursor.offset, cursor.line_number), (41, 6));
+
+        let inverted = LineFilter {
+            regex: Some(Regex::new("^ERROR")?),
+            invert: true,
+            context: ContextLines::default(),
+            since: None,
+        };
+        assert!(inverted.matches("ok\n"));
+        assert!(!inverted.matches("ERROR\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_follow_continues_where_reading_stopped() -> Result<()> {
+        let mut writer = tempfile::NamedTempFile::new()?;
+        write!(writer, "ok\nERROR one\nok\nunfin")?;
+        writer.flush()?;
+
+        let mut file = OpenOptions::new().read(true).open(writer.path())?;
+        let line_filter = LineFilter {
+            regex: Some(Regex::new("^ERROR")?),
+            invert: false,
+            context: ContextLines::default(),
+            since: None,
+        };
+
+        // -f -n 50 --grep ERROR reads the whole file
+        let lines = read_matching_lines(
+            &mut file,
+            Position::FromEnd(0),
+            Position::FromEnd(50),
+            ReadingDirection::BottomToTop,
+            LineFormat::default(),
+            Some(&line_filter),
+        )?;
+        assert_eq!(lines, to_lines(&[(2, "ERROR one\n")]));
+        let end = file.stream_position()?;
+
+        // More is written before following starts, which would be skipped by starting at the new end
+        write!(writer, "ished\nERROR two\n")?;
+        writer.flush()?;
+        let mut cursor = cursor_at(&mut file, end, LineFormat::default())?;
+        assert_eq!(cursor.offset, end);
+        assert_eq!(cursor.line_number, 4);
+        assert_eq!(cursor.unfinished_line.as_deref(), Some("unfin"));
+
+        // Following picks up exactly the lines written in between. The rest of the unfinished line belongs to a line that has
+        // been read already
+        let lines = read_new_lines(
+            &mut file,
+            &mut cursor,
+            ReadingDirection::TopToBottom,
+            &mut LineNumbering::new(None, OverflowPolicy::Saturate),
+            None,
+            LineFormat::default(),
+        )?;
+        assert_eq!(lines, to_lines(&[(5, "ERROR two\n")]));
+        assert_eq!(cursor.line_number, 5);
+
+        // Nothing read yet, nothing to continue after
+        assert_eq!(
+            cursor_at(&mut file, 0, LineFormat::default())?,
+            ReadCursor::default()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_follow_bottom_to_top() -> Result<()> {
+        let mut writer = tempfile::NamedTempFile::new()?;
+        write!(writer, "one\ntwo\nthree")?;
+        writer.flush()?;
+
+        let mut file = OpenOptions::new().read(true).open(writer.path())?;
+        let mut line_numbering = LineNumbering::new(None, OverflowPolicy::Saturate);
+        let direction = ReadingDirection::BottomToTop;
+
+        let lines = read_lines_seekable(
+            &mut file,
+            Position::FromEnd(0),
+            Position::FromEnd(2),
+            direction,
+            LineFormat::default(),
+        )?;
+        assert_eq!(lines, to_lines(&[(3, "three"), (2, "two\n")]));
+        let mut cursor = ReadCursor::after(lines.first(), file.stream_position()?, b'\n');
+
+        write!(writer, "!\nfour\nfive\n")?;
+        writer.flush()?;
+        let lines = read_new_lines(
+            &mut file,
+            &mut cursor,
+            direction,
+            &mut line_numbering,
+            None,
+            LineFormat::default(),
+        )?;
+        assert_eq!(lines, to_lines(&[(5, "five\n"), (4, "four\n")]));
+        assert_eq!(
+            cursor,
+            ReadCursor {
+                offset: 25,
+                line_number: 5,
+                unfinished_line: None,
+                ..Default::default()
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_follow_truncated_file() -> Result<()> {
+        let writer = tempfile::NamedTempFile::new()?;
+        let numbered_lines = |range: std::ops::RangeInclusive<usize>| {
+            range.map(|i| format!("line {}\n", i)).collect::<String>()
+        };
+        std::fs::write(writer.path(), numbered_lines(1..=20))?;
+
+        let mut file = OpenOptions::new().read(true).open(writer.path())?;
+        let mut line_numbering = LineNumbering::new(None, OverflowPolicy::Saturate);
+        let direction = ReadingDirection::BottomToTop;
+
+        let lines = read_lines_seekable(
+            &mut file,
+            Position::FromEnd(0),
+            Position::FromEnd(1),
+            direction,
+            LineFormat::default(),
+        )?;
+        let mut cursor = ReadCursor::after(lines.first(), file.stream_position()?, b'\n');
+        assert_eq!(cursor.line_number, 20);
+
+        // Nothing has happened yet
+        assert_eq!(
+            detect_size_change(&file, cursor.offset)?,
+            SizeChange::Unchanged
+        );
+
+        // Cut the file down to 5 lines, then append 3 new ones
+        std::fs::write(writer.path(), numbered_lines(1..=5))?;
+        OpenOptions::new()
+            .append(true)
+            .open(writer.path())?
+            .write_all(numbered_lines(21..=23).as_bytes())?;
+
+        assert_eq!(
+            detect_size_change(&file, cursor.offset)?,
+            SizeChange::Truncated
+        );
+        cursor = ReadCursor::default();
+
+        let lines = read_new_lines(
+            &mut file,
+            &mut cursor,
+            direction,
+            &mut line_numbering,
+            None,
+            LineFormat::default(),
+        )?;
+        let mut expected = (1..=5)
+            .chain(21..=23)
+            .enumerate()
+            .map(|(index, i)| (index as u64 + 1, format!("line {}\n", i)))
+            .collect::<Vec<Line>>();
+        expected.reverse();
+        assert_eq!(lines, expected);
+
+        Ok(())
+    }
+
+    // Tells about every change twice, like Hotwatch sometimes does
+    struct StutteringMonitor {
+        pending: usize,
+    }
+
+    impl FileMonitor for StutteringMonitor {
+        fn watch(&mut self, _path: &Path) -> Result<()> {
+            Ok(())
+        }
+
+        fn unwatch(&mut self, _path: &Path) {}
+
+        fn take_changed(&mut self) -> bool {
+            if self.pending == 0 {
+                return false;
+            }
+            self.pending -= 1;
+            true
+        }
+
+        fn is_replaced(&mut self) -> bool {
+            false
+        }
+
+        fn clear_replaced(&mut self) {}
+    }
+
+    #[test]
+    fn test_double_change_notification() -> Result<()> {
+        let mut writer = tempfile::NamedTempFile::new()?;
+        writeln!(writer, "first")?;
+        writer.flush()?;
+
+        let mut file = OpenOptions::new().read(true).open(writer.path())?;
+        let mut cursor = ReadCursor {
+            offset: 6,
+            line_number: 1,
+            unfinished_line: None,
+            ..Default::default()
+        };
+        let mut line_numbering = LineNumbering::new(None, OverflowPolicy::Saturate);
+        let mut file_monitor = StutteringMonitor { pending: 0 };
+
+        // One append, two notifications
+        writeln!(writer, "second")?;
+        writer.flush()?;
+        file_monitor.pending = 2;
+
+        let mut emitted = vec![];
+        while file_monitor.take_changed() {
+            if detect_size_change(&file, cursor.offset)? == SizeChange::Unchanged {
+                continue;
+            }
+            emitted.extend(read_new_lines(
+                &mut file,
+                &mut cursor,
+                ReadingDirection::TopToBottom,
+                &mut line_numbering,
+                None,
+                LineFormat::default(),
+            )?);
+        }
+        assert_eq!(emitted, to_lines(&[(2, "second\n")]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_follow_stream() -> Result<()> {
+        let input: &[u8] = b"first\nsecond\nthird";
+        let interrupted = AtomicBool::new(false);
+
+        let mut received = vec![];
+        let count = follow_stream(input, LineFormat::default(), &interrupted, |lines| {
+            received.extend(lines);
+            Ok(())
+        })?;
+        assert_eq!(count, 3);
+        assert_eq!(
+            received,
+            to_lines(&[(1, "first\n"), (2, "second\n"), (3, "third")])
+        );
+
+        let mut received = vec![];
+        let count = follow_byte_stream(input, &interrupted, |bytes| {
+            received.extend_from_slice(bytes);
+            Ok(())
+        })?;
+        assert_eq!(count, input.len() as u64);
+        assert_eq!(received, input);
+
+        // Nothing is read once interrupted
+        interrupted.store(true, Ordering::SeqCst);
+        let count = follow_stream(input, LineFormat::default(), &interrupted, |_| {
+            panic!("Should not be reached")
+        })?;
+        assert_eq!(count, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_follow_events() -> Result<()> {
+        let mut writer = tempfile::NamedTempFile::new()?;
+        write!(writer, "one\ntwo\n")?;
+        writer.flush()?;
+
+        let mut options = TailOptions::new(writer.path());
+        options.line_count = 1;
+        options.poll = true;
+        options.max_lines_per_sec = Some(0.2);
+        let interrupted = Arc::clone(&options.interrupted);
+
+        let (receiver, handle) = follow_events(options);
+        assert_eq!(receiver.capacity(), Some(2));
+
+        let timeout = Duration::from_secs(5);
+        let event = receiver.recv_timeout(timeout)?;
+        assert_eq!(
+            (
+                event.line_number,
+                event.line.as_str(),
+                event.source.as_path()
+            ),
+            (2, "two\n", writer.path())
+        );
+
+        write!(writer, "three\nfour\nfive\n")?;
+        writer.flush()?;
+        let received = (0..3)
+            .map(|_| {
+                receiver
+                    .recv_timeout(timeout)
+                    .map(|event| event.line_number)
+            })
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        assert_eq!(received, [3, 4, 5]);
+
+        interrupted.store(true, Ordering::SeqCst);
+        handle.join().unwrap()?; // Unwrap is okay, since following doesn't panic
+        assert!(receiver.try_recv().is_err());
+
+        Ok(())
+    }
+
+    // Hands out an endless supply of lines, and tells how many bytes have been taken so far
+    struct EndlessLines {
+        taken: Arc<AtomicUsize>,
+    }
+
+    impl Read for EndlessLines {
+        fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+            buffer.fill(b'\n');
+            self.taken.fetch_add(buffer.len(), Ordering::SeqCst);
+            Ok(buffer.len())
+        }
+    }
+
+    #[test]
+    fn test_stream_lines() -> Result<()> {
+        // Like --head -n 10000000, on data that would take ages to read up to the wanted lines
+        let taken = Arc::new(AtomicUsize::new(0));
+        let data = EndlessLines {
+            taken: Arc::clone(&taken),
+        };
+        let mut batches = vec![];
+        let result = stream_lines(
+            data,
+            0,
+            Some(10_000_000),
+            LineFormat::default(),
+            None,
+            |lines| {
+                batches.push((lines.len(), taken.load(Ordering::SeqCst)));
+                anyhow::ensure!(batches.len() < 3, "Seen enough");
+                Ok(())
+            },
+        );
+        assert_eq!(result.unwrap_err().to_string(), "Seen enough");
+
+        // The first lines have been handed over long before the last ones have been read
+        assert_eq!(batches.len(), 3);
+        assert!(batches
+            .iter()
+            .all(|&(count, taken)| count == STREAM_BATCH_SIZE && taken < 100 * 1024));
+
+        // The same lines as reading them all at once
+        let data = "zero\none\ntwo\nthree\nfour";
+        let mut streamed = vec![];
+        let count = stream_lines(
+            data.as_bytes(),
+            1,
+            Some(4),
+            LineFormat::default(),
+            None,
+            |lines| {
+                streamed.extend(lines);
+                Ok(())
+            },
+        )?;
+        assert_eq!(count, 3);
+        let read = read_lines(
+            data.as_bytes(),
+            Position::FromBegin(1),
+            Position::FromBegin(4),
+            ReadingDirection::TopToBottom,
+            LineFormat::default(),
+        )?;
+        assert_eq!(streamed, read);
+
+        let mut streamed = vec![];
+        stream_lines(
+            data.as_bytes(),
+            3,
+            None,
+            LineFormat::default(),
+            None,
+            |lines| {
+                streamed.extend(lines);
+                Ok(())
+            },
+        )?;
+        assert_eq!(streamed, to_lines(&[(4, "three\n"), (5, "four")]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stream_lines_reversed() -> Result<()> {
+        // Several blocks and batches, with lines reaching across blocks
+        let mut data = (1..=3000)
+            .map(|number| format!("{} {}\n", number, "x".repeat(number % 37)))
+            .collect::<String>();
+        data.push_str("\n\nno newline at the end");
+
+        for data in [
+            data.as_str(),
+            "",
+            "\n",
+            "single",
+            "one\ntwo\n",
+            "\u{feff}bom\n",
+        ] {
+            let mut expected = read_lines(
+                data.as_bytes(),
+                Position::FromBegin(0),
+                Position::FromEnd(0),
+                ReadingDirection::TopToBottom,
+                LineFormat::default(),
+            )?;
+            expected.reverse();
+
+            let mut lines = vec![];
+            let count = stream_lines_reversed(
+                io::Cursor::new(data.as_bytes()),
+                LineFormat::default(),
+                |mut batch| {
+                    assert!(batch.len() <= STREAM_BATCH_SIZE);
+                    lines.append(&mut batch);
+                    Ok(())
+                },
+            )?;
+            assert_eq!(lines, expected);
+            assert_eq!(count, expected.len() as u64);
+        }
+
+        // Other encodings are turned around as a whole
+        let data = b"\xff\xfeo\0n\0e\0\n\0t\0w\0o\0\n\0";
+        let mut lines = vec![];
+        stream_lines_reversed(io::Cursor::new(data), LineFormat::default(), |mut batch| {
+            lines.append(&mut batch);
+            Ok(())
+        })?;
+        assert_eq!(lines, [(2, "two\n".to_string()), (1, "one\n".to_string())]);
+
+        Ok(())
+    }
+
+    // A log that is written to right before its size is checked
+    struct GrowingLog(io::Cursor<Vec<u8>>);
+
+    impl Read for GrowingLog {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            self.0.read(buffer)
+        }
+    }
+
+    impl Seek for GrowingLog {
+        fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+            if let SeekFrom::End(_) = position {
+                self.0.get_mut().extend_from_slice(b"late\n");
+            }
+            self.0.seek(position)
+        }
+    }
+
+    #[test]
+    fn test_stream_lines_reversed_growing() -> Result<()> {
+        let data = GrowingLog(io::Cursor::new(b"one\ntwo\n".to_vec()));
+        let mut lines = vec![];
+        let count = stream_lines_reversed(data, LineFormat::default(), |mut batch| {
+            lines.append(&mut batch);
+            Ok(())
+        })?;
+
+        // Every line that is handed over has been counted
+        assert_eq!(count, lines.len() as u64);
+        assert_eq!(
+            lines
+                .iter()
+                .map(|(line_number, _)| *line_number)
+                .collect::<Vec<_>>(),
+            (1..=count).rev().collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_count_lines() -> Result<()> {
+        let mut data = io::Cursor::new("one\ntwo\n\nfour");
+        data.seek(SeekFrom::Start(2))?;
+        assert_eq!(count_lines(&mut data, b'\n')?, 4);
+        assert_eq!(data.stream_position()?, 2);
+
+        // Counted while the lines are read for something else
+        let mut counter = LineCounter::new("one\ntwo\n".as_bytes(), b'\n');
+        let lines = read_lines(
+            &mut counter,
+            Position::FromEnd(0),
+            Position::FromEnd(1),
+            ReadingDirection::BottomToTop,
+            LineFormat::default(),
+        )?;
+        assert_eq!(lines, to_lines(&[(2, "two\n")]));
+        assert_eq!(counter.count(), 2);
+
+        assert_eq!(count_lines(&mut io::Cursor::new(""), b'\n')?, 0);
+        assert_eq!(count_lines(&mut io::Cursor::new("a\0b\0"), b'\0')?, 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_follow_without_last_lines() -> Result<()> {
+        let mut writer = tempfile::NamedTempFile::new()?;
+        write!(writer, "one\ntwo\nthree\n")?;
+        writer.flush()?;
+
+        // Like -n 0 -f
+        let mut options = TailOptions::new(writer.path());
+        options.line_count = 0;
+        options.poll = true;
+        let interrupted = Arc::clone(&options.interrupted);
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let handle = std::thread::spawn(move || follow(options, |line| sender.send(line).is_ok()));
+
+        // Nothing is passed along until the file grows, and then only the new line, numbered like in the file
+        assert!(receiver.recv_timeout(Duration::from_millis(500)).is_err());
+        writeln!(writer, "four")?;
+        writer.flush()?;
+        assert_eq!(
+            receiver.recv_timeout(Duration::from_secs(5))?,
+            (4, "four\n".to_string())
+        );
+
+        interrupted.store(true, Ordering::SeqCst);
+        handle.join().unwrap()?; // Unwrap is okay, since following doesn't panic
+        assert!(receiver.try_recv().is_err());
+
+        Ok(())
+    }
+
+    // Pretends to be a file of nothing but empty lines, without having to keep all of them around
+    struct EmptyLines {
+        length: u64,
+        position: u64,
+    }
+
+    impl Read for EmptyLines {
+        fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+            let count = (self.length.saturating_sub(self.position) as usize).min(buffer.len());
+            buffer[..count].fill(b'\n');
+            self.position += count as u64;
+            Ok(count)
+        }
+    }
+
+    impl Seek for EmptyLines {
+        fn seek(&mut self, position: SeekFrom) -> std::io::Result<u64> {
+            self.position = match position {
+                SeekFrom::Start(offset) => offset,
+                SeekFrom::End(offset) => (self.length as i64 + offset) as u64,
+                SeekFrom::Current(offset) => (self.position as i64 + offset) as u64,
+            };
+            Ok(self.position)
+        }
+    }
+
+    #[test]
+    fn test_read_beyond_u32() -> Result<()> {
+        // More lines than a 32-bit counter could number
+        let length = u32::MAX as u64 + 3;
+        let mut data = EmptyLines {
+            length,
+            position: 0,
+        };
+
+        let bytes = read_bytes(
+            &mut data,
+            Position::FromBegin(length - 1),
+            Position::FromEnd(0),
+            ReadingDirection::TopToBottom,
+        )?;
+        assert_eq!(bytes, b"\n");
+
+        let mut line_numbering = LineNumbering::new(None, OverflowPolicy::Saturate);
+        let mut cursor = ReadCursor {
+            offset: length - 1,
+            line_number: length - 1,
+            unfinished_line: None,
+            ..Default::default()
+        };
+        let lines = read_new_lines(
+            &mut data,
+            &mut cursor,
+            ReadingDirection::TopToBottom,
+            &mut line_numbering,
+            None,
+            LineFormat::default(),
+        )?;
+        assert_eq!(lines, to_lines(&[(length, "\n")]));
+        assert_eq!(cursor.offset, length);
+
+        Ok(())
+    }
+
+    // Counting the lines in front of the last ones means going through all 4 GB, which takes a while in debug builds
+    #[test]
+    #[ignore]
+    fn test_count_lines_beyond_u32() -> Result<()> {
+        let length = u32::MAX as u64 + 3;
+        let data = EmptyLines {
+            length,
+            position: 0,
+        };
+
+        let lines = read_lines_seekable(
+            data,
+            Position::FromEnd(0),
+            Position::FromEnd(2),
+            ReadingDirection::BottomToTop,
+            LineFormat::default(),
+        )?;
+        assert_eq!(lines, to_lines(&[(length, "\n"), (length - 1, "\n")]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_bytes() -> Result<()> {
+        let mut data = std::io::Cursor::new(b"0123456789\xff\xfe".to_vec());
+
+        let first = read_bytes(
+            &mut data,
+            Position::FromBegin(0),
+            Position::FromBegin(4),
+            ReadingDirection::TopToBottom,
+        )?;
+        assert_eq!(first, b"0123");
+
+        let last = read_bytes(
+            &mut data,
+            Position::FromEnd(0),
+            Position::FromEnd(4),
+            ReadingDirection::BottomToTop,
+        )?;
+        assert_eq!(last, b"89\xff\xfe");
+        assert_eq!(data.position(), 12);
+
+        let appended = read_bytes(
+            &mut data,
+            Position::FromBegin(10),
+            Position::FromEnd(0),
+            ReadingDirection::TopToBottom,
+        )?;
+        assert_eq!(appended, b"\xff\xfe");
+
+        let too_many = read_bytes(
+            &mut data,
+            Position::FromEnd(0),
+            Position::FromEnd(100),
+            ReadingDirection::BottomToTop,
+        )?;
+        assert_eq!(too_many, data.get_ref().as_slice());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_line_numbering_overflow() {
+        let mut numbering = LineNumbering::new(None, OverflowPolicy::Saturate);
+        assert_eq!(numbering.offset(3, 4), 7);
+        assert_eq!(numbering.offset(2, u64::MAX - 1), u64::MAX);
+        assert_eq!(numbering.offset(3, u64::MAX - 1), u64::MAX);
+        assert!(numbering.overflowed);
+
+        let mut numbering = LineNumbering::new(Some(2), OverflowPolicy::Saturate);
+        assert_eq!(numbering.offset(1, 98), 99);
+        assert!(!numbering.overflowed);
+        assert_eq!(numbering.offset(2, 98), 99);
+        assert!(numbering.overflowed);
+        numbering.report_overflow();
+        assert!(!numbering.overflowed);
+
+        let mut numbering = LineNumbering::new(Some(2), OverflowPolicy::Reset);
+        assert_eq!(numbering.offset(1, 98), 99);
+        assert_eq!(numbering.offset(2, 98), 1);
+        assert_eq!(numbering.offset(5, 98), 4);
+        assert_eq!(numbering.offset(1, 297), 1);
+
+        let mut numbering = LineNumbering::new(None, OverflowPolicy::Reset);
+        assert_eq!(numbering.offset(3, u64::MAX - 1), 2);
+    }
+}
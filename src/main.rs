@@ -15,47 +15,134 @@
 // https://depth-first.com/articles/2020/07/20/reading-sd-files-in-rust/
 // https://stackoverflow.com/questions/31986628/collect-items-from-an-iterator-at-a-specific-index
 
-#![feature(destructuring_assignment)]
-
 use std::{
-    collections::VecDeque,
-    fs::OpenOptions,
-    io::{BufRead, BufReader, Read},
+    cell::{Cell, RefCell},
+    collections::{HashMap, VecDeque},
+    convert::TryFrom,
+    ffi::OsString,
+    fs::{File, OpenOptions},
+    io::{self, BufWriter, IsTerminal, Write},
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
     thread,
     time::{Duration, Instant},
 };
 
 use anyhow::anyhow;
 use anyhow::{Context, Result};
-use clap::{App, Arg};
-use crossbeam_utils::atomic::AtomicCell;
-use hotwatch::{Event, Hotwatch};
+use chrono::format::{Item, StrftimeItems};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use clap::{App, Arg, ArgGroup, ErrorKind, Shell};
+use encoding_rs::Encoding;
 use path_absolutize::*;
+use regex::Regex;
+use serde::Serialize;
 use thiserror::Error;
 
-type Line = (usize, String);
+mod color;
+mod config;
+mod on_match;
+mod session;
+mod signals;
+mod tui;
+
+use color::ColorMode;
+use on_match::MatchCommand;
+use session::{Backoff, Session, TransientRetry};
+use tail::monitor::{self, EventLog, Wakeup, WatchMode};
+use tail::{
+    strip_line_ending, ContextLines, FileError, Line, LineFilter, LineFormat, LineNumbering,
+    OverflowPolicy, Position, ReadingDirection, TimeFilter,
+};
+
+const DEFAULT_TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+const DEFAULT_LIVE_MARKER: &str = "--- live ---";
+const DEFAULT_MERGE_WINDOW: Duration = Duration::from_secs(1);
+// While waiting for changes, how often the things that can't announce themselves are looked at
+const HOUSEKEEPING_INTERVAL: Duration = Duration::from_secs(1);
+// With --age, lines that are shown again are redrawn at least this often, so that their ages keep up
+pub const AGE_REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+// Exit codes besides 0, as listed in the help
+const EXIT_FAILURE: i32 = 1; // E.g. a file that can't be read, even after --retry
+const EXIT_USAGE: i32 = 2;
+const EXIT_UNTIL_NOT_FOUND: i32 = 3;
+
+const EXIT_CODES: &str = "EXIT STATUS:
+    0    Everything has been printed, or following has been stopped by Ctrl+C, --timeout or --pid
+    1    Something went wrong, e.g. a file couldn't be read, even after waiting for it with --retry
+    2    The arguments don't make sense, e.g. an unknown option or one that needs -f without it
+    3    --timeout stopped following before a line matching --until showed up
+    --timeout-exit-code replaces 0 and 3 for when --timeout stops following";
+
+// Shown by --examples. Every one of them has to get past the argument parser, which the tests make sure of
+const EXAMPLES: &[(&str, &str)] = &[
+    ("The last 50 lines of a file", "tail -n 50 app.log"),
+    ("Everything from line 100 on", "tail -n +100 app.log"),
+    ("Lines 100 to 150", "tail --range 100:150 app.log"),
+    ("The first 20 lines", "tail --head -n 20 app.log"),
+    (
+        "Follow a file, only printing errors",
+        "tail -f --grep ERROR app.log",
+    ),
+    (
+        "Follow a file by name, so that log rotation doesn't stop it, with timestamps",
+        "tail -F --timestamp app.log",
+    ),
+    (
+        "Merge several files into one stream, ordered by the timestamps of their lines",
+        "tail -f --merge app.log db.log",
+    ),
+    (
+        "Follow standard input together with a file",
+        "make 2>&1 | tail -f --also app.log",
+    ),
+    (
+        "Wait up to a minute for a line, and stop once it shows up",
+        "tail -f --until 'server started' --timeout 60 app.log",
+    ),
+    (
+        "Lay out the lines yourself",
+        "tail -f --prefix '{time} {file}: {content}' app.log",
+    ),
+    (
+        "Only print a field of JSON lines",
+        "tail --json-field request.path access.log",
+    ),
+    (
+        "Print a file from its last line to its first",
+        "tail --tac app.log",
+    ),
+    ("Scroll through a followed file", "tail -f --tui app.log"),
+];
 
+// Arguments that don't make sense together, which aren't caught while parsing them
 #[derive(Debug, Error)]
-enum FileError {
-    #[error("Unable to access file: \"{path}\"")]
-    Access {
-        path: PathBuf,
-        source: std::io::Error,
-    },
-    #[error("Unable to read line: {error_line}")]
-    Read {
-        valid_reads: Vec<Line>,
-        error_line: usize,
-        source: std::io::Error,
-    },
-    #[error(transparent)]
-    Other(#[from] anyhow::Error),
+#[error("{0}")]
+struct UsageError(String);
+
+fn main() {
+    let exit_code = match run() {
+        Ok(()) => 0,
+        // Whoever reads our output has stopped doing so. There is nobody left to tell about anything, so that's fine
+        Err(error) if is_broken_pipe(&error) => 0,
+        Err(error) => {
+            eprintln!("Error: {:?}", error);
+            if error.is::<UsageError>() {
+                EXIT_USAGE
+            } else {
+                EXIT_FAILURE
+            }
+        }
+    };
+    std::process::exit(exit_code);
 }
 
-fn main() -> Result<()> {
-    let matches = App::new("tail")
+fn app() -> App<'static, 'static> {
+    App::new("tail")
         .version("1.0")
         .author("Andy")
         .about("Prints the last lines of a file")
@@ -65,17 +152,42 @@ fn main() -> Result<()> {
                 .case_insensitive(true)
                 .takes_value(true)
                 .default_value("10")
-                .default_value_if("follow", None, "1")
-                .validator(|value| {
-                    let value = value.parse::<usize>();
-                    match value {
-                        Ok(_) => Ok(()),
-                        Err(_) => Err("n should be a positive integer".to_string()),
-                    }
+                .default_value_ifs(&[("follow", None, "1"), ("follow-name", None, "1")])
+                .allow_hyphen_values(true)
+                .validator(|value| match parse_count(value.strip_prefix(['+', '-']).unwrap_or(&value)) {
+                    Ok(_) => Ok(()),
+                    Err(error) => Err(format!("n {}", error)),
+                })
+                .value_name("NUMBER")
+                .required(false)
+                .help("The number of lines to display. May be suffixed with b (512), k (1024), m (1024^2) or g (1024^3). Use +NUMBER to display everything starting at line NUMBER, or -NUMBER with --head to display everything but the last NUMBER lines"),
+        )
+        .arg(
+            Arg::with_name("bytes")
+                .short("c")
+                .case_insensitive(true)
+                .long("bytes")
+                .case_insensitive(true)
+                .takes_value(true)
+                .conflicts_with_all(&["n", "reverse"])
+                .allow_hyphen_values(true)
+                .validator(|value| match parse_count(value.strip_prefix(['+', '-']).unwrap_or(&value)) {
+                    Ok(_) => Ok(()),
+                    Err(error) => Err(format!("The number of bytes {}", error)),
                 })
                 .value_name("NUMBER")
                 .required(false)
-                .help("The number of lines to display"),
+                .help("The number of bytes to display, instead of lines. Takes the same suffixes and signs as -n: +NUMBER displays everything starting at byte NUMBER, and -NUMBER with --head everything but the last NUMBER bytes. Bytes are passed along as they are, without decoding them"),
+        )
+        .arg(
+            Arg::with_name("range")
+                .long("range")
+                .takes_value(true)
+                .validator(|value| parse_range(&value).map(|_| ()))
+                .value_name("START:END")
+                .conflicts_with_all(&["n", "bytes", "head", "reverse"])
+                .required(false)
+                .help("Display the lines from START to END, both included, e.g. 100:150. Without START, lines are displayed from the first one, e.g. :50, and without END up to the last one, e.g. 100:"),
         )
         .arg(
             Arg::with_name("follow")
@@ -83,16 +195,90 @@ fn main() -> Result<()> {
                 .case_insensitive(true)
                 .long("follow")
                 .case_insensitive(true)
+                .takes_value(true)
+                .min_values(0)
+                .require_equals(true) // Otherwise, "-f app.log" would take the file name for the mode
+                .possible_values(&["descriptor", "name"])
+                .value_name("mode")
+                .required(false)
+                .help("Continuously monitor the file for new lines. \"descriptor\" (the default) keeps reading the opened file, even if it is renamed or deleted. \"name\" is the same as --follow-name. With --retry, following by name also waits for the file to reappear, while following by descriptor only waits for the file to show up the first time"),
+        )
+        .arg(
+            Arg::with_name("follow-name")
+                .short("F")
+                .long("follow-name")
+                .case_insensitive(true)
+                .takes_value(false)
+                .required(false)
+                .help("Like --follow, but follows the file by name. If the file is deleted or renamed, the new file with the same name is followed, once whatever has still been written to the old one has been printed"),
+        )
+        // Either way of following will do for options that only make sense while following
+        .group(
+            ArgGroup::with_name("following")
+                .args(&["follow", "follow-name"])
+                .multiple(true),
+        )
+        .arg(
+            Arg::with_name("retry")
+                .long("retry")
+                .case_insensitive(true)
+                .takes_value(false)
+                .required(false)
+                .help("Keep trying to open the file if it doesn't exist, is inaccessible or is a directory, also when it disappears while following by name"),
+        )
+        .arg(
+            Arg::with_name("follow-retry-interval")
+                .long("follow-retry-interval")
+                .takes_value(true)
+                .default_value("5s")
+                .validator(|value| match parse_duration(&value, BareNumber::Seconds) {
+                    Ok(duration) if duration.is_zero() => Err("should be more than 0".to_string()),
+                    Ok(_) => Ok(()),
+                    Err(error) => Err(error),
+                })
+                .value_name("DURATION")
+                .required(false)
+                .help("With --retry, how long to wait at most between attempts to open a missing file, in seconds or with a unit, e.g. 500ms. Attempts start out once per frame, and the time between them doubles up to this"),
+        )
+        .arg(
+            Arg::with_name("transient-retries")
+                .long("transient-retries")
+                .takes_value(true)
+                .default_value("3")
+                .validator(|value| match value.parse::<u32>() {
+                    Ok(_) => Ok(()),
+                    Err(_) => Err(format!("should be a number, not \"{}\"", value)),
+                })
+                .value_name("NUMBER")
+                .required(false)
+                .help("While following, how many times in a row to try reading a file again after an error that is likely to go away by itself, like another process holding a lock on it for a moment. The waits in between start at 50ms and double. 0 gives up right away. Files that are gone are left to --retry"),
+        )
+        .arg(
+            Arg::with_name("max-unchanged-stats")
+                .long("max-unchanged-stats")
+                .takes_value(true)
+                .validator(|value| match value.parse::<u64>() {
+                    Ok(count) if count > 0 => Ok(()),
+                    _ => Err(format!("should be a positive integer, not \"{}\"", value)),
+                })
+                .value_name("NUMBER")
+                .required(false)
+                .help("With -F, look the file up by name again after this many checks in a row without any change, like GNU tail. This finds a rotation or a write that went by without notice, which can happen on network file systems. Ignored when following by descriptor"),
+        )
+        .arg(
+            Arg::with_name("no-follow-symlinks")
+                .long("no-follow-symlinks")
                 .takes_value(false)
                 .required(false)
-                .help("Continuously monitor the file for new lines"),
+                .help("Watch symlinks themselves, instead of the files they point to. By default, a symlink is resolved, and when following by name, it is resolved again once it points to another file"),
         )
         .arg(
             Arg::with_name("file")
                 .takes_value(true)
+                .multiple(true)
                 .value_name("FILE")
-                .required(true)
-                .help("The file to monitor"),
+                .required(false)
+                .help("The files to monitor. Standard input is read if this is \"-\" or omitted. With several files, each block of lines is preceded by a header naming its file"),
         )
         .arg(
             Arg::with_name("rate")
@@ -100,19 +286,14 @@ fn main() -> Result<()> {
                 .case_insensitive(true)
                 .takes_value(true)
                 .default_value("60")
-                .validator(|value| {
-                    let value = value.parse::<f64>();
-                    if let Ok(number) = value {
-                        if number > 0.0 {
-                            return Ok(());
-                        }
-                    }
-
-                    Err("rate should be a positive number".to_string())
+                .validator(|value| match parse_duration(&value, BareNumber::Hertz) {
+                    Ok(duration) if duration.is_zero() => Err("is too fast".to_string()),
+                    Ok(_) => Ok(()),
+                    Err(error) => Err(error),
                 })
-                .value_name("NUMBER")
+                .value_name("RATE")
                 .required(false)
-                .help("Program logic refresh rate in Hz"),
+                .help("Program logic refresh rate in Hz, or the time between frames, e.g. 250ms or 0.5s. Every frame, the followed files are checked for changes, which Hotwatch tells about after --delay, or which are looked for with --poll. See also --sleep-interval"),
         )
         .arg(
             Arg::with_name("delay")
@@ -120,13 +301,10 @@ fn main() -> Result<()> {
                 .case_insensitive(true)
                 .takes_value(true)
                 .default_value("100")
-                .validator(|value| match value.parse::<u64>() {
-                    Ok(_) => Ok(()),
-                    Err(_) => Err("Delay should be a non-negative 64-bit integer.".to_string()),
-                })
-                .value_name("NUMBER")
+                .validator(|value| parse_duration(&value, BareNumber::Milliseconds).map(|_| ()))
+                .value_name("DURATION")
                 .required(false)
-                .help("Delay in milliseconds between checks for file changes"),
+                .help("Delay that Hotwatch waits for further changes before telling about them, in milliseconds or with a unit, e.g. 1.5s. Has no effect with --poll"),
         )
         .arg(
             Arg::with_name("head")
@@ -134,7 +312,6 @@ fn main() -> Result<()> {
                 .case_insensitive(true)
                 .takes_value(false)
                 .required(false)
-                .conflicts_with("follow")
                 .help("Read the first lines of the file, instead of the last lines"),
         )
         .arg(
@@ -147,442 +324,4843 @@ fn main() -> Result<()> {
                 .required(false)
                 .help("Print lines in reverse order"),
         )
-        .get_matches();
-
-    // Parsing input arguments
-    let clock = Instant::now();
-
-    let mut refresh_count = 0;
-    let refresh_rate = matches.value_of("rate").unwrap().parse::<f64>().unwrap(); // Unwraps here are okay, I guess, because this has a default value and a validator
-
-    let notification_delay = matches.value_of("delay").unwrap().parse::<u64>().unwrap(); // Unwraps here are okay, I guess, because this has a default value and a validator
-
-    let reverse_output = matches.is_present("reverse");
-
-    let n = matches.value_of("n").unwrap().parse::<usize>().unwrap(); // Unwraps are safe because argument has validator and default value
-
-    let (mut start_position, mut stop_position, reading_direction) = if matches.is_present("head") {
-        (
-            Position::FromBegin(0),
-            Position::FromBegin(n),
-            ReadingDirection::TopToBottom,
+        .arg(
+            Arg::with_name("tac")
+                .long("tac")
+                .takes_value(false)
+                .conflicts_with_all(&["n", "bytes", "range", "head", "reverse", "follow", "follow-name", "merge", "tui"])
+                .required(false)
+                .help("Print all lines, from the last one to the first one, like tac. Files are read backwards, so that they don't have to fit into memory"),
         )
-    } else {
-        (
-            Position::FromEnd(0),
-            Position::FromEnd(n),
-            ReadingDirection::BottomToTop,
+        .arg(
+            Arg::with_name("reopen-signal")
+                .long("reopen-signal")
+                .case_insensitive(true)
+                .takes_value(false)
+                .required(false)
+                .requires("following")
+                .help("Reopen the file by name when receiving SIGUSR1 while following (Unix only, ignored elsewhere)"),
         )
-    };
-
-    // Parse input argument as file path
-    let file_path = matches.value_of("file").unwrap(); // The unwrap here is safe, because the argument is required
-    let mut file_path = validate_path(file_path);
-
-    // Try to handle possible errors
-    file_path = match file_path {
-        Ok(path) => Ok(path),
-        Err(error) => {
-            match error {
-                FileError::Access {
-                    ref path,
-                    source: _,
-                } => {
-                    eprintln!("{}\n{:#?}", error, error);
-                    println!("Waiting for file to become accessible");
-
-                    while OpenOptions::new().read(true).open(path.clone()).is_err() {
-                        sleep_remaining_frame(clock, &mut refresh_count, refresh_rate);
-                    }
-
-                    Ok(path.clone())
-                }
-                FileError::Read {
-                    valid_reads: _,
-                    error_line: _,
-                    source: _,
-                } => Err(error), // Don't think this case should happen, as we are not trying to read here
-                FileError::Other(_) => Err(error),
-            }
-        }
-    };
-
-    // If error can't be handled, return
-    let file_path = file_path?;
-
-    // Read once, and then monitor if wanted
-    let mut file = OpenOptions::new()
-        .read(true)
-        .open(file_path.clone())
-        .map_err(|error| FileError::Access {
-            path: file_path.clone(),
-            source: error,
-        })?;
-
-    let lines = read_lines(&mut file, start_position, stop_position, reading_direction)?;
-    let mut last_read_line = match reading_direction {
-        ReadingDirection::TopToBottom => lines.last().cloned(),
-        ReadingDirection::BottomToTop => lines.first().cloned(),
-    };
-    print_lines(lines, reading_direction, reverse_output);
-
-    if matches.occurrences_of("follow") > 0 {
-        // Monitor continuously
-        let file_changed = Arc::new(AtomicCell::new(false));
-
-        let mut file_watcher = Hotwatch::new_with_custom_delay(Duration::from_millis(
-            notification_delay,
-        ))
-        .context(format!(
-            "Hotwatch failed to initialize. Unable to monitor {:?}!",
-            file_path
-        ))?;
-
-        {
-            let file_changed = Arc::clone(&file_changed);
-
-            file_watcher
-                .watch(&file_path, move |event| {
-                    if let Event::Write(_path) = event {
-                        file_changed.store(true);
+        .arg(
+            Arg::with_name("max-line-number-digits")
+                .long("max-line-number-digits")
+                .case_insensitive(true)
+                .takes_value(true)
+                .validator(|value| {
+                    let max_digits = u64::MAX.to_string().len() as u32;
+                    match value.parse::<u32>() {
+                        Ok(digits) if (1..=max_digits).contains(&digits) => Ok(()),
+                        _ => Err(format!(
+                            "The number of digits should be an integer between 1 and {}",
+                            max_digits
+                        )),
                     }
                 })
-                .context(format!("Failed to watch {:?}!", file_path))?;
-        }
-
-        loop {
-            // Monitor file
-            if file_changed.compare_exchange(true, false).is_ok() {
-                match reading_direction {
-                    ReadingDirection::TopToBottom => {
-                        // This case should not happen, as the input arguments leading to this case should conflict
-                        anyhow::bail!("Continuous monitoring can only be used to check for new lines inserted at the end of the file, not at the top.");
-                    }
-                    ReadingDirection::BottomToTop => {
-                        (start_position, stop_position) =
-                            (Position::FromEnd(0), Position::FromBegin(0)); // stop_position is FromBegin(0), since the curser is where we left it
-                    }
-                }
-
-                let mut lines =
-                    read_lines(&mut file, start_position, stop_position, reading_direction)?;
-
-                let mut previous_last_read_line = last_read_line.clone();
-
-                if let Some((last_line_number, last_line_content)) = &mut last_read_line {
-                    if !last_line_content.ends_with('\n') {
-                        // Previous last line did not include newline characters. These are read as their own line now
-                        match reading_direction {
-                            ReadingDirection::TopToBottom => {
-                                if let Some((_, line)) = lines.first() {
-                                    // Consider this part of the last read line
-                                    if let Some((number, mut string)) = previous_last_read_line {
-                                        string.push_str(line);
-                                        previous_last_read_line = Some((number, string));
-                                    };
-
-                                    lines.remove(0);
-
-                                    for (line_number, _) in &mut lines {
-                                        *line_number += *last_line_number - 1;
-                                        // - 1 because the new line ending on the previous last line shoult not be counted as an individual new line
-                                    }
-                                }
-                            }
-                            ReadingDirection::BottomToTop => {
-                                if let Some((_, line)) = lines.last() {
-                                    // Consider this part of the last read line
-                                    if let Some((number, mut string)) = previous_last_read_line {
-                                        string.push_str(line);
-                                        previous_last_read_line = Some((number, string));
-                                    };
-
-                                    lines.remove(lines.len() - 1);
-
-                                    for (line_number, _) in &mut lines {
-                                        *line_number += *last_line_number - 1;
-                                        // - 1 because the new line ending on the previous last line should not be counted as an individual new line
-                                    }
-                                }
-                            }
-                        }
+                .value_name("NUMBER")
+                .required(false)
+                .help("The largest number of digits a line number may grow to while following"),
+        )
+        .arg(
+            Arg::with_name("line-number-overflow")
+                .long("line-number-overflow")
+                .case_insensitive(true)
+                .takes_value(true)
+                .possible_values(&["saturate", "reset"])
+                .default_value("saturate")
+                .value_name("POLICY")
+                .required(false)
+                .help("What to do when line numbers outgrow their limit: Stay at the largest number, or start over from 1"),
+        )
+        .arg(
+            Arg::with_name("output-line-ending")
+                .long("output-line-ending")
+                .case_insensitive(true)
+                .takes_value(true)
+                .possible_values(&["lf", "crlf", "keep"])
+                .default_value("keep")
+                .value_name("ENDING")
+                .required(false)
+                .help("Line ending used for printed lines. \"keep\" uses the line endings of the file. Without this, Windows gets \"lf\", like --text"),
+        )
+        .arg(
+            Arg::with_name("timestamp")
+                .long("timestamp")
+                .case_insensitive(true)
+                .takes_value(true)
+                .min_values(0)
+                .require_equals(true)
+                .validator(|value| {
+                    if StrftimeItems::new(&value).any(|item| item == Item::Error) {
+                        Err(format!("\"{}\" is not a valid timestamp format", value))
                     } else {
-                        for (line_number, _) in &mut lines {
-                            *line_number += *last_line_number;
-                        }
-                    }
-                }
-
-                match reading_direction {
-                    ReadingDirection::TopToBottom => {
-                        if lines.last().is_some() {
-                            last_read_line = lines.last().cloned();
-                        } else {
-                            last_read_line = previous_last_read_line;
-                        }
-                    }
-                    ReadingDirection::BottomToTop => {
-                        if lines.first().is_some() {
-                            last_read_line = lines.first().cloned();
-                        } else {
-                            last_read_line = previous_last_read_line;
-                        }
+                        Ok(())
                     }
-                };
-
-                print_lines(lines, reading_direction, reverse_output);
-            }
-
-            sleep_remaining_frame(clock, &mut refresh_count, refresh_rate);
-        }
-    }
-
-    Ok(())
-}
-
-#[derive(Debug, PartialEq, Clone, Copy)]
-enum ReadingDirection {
-    TopToBottom,
-    BottomToTop,
-}
-
-#[derive(Debug, PartialEq, Clone, Copy)]
-enum Position {
-    FromBegin(usize),
-    FromEnd(usize),
-}
-
-fn read_lines<Readable: Read>(
-    data: Readable,
-    mut start: Position,
-    mut stop: Position,
-    direction: ReadingDirection,
-) -> std::result::Result<Vec<Line>, FileError> {
-    match direction {
-        ReadingDirection::TopToBottom => match (start, stop) {
-            (Position::FromBegin(a), Position::FromBegin(b)) => {
-                if a >= b {
-                    return Ok(vec![]);
-                }
-            }
-            (Position::FromBegin(_), Position::FromEnd(_)) => {}
-            (Position::FromEnd(_), Position::FromBegin(_)) => {}
-            (Position::FromEnd(a), Position::FromEnd(b)) => {
-                if a <= b {
-                    return Ok(vec![]);
-                }
-            }
-        },
-        ReadingDirection::BottomToTop => match (start, stop) {
-            (Position::FromBegin(a), Position::FromBegin(b)) => {
-                if a <= b {
-                    return Ok(vec![]);
-                } else {
-                    (start, stop) = (stop, start);
-                }
-            }
-            (Position::FromBegin(_), Position::FromEnd(_)) => (start, stop) = (stop, start),
-            (Position::FromEnd(_), Position::FromBegin(_)) => (start, stop) = (stop, start),
-            (Position::FromEnd(a), Position::FromEnd(b)) => {
-                if a >= b {
-                    return Ok(vec![]);
-                } else {
-                    (start, stop) = (stop, start);
-                }
-            }
-        },
-    }
-
-    let mut reader = BufReader::new(data);
-
-    let mut lines = VecDeque::new();
-    let mut line_count = 0;
-    let mut line_buffer = String::new();
-
-    // Keep on reading
-    loop {
-        // When to store line?
-        // -> If start is FromBegin(pos) and line_count >= pos
-        // -> If start is FromEnd (since we don't know the total line count before hand)
-        // When to stop?
-        // -> If stop is FromBegin(pos) and line_count >= pos
-        // -> If end of file has been reached
-
-        // Check for stop condition
-        if let Position::FromBegin(pos) = stop {
-            if line_count >= pos {
-                break;
-            }
-        }
-
-        line_buffer.clear();
-        let bytes_read = reader.read_line(&mut line_buffer);
-        line_count += 1;
-
-        match bytes_read {
-            Ok(count) => {
-                if count == 0 {
-                    // End of file reached
-                    break;
-                }
-            }
-            Err(error) => {
-                return Err(FileError::Read {
-                    valid_reads: match direction {
-                        ReadingDirection::TopToBottom => lines.into(),
-                        ReadingDirection::BottomToTop => {
-                            lines.into_iter().rev().collect::<Vec<Line>>()
-                        }
-                    },
-                    error_line: line_count,
-                    source: error,
                 })
-            }
-        }
-
-        // Don't store line if wanted starting position hasn't been reached
-        if let Position::FromBegin(pos) = start {
-            if line_count < pos {
-                continue;
-            }
-        }
-
-        lines.push_back((line_count, line_buffer.clone()));
-
-        // Drop lines making the container larger than wanted
-        match (start, stop) {
-            (Position::FromBegin(a), Position::FromBegin(b)) => {
-                if lines.len() > b - a {
-                    lines.pop_front();
-                }
-            }
-            (Position::FromBegin(_), Position::FromEnd(_)) => {}
-            (Position::FromEnd(a), Position::FromBegin(_)) => {
-                if lines.len() > a {
-                    lines.pop_front();
-                }
-            }
-            (Position::FromEnd(a), Position::FromEnd(_)) => {
-                if lines.len() > a {
-                    lines.pop_front();
-                }
-            }
-        }
-    }
-
-    // Remove lines towards end of file that shouldn't be included
-    if let Position::FromEnd(n) = stop {
-        lines.drain(lines.len().saturating_sub(n)..);
-    }
-
-    match direction {
-        ReadingDirection::TopToBottom => Ok(lines.into()),
-        ReadingDirection::BottomToTop => Ok(lines.into_iter().rev().collect::<Vec<Line>>()),
-    }
-}
-
-fn print_lines(mut lines: Vec<Line>, reading_direction: ReadingDirection, reverse_output: bool) {
-    if reading_direction == ReadingDirection::BottomToTop {
-        lines = lines.into_iter().rev().collect();
-    }
-
-    if reverse_output {
-        for (line_number, line) in lines.iter().rev() {
-            print!("{}:\t{}", line_number, line);
-            if !line.ends_with('\n') {
-                println!();
-            }
-        }
-    } else {
-        for (line_number, line) in lines.iter() {
-            print!("{}:\t{}", line_number, line);
-            if !line.ends_with('\n') {
-                println!();
-            }
-        }
-    }
-}
-
-fn validate_path(path_string: &str) -> std::result::Result<PathBuf, FileError> {
-    let mut path = path_string.to_string();
-    if path.trim().is_empty() {
-        return Err(FileError::Other(anyhow!("Supplied path is empty!")));
-    }
-
-    // If the path is relative, trim it and add "./" to the beginning
-    let trim_characters = ['\\', '/', '.'];
-    if Path::new(&path).is_relative() {
-        let first_character = path.chars().next().unwrap(); // At least one character is contained, as given by the check above
-        if first_character != '.' {
-            path = path
-                .trim_start_matches(|c: char| c.is_whitespace() || trim_characters.contains(&c))
-                .to_string();
-            path.insert_str(0, "./");
-        }
-    }
-
-    let path = Path::new(&path)
-        .absolutize()
-        .with_context(|| format!("Unable to turn \"{}\" into absolute path", path))?;
-
-    if path.is_dir() {
-        return Err(FileError::Other(anyhow!(
-            "The path \"{}\" points to a directory. It should point to a file",
-            path.to_str().unwrap_or("")
-        )));
-    }
-
-    let file = OpenOptions::new().read(true).open(path.clone());
-    match file {
-        Ok(_) => Ok(path.into()),
-        Err(error) => Err(FileError::Access {
-            path: path.into(),
-            source: error,
-        }),
-    }
-}
-
-fn sleep_remaining_frame(clock: Instant, count: &mut u128, rate: f64) {
-    *count += 1;
-
-    let micros_per_second = 1_000_000;
-    let expected_frame_count = (clock.elapsed().as_micros() as f64 * rate) as u128;
-    let frame_count = *count * micros_per_second;
-
-    let count_delta = (frame_count as i128) - (expected_frame_count as i128);
-
-    if count_delta > 0 {
-        let sleep_time = ((count_delta as f64) / rate) as u128;
-        thread::sleep(Duration::from_micros(sleep_time as u64));
-    }
-}
-
-mod tests {
-    use super::*;
+                .value_name("FORMAT")
+                .required(false)
+                .help("Prefix each line with the time it was read. Optionally takes a strftime-style format, e.g. --timestamp=\"%H:%M:%S\" [default format: %Y-%m-%d %H:%M:%S]"),
+        )
+        .arg(
+            Arg::with_name("age")
+                .long("age")
+                .takes_value(false)
+                .conflicts_with("bytes")
+                .required(false)
+                .help("Prefix each line with how long it took to arrive after tail started, e.g. [+42s]. With --clear or --tui, where lines are shown again and again, this is how long ago they arrived instead, e.g. [42s ago]. JSON lines get an age field in seconds instead. Needs -f or -F"),
+        )
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .takes_value(true)
+                .value_name("PATH")
+                .required(false)
+                .help("Read default options from this TOML file, instead of tail/config.toml in the config directory, e.g. ~/.config/tail/config.toml. Knows rate, delay, color, timestamp, separator, number-format, prefix, format and encoding, e.g. rate = 30 or timestamp = true. Options given here take precedence"),
+        )
+        .arg(
+            Arg::with_name("print-config")
+                .long("print-config")
+                .takes_value(false)
+                .required(false)
+                .help("Print the options as they have been understood, including defaults, to stderr, and exit without reading any files"),
+        )
+        .arg(
+            Arg::with_name("examples")
+                .long("examples")
+                .takes_value(false)
+                .required(false)
+                .help("Print examples of what tail can do, and exit"),
+        )
+        .arg(
+            Arg::with_name("generate-completions")
+                .long("generate-completions")
+                .takes_value(true)
+                .possible_values(&Shell::variants())
+                .value_name("SHELL")
+                .hidden(true)
+                .required(false)
+                .help("Print a script for tab completion in the given shell, and exit"),
+        )
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .case_insensitive(true)
+                .takes_value(true)
+                .possible_values(&["text", "json"])
+                .default_value("text")
+                .value_name("FORMAT")
+                .required(false)
+                .help("Output format. \"json\" prints one JSON object per line, with the fields line, content, file and, with --timestamp, time"),
+        )
+        .arg(
+            Arg::with_name("grep")
+                .long("grep")
+                .takes_value(true)
+                .validator(|value| match Regex::new(&value) {
+                    Ok(_) => Ok(()),
+                    Err(error) => Err(error.to_string()),
+                })
+                .value_name("REGEX")
+                .required(false)
+                .help("Only display lines matching the regular expression"),
+        )
+        .arg(
+            Arg::with_name("invert")
+                .short("v")
+                .long("invert")
+                .case_insensitive(true)
+                .takes_value(false)
+                .requires("grep")
+                .required(false)
+                .help("Only display lines that do not match the --grep expression"),
+        )
+        .arg(
+            Arg::with_name("after-context")
+                .short("A")
+                .long("after-context")
+                .takes_value(true)
+                .requires("grep")
+                .validator(validate_context)
+                .value_name("NUMBER")
+                .required(false)
+                .help("Also display this many lines after every --grep match. Groups of lines that aren't next to each other are separated by --, like grep does. With -n, these lines count, too"),
+        )
+        .arg(
+            Arg::with_name("before-context")
+                .short("B")
+                .long("before-context")
+                .takes_value(true)
+                .requires("grep")
+                .validator(validate_context)
+                .value_name("NUMBER")
+                .required(false)
+                .help("Also display this many lines before every --grep match"),
+        )
+        .arg(
+            Arg::with_name("context")
+                .short("C")
+                .long("context")
+                .takes_value(true)
+                .requires("grep")
+                .validator(validate_context)
+                .value_name("NUMBER")
+                .required(false)
+                .help("Also display this many lines before and after every --grep match. -A and -B take precedence"),
+        )
+        .arg(
+            Arg::with_name("since")
+                .long("since")
+                .takes_value(true)
+                .conflicts_with("bytes")
+                .validator(|value| parse_since(&value).map(|_| ()))
+                .value_name("TIME")
+                .required(false)
+                .help("Only display lines whose timestamp is at or after this time, e.g. --since \"2024-01-02 03:00:00\". The time can also be given without seconds, or without the time of day. Lines are expected to start with a timestamp laid out like --time-format. Lines without one, like those of a stack trace, belong to the last line that had one"),
+        )
+        .arg(
+            Arg::with_name("time-format")
+                .long("time-format")
+                .takes_value(true)
+                .validator(|value| {
+                    if StrftimeItems::new(&value).any(|item| item == Item::Error) {
+                        Err(format!("\"{}\" is not a valid time format", value))
+                    } else {
+                        Ok(())
+                    }
+                })
+                .value_name("FORMAT")
+                .required(false)
+                .help("strftime-style layout of the timestamps at the start of lines, for --since and --merge, e.g. --time-format \"[%d/%b/%Y:%H:%M:%S\" for web server logs. Without a year, like with syslog's \"%b %d %H:%M:%S\", the year of --since is assumed [default: %Y-%m-%d %H:%M:%S]"),
+        )
+        .arg(
+            Arg::with_name("on-match")
+                .long("on-match")
+                .takes_value(true)
+                .requires("grep")
+                .value_name("COMMAND")
+                .required(false)
+                .help("While following, run a shell command for every new line matching --grep, e.g. --on-match 'notify-send \"$TAIL_LINE\"' for a desktop notification. The line, its number and its file are passed in the environment variables TAIL_LINE, TAIL_LINE_NUMBER and TAIL_FILE. The command runs with your rights, so don't paste lines into it yourself, since whoever can write to the file could sneak in commands that way. Quote the variables instead. Commands run in the background, at most 8 at a time, and matches beyond that are skipped"),
+        )
+        .arg(
+            Arg::with_name("color")
+                .long("color")
+                .case_insensitive(true)
+                .takes_value(true)
+                .min_values(0)
+                .require_equals(true)
+                .possible_values(&["auto", "always", "never"])
+                .value_name("WHEN")
+                .required(false)
+                .help("Color line numbers and highlight --grep matches. \"auto\" (the default for plain --color) only colors when writing to a terminal and NO_COLOR is not set. Lines arriving while following get green line numbers"),
+        )
+        .arg(
+            Arg::with_name("no-line-numbers")
+                .short("N")
+                .long("no-line-numbers")
+                .case_insensitive(true)
+                .takes_value(false)
+                .required(false)
+                .help("Print only the content of lines, without line numbers in front of them"),
+        )
+        .arg(
+            Arg::with_name("relative-numbers")
+                .long("relative-numbers")
+                .takes_value(false)
+                .conflicts_with("no-line-numbers")
+                .required(false)
+                .help("Number lines from the end of the lines printed at first, so that the last of them is -1. While following, new lines go on with 0, +1 and so on. JSON lines keep their line number in the file"),
+        )
+        .arg(
+            Arg::with_name("separator")
+                .long("separator")
+                .takes_value(true)
+                .default_value("\\t")
+                .validator(|value| decode_escapes(&value).map(|_| ()))
+                .value_name("STRING")
+                .required(false)
+                .help("Text between the line number and the content of a line. Understands the escape sequences \\t, \\n, \\r, \\0 and \\\\"),
+        )
+        .arg(
+            Arg::with_name("prefix")
+                .long("prefix")
+                .takes_value(true)
+                .validator(|value| Template::parse(&value).map(|_| ()))
+                .value_name("TEMPLATE")
+                .required(false)
+                .help("Lay out every line of text output like TEMPLATE, e.g. \"{time} {file} {line}: {content}\". Knows the placeholders {file}, {line}, {time}, {age} and {content}, and {{ and }} for braces. Takes the place of the line number, --timestamp and --age prefixes, and the line is printed like \"{line}:\\t{content}\" without it"),
+        )
+        .arg(
+            Arg::with_name("number-format")
+                .long("number-format")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("plain")
+                .validator(|value| NumberFormat::parse(&value).map(|_| ()))
+                .value_name("FORMAT")
+                .required(false)
+                .help("How to print line numbers. \"plain\" prints them as they are, a width like \"6\" pads them with spaces and a width like \"06\" pads them with zeros"),
+        )
+        .arg(
+            Arg::with_name("zero-terminated")
+                .short("z")
+                .long("zero-terminated")
+                .case_insensitive(true)
+                .takes_value(false)
+                .required(false)
+                .help("Lines are terminated by NUL instead of newline, both when reading and printing. Overrides --output-line-ending"),
+        )
+        .arg(
+            Arg::with_name("line-ending")
+                .long("line-ending")
+                .case_insensitive(true)
+                .takes_value(true)
+                .possible_values(&["lf", "crlf", "cr", "auto"])
+                .default_value("lf")
+                .value_name("ENDING")
+                .conflicts_with("zero-terminated")
+                .required(false)
+                .help("How lines end in the files. \"lf\" also covers \"crlf\". \"cr\" is for classic Mac files. \"auto\" goes with cr if the beginning of a file has CRs, but no LFs. Standard input can't be looked at in advance, so there, \"auto\" is the same as \"lf\""),
+        )
+        .arg(
+            Arg::with_name("poll")
+                .long("poll")
+                .case_insensitive(true)
+                .takes_value(false)
+                .required(false)
+                .help("Check the file for changes on every refresh (see --rate), instead of waiting for the operating system to report them. Costs a little more, but also works where change notifications don't, e.g. on network file systems. Used automatically if watching the file fails"),
+        )
+        .arg(
+            Arg::with_name("open-mode")
+                .long("open-mode")
+                .takes_value(true)
+                .possible_values(&["shared", "exclusive"])
+                .default_value("shared")
+                .value_name("MODE")
+                .required(false)
+                .help("What other processes may do with a file while it is open for reading (Windows only, ignored elsewhere). \"shared\" lets them keep writing to it, and even rename or delete it, like log rotation does. \"exclusive\" only lets them read it, so opening fails while somebody is writing to it"),
+        )
+        .arg(
+            Arg::with_name("watch-parent-dir")
+                .long("watch-parent-dir")
+                .takes_value(false)
+                .conflicts_with("poll")
+                .required(false)
+                .help("Watch the directory of the file for changes to it, instead of the file itself. For file systems that only report changes of whole directories. Used automatically if watching the file fails"),
+        )
+        .arg(
+            Arg::with_name("encoding")
+                .long("encoding")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("utf-8")
+                .validator(|value| match Encoding::for_label(value.as_bytes()) {
+                    Some(_) => Ok(()),
+                    None => Err(format!("is not a known encoding: \"{}\"", value)),
+                })
+                .value_name("ENCODING")
+                .required(false)
+                .help("Encoding of the file, e.g. utf-8, utf-16le or windows-1252. A byte order mark at the beginning of the file takes precedence"),
+        )
+        .arg(
+            Arg::with_name("gzip")
+                .long("gzip")
+                .case_insensitive(true)
+                .takes_value(false)
+                .required(false)
+                .help("Decompress the file as gzip before reading it, whatever it starts with. Files compressed with gzip, zstd or bzip2 are otherwise recognized by their first bytes. Compressed files can't be followed"),
+        )
+        .arg(
+            Arg::with_name("no-decompress")
+                .long("no-decompress")
+                .takes_value(false)
+                .required(false)
+                .conflicts_with("gzip")
+                .help("Read files as they are, even if they start like compressed data"),
+        )
+        .arg(
+            Arg::with_name("verbose")
+                .long("verbose")
+                .case_insensitive(true)
+                .takes_value(false)
+                .conflicts_with("quiet")
+                .required(false)
+                .help("Always print headers naming the file, even if there is only one. Unlike GNU tail, there is no -v, since that is --invert"),
+        )
+        .arg(
+            Arg::with_name("quiet")
+                .short("q")
+                .long("quiet")
+                .alias("silent")
+                .case_insensitive(true)
+                .takes_value(false)
+                .required(false)
+                .help("Never print headers naming the file, not even for several files"),
+        )
+        .arg(
+            Arg::with_name("pid")
+                .long("pid")
+                .takes_value(true)
+                .validator(|value| match value.parse::<u32>() {
+                    Ok(pid) if pid > 0 => Ok(()),
+                    _ => Err(format!("should be a positive integer, not \"{}\"", value)),
+                })
+                .value_name("PID")
+                .required(false)
+                .help("Stop following once the process with this ID has exited, after printing what it has written. Ignored without -f or -F, like GNU tail does"),
+        )
+        .arg(
+            Arg::with_name("timeout")
+                .long("timeout")
+                .takes_value(true)
+                .validator(|value| validate_seconds(&value, false))
+                .value_name("SECONDS")
+                .required(false)
+                .help("Stop following after this many seconds, whether anything has changed or not"),
+        )
+        .arg(
+            Arg::with_name("heartbeat")
+                .long("heartbeat")
+                .takes_value(true)
+                .validator(|value| validate_seconds(&value, true))
+                .value_name("SECONDS")
+                .required(false)
+                .help("While following, tell on stderr that tail is still running whenever nothing has been printed for this many seconds. 0 turns it off"),
+        )
+        .arg(
+            Arg::with_name("until")
+                .long("until")
+                .takes_value(true)
+                .validator(|value| match Regex::new(&value) {
+                    Ok(_) => Ok(()),
+                    Err(error) => Err(error.to_string()),
+                })
+                .value_name("REGEX")
+                .required(false)
+                .help("Stop following after printing a line matching the regular expression. With --grep, only lines passing the filter count"),
+        )
+        .arg(
+            Arg::with_name("timeout-exit-code")
+                .long("timeout-exit-code")
+                .takes_value(true)
+                .validator(|value| match value.parse::<i32>() {
+                    Ok(_) => Ok(()),
+                    Err(error) => Err(error.to_string()),
+                })
+                .value_name("CODE")
+                .required(false)
+                .help("Exit code for when --timeout ends following, e.g. to tell it apart from --until finding its line [default: 0, or 3 with --until]"),
+        )
+        .arg(
+            Arg::with_name("clear")
+                .long("clear")
+                .takes_value(false)
+                .conflicts_with_all(&["bytes", "output"])
+                .required(false)
+                .help("When following, clear the screen and redraw the latest lines whenever something changes, like watch does, instead of scrolling. Has no effect if stdout is not a terminal"),
+        )
+        .arg(
+            Arg::with_name("also")
+                .long("also")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .conflicts_with_all(&["file", "dir", "bytes", "tui"])
+                .value_name("FILE")
+                .required(false)
+                .help("Read standard input, and FILE along with it. Can be given several times. While following, the lines of all of them are passed along as they come in, each tagged with the name of its source"),
+        )
+        .arg(
+            Arg::with_name("merge")
+                .long("merge")
+                .takes_value(false)
+                .conflicts_with_all(&["bytes", "max-lines-per-sec", "tui"])
+                .required(false)
+                .help("Merge the lines of all files into a single stream, ordered by the timestamps at their start (see --time-format). Every line is tagged with the name of its file. Lines without a timestamp stay with the line before them"),
+        )
+        .arg(
+            Arg::with_name("merge-window")
+                .long("merge-window")
+                .takes_value(true)
+                .requires("merge")
+                .validator(|value| parse_duration(&value, BareNumber::Seconds).map(|_| ()))
+                .value_name("DURATION")
+                .required(false)
+                .help("How long new lines are held back while following with --merge, so that lines of other files with earlier timestamps can still be put before them, in seconds or with a unit, e.g. 500ms [default: 1s]"),
+        )
+        .arg(
+            Arg::with_name("max-lines-per-sec")
+                .long("max-lines-per-sec")
+                .takes_value(true)
+                .conflicts_with("bytes")
+                .validator(|value| match value.parse::<f64>() {
+                    Ok(number) if number > 0.0 && number.is_finite() => Ok(()),
+                    _ => Err(format!("should be a positive number, not \"{}\"", value)),
+                })
+                .value_name("NUMBER")
+                .required(false)
+                .help("When following files, print at most this many new lines per second. Lines coming in faster wait in a queue holding up to 10 seconds worth of lines"),
+        )
+        .arg(
+            Arg::with_name("drop-policy")
+                .long("drop-policy")
+                .case_insensitive(true)
+                .takes_value(true)
+                .possible_values(&["drop-oldest", "block"])
+                .default_value("drop-oldest")
+                .value_name("POLICY")
+                .required(false)
+                .help("What to do when the queue of --max-lines-per-sec is full: Drop the oldest lines in it, or stop reading until there is room again"),
+        )
+        .arg(
+            Arg::with_name("stats")
+                .long("stats")
+                .takes_value(false)
+                .required(false)
+                .help("When done, print how many lines and bytes have been printed and how long it took to stderr"),
+        )
+        .arg(
+            Arg::with_name("stats-interval")
+                .long("stats-interval")
+                .takes_value(true)
+                .validator(|value| validate_seconds(&value, true))
+                .value_name("SECONDS")
+                .required(false)
+                .help("While following, print the throughput to stderr every this many seconds: Lines and bytes per second since the last time, how many lines have been printed in total, and for how long tail has been running. 0 turns it off"),
+        )
+        .arg(
+            Arg::with_name("debug-events")
+                .long("debug-events")
+                .takes_value(false)
+                .required(false)
+                .help("While following, print every change the operating system tells about to stderr, with the time it came in. Helps to find out why changes aren't picked up, e.g. when an editor saves by renaming a new file into place. Has no effect with --poll"),
+        )
+        .arg(
+            Arg::with_name("line-buffered")
+                .long("line-buffered")
+                .takes_value(false)
+                .required(false)
+                .help("Write out every batch of lines right away, instead of collecting them while stdout is a pipe or file. Always on when following"),
+        )
+        .arg(
+            Arg::with_name("dir")
+                .long("dir")
+                .takes_value(true)
+                .value_name("DIRECTORY")
+                .required(false)
+                .help("Also follow every file created in the directory while following, e.g. logs rotated into new, timestamped files. Needs -f or -F"),
+        )
+        .arg(
+            Arg::with_name("glob")
+                .long("glob")
+                .takes_value(true)
+                .requires("dir")
+                .validator(|value| match glob::Pattern::new(&value) {
+                    Ok(_) => Ok(()),
+                    Err(error) => Err(error.to_string()),
+                })
+                .value_name("PATTERN")
+                .required(false)
+                .help("Only pick up new files in --dir whose name matches the pattern, e.g. \"*.log\""),
+        )
+        .arg(
+            Arg::with_name("expand-globs")
+                .long("expand-globs")
+                .takes_value(false)
+                .required(false)
+                .help("Treat FILE as glob patterns, e.g. \"/var/log/*.log\", instead of literal paths. Useful where the shell doesn't expand them, like on Windows"),
+        )
+        .arg(
+            Arg::with_name("sleep-interval")
+                .short("s")
+                .long("sleep-interval")
+                .takes_value(true)
+                .validator(|value| validate_seconds(&value, false))
+                .value_name("SECONDS")
+                .required(false)
+                .help("Time between checks for changes when following, like GNU tail. Overrides --rate, which says the same in Hz. Mostly useful with --poll, where every check means looking at the files"),
+        )
+        .arg(
+            Arg::with_name("output")
+                .long("output")
+                .takes_value(true)
+                .value_name("path")
+                .required(false)
+                .help("Also write the output to this file, like tee does. The file is overwritten. Colors are left out of it, unless --color=always is given"),
+        )
+        .arg(
+            Arg::with_name("no-stdout")
+                .long("no-stdout")
+                .takes_value(false)
+                .requires("output")
+                .required(false)
+                .help("Only write to the file given with --output, not to stdout"),
+        )
+        .arg(
+            Arg::with_name("keep-newlines")
+                .long("keep-newlines")
+                .visible_alias("binary")
+                .takes_value(false)
+                .conflicts_with("output-line-ending")
+                .required(false)
+                .help("Print lines exactly as they are in the file, instead of making sure every line ends in a line ending. A last line without one is printed without one"),
+        )
+        .arg(
+            Arg::with_name("text")
+                .long("text")
+                .takes_value(false)
+                .conflicts_with_all(&["bytes", "keep-newlines", "output-line-ending", "zero-terminated"])
+                .required(false)
+                .help("Print every line with a plain newline, turning CRLF into LF, like text mode on Windows does. The default on Windows, while lines keep the endings of the file elsewhere. Same as --output-line-ending lf"),
+        )
+        .arg(
+            Arg::with_name("max-line-length")
+                .long("max-line-length")
+                .takes_value(true)
+                .validator(|value| match value.parse::<usize>() {
+                    Ok(length) if length > 0 => Ok(()),
+                    _ => Err("The maximum line length should be a positive integer".to_string()),
+                })
+                .value_name("NUMBER")
+                .conflicts_with("bytes")
+                .required(false)
+                .help("Cut lines short after this many characters, and mark them with an ellipsis. Keeps a huge line from being read into memory as a whole"),
+        )
+        .arg(
+            Arg::with_name("skip-errors")
+                .long("skip-errors")
+                .takes_value(false)
+                .conflicts_with("bytes")
+                .required(false)
+                .help("Warn about lines that can't be read, e.g. because of an I/O error, and go on with the next line, instead of stopping. Gives up after 10 such lines in a row"),
+        )
+        .arg(
+            Arg::with_name("strip-ansi")
+                .long("strip-ansi")
+                .takes_value(false)
+                .conflicts_with("bytes")
+                .required(false)
+                .help("Remove ANSI escape sequences, like the colors some programs write to their logs, from the lines while reading them. Patterns of --grep and the like match the text without them"),
+        )
+        .arg(
+            Arg::with_name("max-buffer-bytes")
+                .long("max-buffer-bytes")
+                .takes_value(true)
+                .validator(|value| match parse_count(&value) {
+                    Ok(0) => Err("should be at least 1 byte".to_string()),
+                    Ok(_) => Ok(()),
+                    Err(error) => Err(error),
+                })
+                .value_name("SIZE")
+                .conflicts_with("bytes")
+                .required(false)
+                .help("Hold at most this many bytes of lines in memory while reading, e.g. 512m, so that a huge -n can't use up all memory. The oldest of the wanted lines are left out beyond that, or with --head, the newest. The suffixes are the same as for -c"),
+        )
+        .arg(
+            Arg::with_name("count")
+                .long("count")
+                .takes_value(false)
+                .conflicts_with_all(&["bytes", "clear", "squeeze", "tui"])
+                .required(false)
+                .help("Print how many lines would have been printed, instead of the lines themselves, e.g. how many of the last lines match --grep. With several files, every file gets its own count. When following, a running total of all files is updated as lines come in"),
+        )
+        .arg(
+            Arg::with_name("mark-live")
+                .long("mark-live")
+                .takes_value(true)
+                .min_values(0)
+                .require_equals(true)
+                .value_name("TEXT")
+                .conflicts_with_all(&["bytes", "clear", "count", "tui"])
+                .required(false)
+                .help("Print a line right before the first line that arrives while following, to tell it apart from the lines that have been there before. Optionally takes the text of that line, e.g. --mark-live=\"=== now ===\" [default text: --- live ---]. Not with JSON lines"),
+        )
+        .arg(
+            Arg::with_name("count-total")
+                .long("count-total")
+                .takes_value(false)
+                .conflicts_with("bytes")
+                .required(false)
+                .help("Print how many lines every file has to stderr, e.g. \"app.log: 12345 lines\", before its last lines. Standard input is counted while it's read. Not for pipes that are followed, which never end"),
+        )
+        .arg(
+            Arg::with_name("squeeze")
+                .long("squeeze")
+                .takes_value(false)
+                .conflicts_with_all(&["bytes", "reverse"])
+                .required(false)
+                .help("Print runs of identical lines only once, like uniq, followed by how often the line has been repeated. Each file's lines are squeezed on their own"),
+        )
+        .arg(
+            Arg::with_name("field")
+                .long("field")
+                .takes_value(true)
+                .validator(|value| match value.parse::<usize>() {
+                    Ok(field) if field > 0 => Ok(()),
+                    _ => Err(String::from("Fields are counted from 1")),
+                })
+                .value_name("N")
+                .conflicts_with("bytes")
+                .required(false)
+                .help("Print only the Nth field of every line, counting from 1. Lines with fewer fields print empty. Lines are matched by --grep before the field is taken out"),
+        )
+        .arg(
+            Arg::with_name("delimiter")
+                .long("delimiter")
+                .takes_value(true)
+                .validator(|value| {
+                    if value.is_empty() {
+                        Err(String::from("The delimiter can't be empty"))
+                    } else {
+                        Ok(())
+                    }
+                })
+                .value_name("TEXT")
+                .requires("field")
+                .required(false)
+                .help("What separates the fields for --field. Without it, fields are separated by runs of whitespace, like awk does"),
+        )
+        .arg(
+            Arg::with_name("json-field")
+                .long("json-field")
+                .takes_value(true)
+                .value_name("PATH")
+                .conflicts_with_all(&["bytes", "field"])
+                .required(false)
+                .help("Read every line as a JSON object and print only the value of the given field. Nested fields are reached with dots, e.g. error.code, and array elements by their index. Lines without the field print empty. Lines that aren't JSON are printed unchanged"),
+        )
+        .arg(
+            Arg::with_name("json-strict")
+                .long("json-strict")
+                .takes_value(false)
+                .requires("json-field")
+                .required(false)
+                .help("Leave out lines that aren't JSON, instead of printing them unchanged"),
+        )
+        .arg(
+            Arg::with_name("tui")
+                .long("tui")
+                .takes_value(false)
+                .conflicts_with_all(&["bytes", "clear", "output", "reverse"])
+                .required(false)
+                .help("Show the lines in a scrollable view, instead of printing them. Arrow keys, PgUp/PgDn and Home/End scroll through the lines, f pauses or resumes following and q quits. / searches for a regular expression, n and N jump to the next and previous match and u clears the highlight. Lines arriving while scrolled up don't move the view. Needs -f or -F"),
+        )
+        .arg(
+            Arg::with_name("scrollback")
+                .long("scrollback")
+                .takes_value(true)
+                .default_value("10000")
+                .validator(|value| match value.parse::<usize>() {
+                    Ok(count) if count > 0 => Ok(()),
+                    _ => Err("The scrollback should be a positive number of lines".to_string()),
+                })
+                .value_name("NUMBER")
+                .required(false)
+                .help("How many lines --tui keeps around to scroll back to"),
+        )
+        .after_help(EXIT_CODES)
+}
+
+fn run() -> Result<()> {
+    let arguments: Vec<OsString> = std::env::args_os().collect();
+    let config_path = config::path(&arguments);
+    // A broken config file shouldn't keep tail from working
+    let config_options = match &config_path {
+        Some(path) => config::options(path).unwrap_or_else(|error| {
+            eprintln!("tail: ignoring {:?}: {:#}", path, error);
+            vec![]
+        }),
+        None => vec![],
+    };
+    let matches = match app().get_matches_from_safe(config::merge(&config_options, &arguments)) {
+        Err(error)
+            if !config_options.is_empty()
+                && !matches!(
+                    error.kind,
+                    ErrorKind::HelpDisplayed | ErrorKind::VersionDisplayed
+                ) =>
+        {
+            // Maybe it's the options from the config file that don't go with the given ones
+            let matches = app().get_matches_from_safe(&arguments);
+            if matches.is_ok() {
+                eprintln!(
+                    "tail: ignoring {:?}, since its options don't work here: {}",
+                    config_path.unwrap(), // Unwrap is safe, since there are no config options without a config file
+                    error
+                        .message
+                        .lines()
+                        .next()
+                        .unwrap_or_default()
+                        .trim_start_matches("error: ")
+                );
+            }
+            matches
+        }
+        matches => matches,
+    }
+    .unwrap_or_else(|error| match error.kind {
+        ErrorKind::HelpDisplayed | ErrorKind::VersionDisplayed => error.exit(),
+        _ => {
+            eprintln!("{}", error.message);
+            std::process::exit(EXIT_USAGE);
+        }
+    });
+
+    // Nothing else matters then
+    if let Some(shell) = matches.value_of("generate-completions") {
+        let shell = shell.parse::<Shell>().unwrap(); // Unwrap is safe because argument is restricted to the possible values
+        app().gen_completions_to("tail", shell, &mut io::stdout());
+        return Ok(());
+    }
+    if matches.is_present("examples") {
+        print_examples(&mut io::stdout()).context("Unable to write to stdout")?;
+        return Ok(());
+    }
+
+    // Parsing input arguments
+    let clock = Instant::now();
+
+    let frame_duration = match matches.value_of("sleep-interval") {
+        Some(seconds) => parse_duration(seconds, BareNumber::Seconds).unwrap(), // Unwrap is safe because argument has validator
+        None => parse_duration(matches.value_of("rate").unwrap(), BareNumber::Hertz).unwrap(), // Unwraps here are okay, I guess, because this has a default value and a validator
+    };
+
+    let notification_delay =
+        parse_duration(matches.value_of("delay").unwrap(), BareNumber::Milliseconds).unwrap(); // Unwraps here are okay, I guess, because this has a default value and a validator
+
+    let tac = matches.is_present("tac");
+    // Only what can't be read backwards is read from the top and turned around afterwards
+    let reverse_output = matches.is_present("reverse") || tac;
+
+    let follow_name =
+        matches.is_present("follow-name") || matches.value_of("follow") == Some("name");
+    let follow = matches.is_present("follow") || follow_name;
+    let retry = matches.is_present("retry");
+    let retry_backoff = Backoff::new(
+        frame_duration,
+        parse_duration(
+            matches.value_of("follow-retry-interval").unwrap(),
+            BareNumber::Seconds,
+        )
+        .unwrap(), // Unwraps are safe because argument has default value and validator
+    );
+    let transient_retry = TransientRetry::new(
+        matches
+            .value_of("transient-retries")
+            .unwrap()
+            .parse::<u32>()
+            .unwrap(), // Unwraps are safe because argument has default value and validator
+    );
+    let follow_symlinks = !matches.is_present("no-follow-symlinks");
+    let open_mode = match matches.value_of("open-mode").unwrap() {
+        "exclusive" => OpenMode::Exclusive,
+        _ => OpenMode::Shared,
+    }; // Unwrap is safe because argument has default value and is restricted to the possible values
+    let timeout = matches
+        .value_of("timeout")
+        .map(|seconds| parse_duration(seconds, BareNumber::Seconds).unwrap()); // Unwrap is safe because argument has validator
+    let heartbeat = matches
+        .value_of("heartbeat")
+        .map(|seconds| parse_duration(seconds, BareNumber::Seconds).unwrap()) // Unwrap is safe because argument has validator
+        .filter(|interval| !interval.is_zero());
+    let stats_interval = matches
+        .value_of("stats-interval")
+        .map(|seconds| parse_duration(seconds, BareNumber::Seconds).unwrap()) // Unwrap is safe because argument has validator
+        .filter(|interval| !interval.is_zero());
+    let timeout_exit_code = matches
+        .value_of("timeout-exit-code")
+        .map(|exit_code| exit_code.parse::<i32>().unwrap()); // Unwrap is safe because argument has validator
+    let until = matches
+        .value_of("until")
+        .map(|pattern| Regex::new(pattern).unwrap()); // Unwrap is safe because argument has validator
+    let pid = matches
+        .value_of("pid")
+        .map(|pid| pid.parse::<u32>().unwrap()); // Unwrap is safe because argument has validator
+
+    let template = matches
+        .value_of("prefix")
+        .map(|template| Template::parse(template).unwrap()); // Unwrap is safe because argument has validator
+    let uses = |part: TemplatePart| {
+        template
+            .as_ref()
+            .is_some_and(|template| template.uses(&part))
+    };
+    let timestamp_format = if matches.is_present("timestamp") || uses(TemplatePart::Time) {
+        Some(
+            matches
+                .value_of("timestamp")
+                .unwrap_or(DEFAULT_TIMESTAMP_FORMAT),
+        )
+    } else {
+        None
+    };
+
+    let line_numbering = LineNumbering::new(
+        matches
+            .value_of("max-line-number-digits")
+            .map(|digits| digits.parse::<u32>().unwrap()), // Unwrap is safe because argument has validator
+        match matches.value_of("line-number-overflow").unwrap() {
+            "reset" => OverflowPolicy::Reset,
+            _ => OverflowPolicy::Saturate,
+        }, // Unwrap is safe because argument has default value and is restricted to the possible values
+    );
+
+    let zero_terminated = matches.is_present("zero-terminated");
+    let detect_line_ending = matches.value_of("line-ending") == Some("auto");
+    let line_format = LineFormat {
+        delimiter: match matches.value_of("line-ending") {
+            _ if zero_terminated => b'\0',
+            Some("cr") => b'\r',
+            _ => b'\n', // CRLF ends in LF just the same, and the CR is taken care of when printing
+        },
+        encoding: Encoding::for_label(matches.value_of("encoding").unwrap().as_bytes()).unwrap(), // Unwraps are safe because argument has default value and validator
+        max_line_length: matches
+            .value_of("max-line-length")
+            .map(|length| length.parse::<usize>().unwrap()), // Unwrap is safe because argument has validator
+        max_buffer_bytes: matches
+            .value_of("max-buffer-bytes")
+            .map(|size| usize::try_from(parse_count(size).unwrap()).unwrap_or(usize::MAX)), // Unwrap is safe because argument has validator
+        skip_errors: matches.is_present("skip-errors"),
+        strip_ansi: matches.is_present("strip-ansi"),
+    };
+
+    let line_ending = match matches.value_of("output-line-ending").unwrap() {
+        _ if matches.is_present("keep-newlines") => LineEnding::Raw,
+        _ if zero_terminated => LineEnding::Nul,
+        _ if matches.is_present("text") => LineEnding::Lf,
+        "lf" => LineEnding::Lf,
+        "crlf" => LineEnding::Crlf,
+        _ if matches.occurrences_of("output-line-ending") == 0 => LineEnding::default(),
+        _ => LineEnding::Keep,
+    }; // Unwrap is safe because argument has default value and is restricted to the possible values
+
+    let byte_count = matches
+        .value_of("bytes")
+        .map(|value| parse_count(value.trim_start_matches(['+', '-'])).unwrap()); // Unwrap is safe because argument has validator
+
+    // In byte mode, the positions count bytes instead of lines
+    let count_argument = matches
+        .value_of("bytes")
+        .unwrap_or_else(|| matches.value_of("n").unwrap()); // Unwrap is safe because argument has default value
+    let from_line = count_argument.starts_with('+');
+    // Like GNU head. Without --head, the minus is ignored, like GNU tail does
+    let all_but_last = count_argument.starts_with('-');
+    let n = parse_count(count_argument.trim_start_matches(['+', '-'])).unwrap(); // Unwrap is safe because arguments have validator
+
+    let (start_position, stop_position, reading_direction) = match matches.value_of("range") {
+        Some(range) => {
+            let (start, end) = parse_range(range).unwrap(); // Unwrap is safe because argument has validator
+            range_positions(start, end)
+        }
+        None if tac => range_positions(None, None),
+        None => count_positions(n, from_line, all_but_last, matches.is_present("head")),
+    };
+
+    let context_lines = |name| {
+        matches
+            .value_of(name)
+            .or_else(|| matches.value_of("context"))
+            .map_or(0, |count| count.parse::<usize>().unwrap()) // Unwrap is safe because arguments have validator
+    };
+    let context = ContextLines {
+        before: context_lines("before-context"),
+        after: context_lines("after-context"),
+    };
+    let regex = matches
+        .value_of("grep")
+        .map(|pattern| Regex::new(pattern).unwrap()); // Unwrap is safe because argument has validator
+    let time_filter = matches.value_of("since").map(|since| TimeFilter {
+        since: parse_since(since).unwrap(), // Unwrap is safe because argument has validator
+        format: matches
+            .value_of("time-format")
+            .unwrap_or(DEFAULT_TIMESTAMP_FORMAT)
+            .to_string(),
+    });
+    if matches.is_present("time-format")
+        && !matches.is_present("since")
+        && !matches.is_present("merge")
+    {
+        return Err(UsageError(
+            "--time-format describes the timestamps for --since or --merge, so it needs one of them"
+                .to_string(),
+        )
+        .into());
+    }
+    // Formats without a year are taken to be in the current one, unless --since says otherwise
+    let merge = matches.is_present("merge").then(|| {
+        RefCell::new(MergeBuffer::new(
+            TimeFilter {
+                since: time_filter
+                    .as_ref()
+                    .map_or_else(|| chrono::Local::now().naive_local(), |filter| filter.since),
+                format: matches
+                    .value_of("time-format")
+                    .unwrap_or(DEFAULT_TIMESTAMP_FORMAT)
+                    .to_string(),
+            },
+            matches
+                .value_of("merge-window")
+                .map_or(DEFAULT_MERGE_WINDOW, |window| {
+                    parse_duration(window, BareNumber::Seconds).unwrap() // Unwrap is safe because argument has validator
+                }),
+        ))
+    });
+    let line_filter = if regex.is_some() || time_filter.is_some() {
+        Some(LineFilter {
+            regex,
+            invert: matches.is_present("invert"),
+            context,
+            since: time_filter,
+        })
+    } else {
+        None
+    };
+
+    let separator = decode_escapes(matches.value_of("separator").unwrap()).unwrap(); // Unwraps are safe because argument has default value and validator
+    if separator.is_empty() && !matches.is_present("no-line-numbers") {
+        eprintln!("tail: warning: the separator is empty, so line numbers will run into the content of lines");
+    }
+    let number_format = NumberFormat::parse(matches.value_of("number-format").unwrap()).unwrap(); // Unwraps are safe because argument has default value and validator
+
+    let file_names: Vec<&str> = match matches.values_of("file") {
+        Some(file_names) => file_names.collect(),
+        None if matches.is_present("dir") => vec![],
+        // Unwrap is safe, since the argument is present
+        None if matches.is_present("also") => std::iter::once("-")
+            .chain(matches.values_of("also").unwrap())
+            .collect(),
+        None => vec!["-"],
+    };
+    // The shell usually does this, but not on Windows, or if the pattern has been quoted
+    let expanded_file_names;
+    let file_names: Vec<&str> = if matches.is_present("expand-globs") {
+        expanded_file_names = expand_globs(&file_names)?;
+        expanded_file_names.iter().map(String::as_str).collect()
+    } else {
+        file_names
+    };
+
+    // New files showing up in here are followed, too
+    let directory = match matches.value_of("dir") {
+        Some(_) if !follow => {
+            return Err(UsageError(
+                "--dir only picks up files created while following, so it needs -f or -F"
+                    .to_string(),
+            )
+            .into())
+        }
+        Some(directory) => Some(
+            std::fs::canonicalize(directory)
+                .context(format!("Unable to find the directory {:?}", directory))?,
+        ),
+        None => None,
+    };
+    let file_pattern = matches
+        .value_of("glob")
+        .map(|pattern| glob::Pattern::new(pattern).unwrap()); // Unwrap is safe because argument has validator
+
+    if matches.is_present("age") && !follow {
+        return Err(UsageError(
+            "--age tells when lines arrived while following, so it needs -f or -F".to_string(),
+        )
+        .into());
+    }
+
+    let format = match matches.value_of("format").unwrap() {
+        "json" => OutputFormat::Json,
+        _ => OutputFormat::Text,
+    }; // Unwrap is safe because argument has default value and is restricted to the possible values
+
+    // Before anything is drawn on the terminal, or any file is opened
+    if matches.is_present("print-config") {
+        eprint!(
+            "{}",
+            Config {
+                file_names: &file_names,
+                count: n,
+                unit: if byte_count.is_some() {
+                    "bytes"
+                } else {
+                    "lines"
+                },
+                start_position,
+                stop_position,
+                reading_direction,
+                frame_duration,
+                notification_delay,
+                follow: match (follow, follow_name) {
+                    (false, _) => "no",
+                    (true, false) => "descriptor",
+                    (true, true) => "name",
+                },
+                retry,
+                format,
+                line_format,
+            }
+        );
+        return Ok(());
+    }
+
+    // The view is drawn on the terminal, and the keys to scroll it are read from there, too
+    let viewer = if matches.is_present("tui") {
+        if !follow {
+            return Err(UsageError(
+                "--tui shows lines as they come in, so it needs -f or -F".to_string(),
+            )
+            .into());
+        }
+        if file_names == ["-"] {
+            return Err(UsageError("--tui can't follow standard input, since it waits for keys while there are no new lines".to_string()).into());
+        }
+        if !io::stdout().is_terminal() {
+            return Err(UsageError("--tui needs stdout to be a terminal".to_string()).into());
+        }
+        Some(RefCell::new(tui::Viewer::new(
+            matches
+                .value_of("scrollback")
+                .unwrap()
+                .parse::<usize>()
+                .unwrap(), // Unwraps are safe because argument has default value and validator
+            matches.is_present("age"),
+        )))
+    } else {
+        None
+    };
+
+    // Like tee. Writing to a file that is being read would make it grow forever, so that's checked before creating the file
+    // truncates it
+    let output_path = matches.value_of("output").map(Path::new);
+    let output_file = match output_path {
+        Some(output_path) => {
+            if let Some(file_name) = file_names.iter().find(|file_name| {
+                **file_name != "-" && is_same_path(Path::new(file_name), output_path)
+            }) {
+                return Err(UsageError(format!(
+                    "{:?} is being read, so it can't be written to with --output",
+                    file_name
+                ))
+                .into());
+            }
+            Some(File::create(output_path).context(format!("Unable to create {:?}", output_path))?)
+        }
+        None => None,
+    };
+
+    // Ctrl+C ends waiting and following gracefully. Plain reads are short, so they don't need this
+    let interrupted = Arc::new(AtomicBool::new(false));
+    // Monitors that are told about changes wake the follow loop up, so it can sleep as long as nothing happens
+    let wakeup = Wakeup::new();
+    // The events are counted for --stats, too
+    let events = (matches.is_present("debug-events") || matches.is_present("stats"))
+        .then(|| EventLog::new(matches.is_present("debug-events")));
+    if follow || retry {
+        signals::register_interrupt(&interrupted, &wakeup)
+            .context("Failed to register handler for Ctrl+C")?;
+    }
+
+    let header_mode = if matches.is_present("quiet") {
+        HeaderMode::Never
+    } else if matches.is_present("verbose") {
+        HeaderMode::Always
+    } else {
+        HeaderMode::Auto
+    };
+
+    let last_source = RefCell::new(None);
+    let squeeze = RefCell::new(Squeeze::default());
+    let groups = RefCell::new(Groups::default());
+    let relative_numbers = RefCell::new(RelativeNumbers::default());
+    let color_mode = if matches.is_present("color") {
+        match matches.value_of("color") {
+            Some("always") => ColorMode::Always,
+            Some("never") => ColorMode::Never,
+            _ => ColorMode::Auto,
+        }
+    } else {
+        ColorMode::Never
+    };
+    // Lines of several sources that end up mixed up with each other
+    let tag_sources = merge.is_some() || matches.is_present("also");
+    let output_options = OutputOptions {
+        reverse_output,
+        line_ending,
+        timestamp_format,
+        age: if matches.is_present("age") || uses(TemplatePart::Age) {
+            Some(clock)
+        } else {
+            None
+        },
+        format,
+        file_name: file_names.first().copied().unwrap_or("-"),
+        tag_sources,
+        // JSON lines name their file anyway, and so do the tags of merged lines
+        // Any number of files might show up in the directory
+        headers: if format == OutputFormat::Text
+            && !tag_sources
+            && header_mode.shows_headers(if directory.is_some() {
+                usize::MAX
+            } else {
+                file_names.len()
+            }) {
+            Some(&last_source)
+        } else {
+            None
+        },
+        color: match color_mode {
+            // The escape codes would end up in the file, too
+            ColorMode::Auto if output_file.is_some() => false,
+            // The view has no use for escape codes, they would show up as garbage
+            _ if viewer.is_some() => false,
+            color_mode => color_mode.enabled(),
+        },
+        highlight: line_filter
+            .as_ref()
+            .filter(|line_filter| !line_filter.invert)
+            .and_then(|line_filter| line_filter.regex.as_ref()),
+        fresh: false,
+        show_line_numbers: !matches.is_present("no-line-numbers"),
+        separator: &separator,
+        number_format,
+        squeeze: if matches.is_present("squeeze") {
+            Some(&squeeze)
+        } else {
+            None
+        },
+        groups: if context != ContextLines::default() {
+            Some(&groups)
+        } else {
+            None
+        },
+        relative_numbers: if matches.is_present("relative-numbers") {
+            Some(&relative_numbers)
+        } else {
+            None
+        },
+        extraction: match (matches.value_of("field"), matches.value_of("json-field")) {
+            (Some(field), _) => Some(Extraction::Field {
+                number: field.parse().unwrap(), // Unwrap is safe because argument has validator
+                delimiter: matches.value_of("delimiter"),
+            }),
+            (None, Some(path)) => Some(Extraction::JsonField {
+                path,
+                strict: matches.is_present("json-strict"),
+            }),
+            (None, None) => None,
+        },
+        template: template.as_ref(),
+    };
+    // Lines arriving while following stand out from the initial ones, if there are colors
+    let fresh_output_options = OutputOptions {
+        fresh: true,
+        ..output_options
+    };
+
+    // Redrawing only makes sense on a terminal. Anywhere else, lines are appended like always
+    let screen = if follow && matches.is_present("clear") && io::stdout().is_terminal() {
+        Some(RefCell::new(ScreenWindow::new(if from_line {
+            None
+        } else {
+            Some(usize::try_from(n).unwrap_or(usize::MAX)) // More lines than fit into memory wouldn't fit on the screen either
+        })))
+    } else {
+        None
+    };
+    // Lines that keep coming in should show up right away, even in a pipe
+    let stdout = RefCell::new(Output::new(
+        Tee {
+            stdout: if matches.is_present("no-stdout") {
+                None
+            } else {
+                Some(io::stdout())
+            },
+            file: output_file,
+        },
+        follow || matches.is_present("line-buffered"),
+    ));
+    let match_command = matches
+        .value_of("on-match")
+        .map(|command| RefCell::new(MatchCommand::new(command, on_match::MAX_RUNNING)));
+    let count_only = matches.is_present("count");
+    let live_marker = matches.is_present("mark-live").then(|| {
+        RefCell::new(LiveMarker::new(
+            matches.value_of("mark-live").unwrap_or(DEFAULT_LIVE_MARKER),
+        ))
+    });
+    // On a terminal, the running count is updated in place
+    let running_count = RefCell::new(RunningCount::new(io::stdout().is_terminal()));
+    let watch_mode = if matches.is_present("poll") {
+        WatchMode::Poll
+    } else if matches.is_present("watch-parent-dir") {
+        WatchMode::ParentDirectory
+    } else {
+        WatchMode::File
+    };
+    // Only following by name cares whether another file has taken the place of the followed one
+    let max_unchanged_stats = matches
+        .value_of("max-unchanged-stats")
+        .filter(|_| follow_name)
+        .map(|count| count.parse::<u64>().unwrap()); // Unwrap is safe because argument has validator
+
+    // Reading from the top, lines can be printed as soon as they have been read, instead of holding all of them in memory
+    // first. Unless something needs all of them at once, or might stop earlier
+    let streamed_range = match (reading_direction, start_position, stop_position) {
+        (ReadingDirection::TopToBottom, Position::FromBegin(start), Position::FromBegin(stop)) => {
+            Some((start, Some(stop)))
+        }
+        (ReadingDirection::TopToBottom, Position::FromBegin(start), Position::FromEnd(0)) => {
+            Some((start, None))
+        }
+        _ => None,
+    }
+    .filter(|_| {
+        merge.is_none()
+            && !count_only
+            && !output_options.reverse_output
+            && output_options.squeeze.is_none()
+            && output_options.relative_numbers.is_none()
+            && line_format.max_buffer_bytes.is_none()
+            && !(follow && until.is_some())
+    });
+    // Counts and relative line numbers need all lines at once
+    let tac_streamed = tac
+        && !count_only
+        && output_options.relative_numbers.is_none()
+        && line_format.max_buffer_bytes.is_none();
+    // With --max-lines-per-sec, new lines wait in a queue, and only a few of them are printed per frame
+    let output_queue = matches
+        .value_of("max-lines-per-sec")
+        .map(|lines_per_second| {
+            RefCell::new(OutputQueue::new(
+                lines_per_second.parse::<f64>().unwrap(), // Unwrap is safe because argument has validator
+                frame_duration,
+                match matches.value_of("drop-policy").unwrap() {
+                    "block" => DropPolicy::Block,
+                    _ => DropPolicy::DropOldest,
+                }, // Unwrap is safe because argument has default value and is restricted to the possible values
+            ))
+        });
+
+    Session {
+        clock,
+        frame_duration,
+        refresh_count: Cell::new(0),
+        interrupted,
+        wakeup,
+        events,
+        byte_count,
+        start_position,
+        stop_position,
+        reading_direction,
+        streamed_range,
+        tac_streamed,
+        line_format,
+        detect_line_ending,
+        line_filter: line_filter.as_ref(),
+        line_numbering,
+        gzip: matches.is_present("gzip"),
+        decompress: !matches.is_present("no-decompress"),
+        count_total: matches.is_present("count-total"),
+        follow,
+        follow_name,
+        follow_symlinks,
+        retry,
+        retry_backoff,
+        transient_retry,
+        open_mode,
+        watch_mode,
+        notification_delay,
+        max_unchanged_stats,
+        also: matches.is_present("also"),
+        directory,
+        file_pattern,
+        output_path,
+        until,
+        pid,
+        reopen_signal: matches.is_present("reopen-signal"),
+        timeout,
+        timeout_exit_code,
+        heartbeat,
+        stats_interval,
+        stats: matches.is_present("stats"),
+        output_options,
+        fresh_output_options,
+        stdout,
+        emitted: Stats::default(),
+        count_only,
+        viewer,
+        screen,
+        live_marker,
+        running_count,
+        match_command,
+        merge,
+        output_queue,
+        until_found: Cell::new(false),
+    }
+    .tail(&file_names)
+}
+
+fn print_examples(out: &mut impl Write) -> io::Result<()> {
+    for (index, (description, command)) in EXAMPLES.iter().enumerate() {
+        if index > 0 {
+            writeln!(out)?;
+        }
+        writeln!(out, "{}:", description)?;
+        writeln!(out, "    {}", command)?;
+    }
+    Ok(())
+}
+
+// Parse a count like GNU tail does, with an optional multiplier suffix, e.g. "2k" for 2048
+fn parse_count(value: &str) -> std::result::Result<u64, String> {
+    let value = value.trim();
+    let (number, multiplier) = match value.char_indices().last() {
+        Some((index, suffix)) if suffix.is_ascii_alphabetic() => {
+            let multiplier: u64 = match suffix.to_ascii_lowercase() {
+                'b' => 512,
+                'k' => 1024,
+                'm' => 1024 * 1024,
+                'g' => 1024 * 1024 * 1024,
+                _ => return Err(format!("has an unknown suffix: \"{}\"", suffix)),
+            };
+            (&value[..index], multiplier)
+        }
+        _ => (value, 1),
+    };
+
+    let number = number.parse::<u64>().map_err(|_| {
+        "should be a positive integer, optionally followed by b, k, m or g".to_string()
+    })?;
+
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("is too large: \"{}\"", value))
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum BareNumber {
+    Hertz,        // --rate has always been a frequency
+    Milliseconds, // --delay has always been in milliseconds
+    Seconds,
+}
+
+// Durations can be given with a unit, e.g. "500ms", "1.5s" or "2m". A bare number means what it always has for the option
+fn parse_duration(value: &str, bare_number: BareNumber) -> std::result::Result<Duration, String> {
+    let value = value.trim();
+    let (number, unit) = value.split_at(
+        value
+            .find(|character: char| character.is_ascii_alphabetic())
+            .unwrap_or(value.len()),
+    );
+    let number = number
+        .parse::<f64>()
+        .ok()
+        .filter(|number| *number >= 0.0 && number.is_finite())
+        .ok_or_else(|| {
+            format!(
+                "should be a non-negative number, optionally followed by hz, ms, s or m, not \"{}\"",
+                value
+            )
+        })?;
+
+    let seconds = match (unit.to_ascii_lowercase().as_str(), bare_number) {
+        ("", BareNumber::Hertz) | ("hz", _) if number == 0.0 => {
+            return Err("should be more than 0 Hz".to_string())
+        }
+        ("", BareNumber::Hertz) | ("hz", _) => 1.0 / number,
+        ("", BareNumber::Milliseconds) | ("ms", _) => number / 1000.0,
+        ("", BareNumber::Seconds) | ("s", _) => number,
+        ("m", _) => number * 60.0,
+        (unit, _) => return Err(format!("has an unknown unit: \"{}\"", unit)),
+    };
+    Duration::try_from_secs_f64(seconds).map_err(|_| format!("is too long: \"{}\"", value))
+}
+
+// Options that take a number of seconds, or a duration with a unit. With zero_allowed, 0 turns the option off
+fn validate_seconds(value: &str, zero_allowed: bool) -> std::result::Result<(), String> {
+    match parse_duration(value, BareNumber::Seconds) {
+        Ok(duration) if duration.is_zero() && !zero_allowed => Err(format!(
+            "should be a positive number of seconds, not \"{}\"",
+            value
+        )),
+        Ok(_) => Ok(()),
+        Err(error) => Err(error),
+    }
+}
+
+// Times are given the same way, no matter how the log lays them out
+fn parse_since(value: &str) -> std::result::Result<NaiveDateTime, String> {
+    ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .ok()
+                .map(|date| date.and_time(NaiveTime::MIN))
+        })
+        .ok_or_else(|| {
+            format!(
+                "\"{}\" is not a valid time. Try something like \"2024-01-02 03:00:00\"",
+                value
+            )
+        })
+}
+
+fn validate_context(value: String) -> std::result::Result<(), String> {
+    match value.parse::<usize>() {
+        Ok(_) => Ok(()),
+        Err(_) => Err(format!(
+            "The number of context lines should be a non-negative integer, not \"{}\"",
+            value
+        )),
+    }
+}
+
+// Turn escape sequences like "\t" into the characters they stand for, so they can be typed on the command line
+fn decode_escapes(value: &str) -> std::result::Result<String, String> {
+    let mut decoded = String::with_capacity(value.len());
+    let mut characters = value.chars();
+
+    while let Some(character) = characters.next() {
+        if character != '\\' {
+            decoded.push(character);
+            continue;
+        }
+
+        decoded.push(match characters.next() {
+            Some('t') => '\t',
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('0') => '\0',
+            Some('\\') => '\\',
+            Some(other) => return Err(format!("has an unknown escape sequence: \"\\{}\"", other)),
+            None => return Err("ends with an incomplete escape sequence".to_string()),
+        });
+    }
+
+    Ok(decoded)
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+struct NumberFormat {
+    width: usize,
+    zero_padded: bool,
+}
+
+impl NumberFormat {
+    // Similar to printf: "6" pads to a width of 6 with spaces, "06" pads with zeros and "plain" doesn't pad at all
+    fn parse(value: &str) -> std::result::Result<Self, String> {
+        if value == "plain" {
+            return Ok(Self::default());
+        }
+
+        let width = value.parse::<usize>().map_err(|_| {
+            "should be \"plain\" or a width, optionally starting with 0 for zero padding"
+                .to_string()
+        })?;
+
+        Ok(Self {
+            width,
+            zero_padded: value.starts_with('0'),
+        })
+    }
+
+    fn format(&self, line_number: u64) -> String {
+        if self.zero_padded {
+            format!("{:0width$}", line_number, width = self.width)
+        } else {
+            format!("{:>width$}", line_number, width = self.width)
+        }
+    }
+
+    // Lines after the end get a plus, so that they can be told apart from the ones before it at a glance
+    fn format_offset(&self, offset: i128) -> String {
+        match (self.zero_padded, offset) {
+            (true, 0) => format!("{:0width$}", offset, width = self.width),
+            (true, _) => format!("{:+0width$}", offset, width = self.width),
+            (false, 0) => format!("{:>width$}", offset, width = self.width),
+            (false, _) => format!("{:>+width$}", offset, width = self.width),
+        }
+    }
+}
+
+// Bytes are written as they are, since they don't have to be valid text
+fn print_bytes(out: &mut impl Write, bytes: &[u8]) -> io::Result<()> {
+    out.write_all(bytes)
+}
+
+// Everything bound for stdout goes through a single buffer. Line buffered, it is flushed after every batch, so that
+// whoever reads from a pipe gets to see the lines right away. Otherwise, it is only written once it's full
+struct Output<Writer: Write> {
+    writer: BufWriter<Writer>,
+    line_buffered: bool,
+}
+
+impl<Writer: Write> Output<Writer> {
+    fn new(writer: Writer, line_buffered: bool) -> Self {
+        Self {
+            writer: BufWriter::new(writer),
+            line_buffered,
+        }
+    }
+
+    fn end_batch(&mut self) -> io::Result<()> {
+        if self.line_buffered {
+            self.writer.flush()
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<Writer: Write> Write for Output<Writer> {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        self.writer.write(buffer)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+// Everything is written to stdout and the file of --output, or to just one of them
+struct Tee {
+    stdout: Option<io::Stdout>,
+    file: Option<File>,
+}
+
+impl Write for Tee {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        if let Some(stdout) = &mut self.stdout {
+            stdout.write_all(buffer)?;
+        }
+        if let Some(file) = &mut self.file {
+            file.write_all(buffer)?;
+        }
+        Ok(buffer.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if let Some(stdout) = &mut self.stdout {
+            stdout.flush()?;
+        }
+        if let Some(file) = &mut self.file {
+            file.flush()?;
+        }
+        Ok(())
+    }
+}
+
+// E.g. `tail file | head`, where head is done before we are
+fn is_broken_pipe(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        cause
+            .downcast_ref::<io::Error>()
+            .is_some_and(|error| error.kind() == io::ErrorKind::BrokenPipe)
+    })
+}
+
+// Once nobody reads our output anymore, following stops just like after Ctrl+C, so that everything is cleaned up.
+// Any other error is an actual error, though
+fn stop_on_broken_pipe<T: Default>(result: Result<T>, interrupted: &AtomicBool) -> Result<T> {
+    match result {
+        Err(error) if is_broken_pipe(&error) => {
+            interrupted.store(true, Ordering::SeqCst);
+            Ok(T::default())
+        }
+        result => result,
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum OutputFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Clone)]
+struct OutputOptions<'a> {
+    reverse_output: bool,
+    line_ending: LineEnding,
+    timestamp_format: Option<&'a str>,
+    age: Option<Instant>, // When tail started, if lines are prefixed with how long they took to arrive
+    format: OutputFormat,
+    file_name: &'a str,
+    tag_sources: bool, // Every line starts with the name of its file, when the lines of several files are mixed
+    color: bool,
+    highlight: Option<&'a Regex>,
+    fresh: bool,
+    show_line_numbers: bool,
+    separator: &'a str,
+    number_format: NumberFormat,
+    headers: Option<&'a RefCell<Option<PathBuf>>>, // The source of the last printed lines, if headers are wanted
+    squeeze: Option<&'a RefCell<Squeeze>>,
+    groups: Option<&'a RefCell<Groups>>, // Only with context lines
+    relative_numbers: Option<&'a RefCell<RelativeNumbers>>,
+    extraction: Option<Extraction<'a>>, // Only part of every line is printed
+    template: Option<&'a Template>,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonLine<'a> {
+    line: u64,
+    content: &'a str,
+    file: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    time: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    age: Option<f64>, // Seconds
+}
+
+#[derive(Debug, Serialize)]
+struct JsonRepeats<'a> {
+    repeated: u64,
+    file: &'a str,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum HeaderMode {
+    Auto, // Only with several files, like GNU tail
+    Always,
+    Never,
+}
+
+impl HeaderMode {
+    fn shows_headers(self, file_count: usize) -> bool {
+        match self {
+            HeaderMode::Auto => file_count > 1,
+            HeaderMode::Always => true,
+            HeaderMode::Never => false,
+        }
+    }
+}
+
+fn header(path: &Path) -> String {
+    if path == Path::new("-") {
+        "==> standard input <==\n".to_string()
+    } else {
+        format!("==> {} <==\n", path.display())
+    }
+}
+
+// A header is only needed when the output switches over to another file. Blocks of different files are separated by an empty line
+fn next_header(source: Option<&Path>, options: &OutputOptions) -> Option<String> {
+    let (source, last_source) = match (source, options.headers) {
+        (Some(source), Some(last_source)) => (source, last_source),
+        _ => return None,
+    };
+
+    let mut last_source = last_source.borrow_mut();
+    if last_source.as_deref() == Some(source) {
+        return None;
+    }
+
+    let separator = if last_source.is_some() { "\n" } else { "" };
+    *last_source = Some(source.to_path_buf());
+    Some(format!("{}{}", separator, header(source)))
+}
+
+fn print_header(
+    out: &mut impl Write,
+    source: Option<&Path>,
+    options: &OutputOptions,
+) -> io::Result<()> {
+    match next_header(source, options) {
+        Some(header) => out.write_all(header.as_bytes()),
+        None => Ok(()),
+    }
+}
+
+fn print_lines(
+    out: &mut impl Write,
+    lines: Vec<Line>,
+    reading_direction: ReadingDirection,
+    source: Option<&Path>,
+    options: &OutputOptions,
+) -> io::Result<()> {
+    print_header(out, source, options)?;
+
+    let mut lines = format_lines(lines, reading_direction, source, options);
+    if options.reverse_output {
+        lines.reverse();
+    }
+
+    for line in lines {
+        out.write_all(line.as_bytes())?;
+    }
+    Ok(())
+}
+
+// With --count, a file's lines are only counted, and the count takes the place of the lines
+fn print_count(
+    out: &mut impl Write,
+    count: u64,
+    source: Option<&Path>,
+    options: &OutputOptions,
+) -> io::Result<()> {
+    print_header(out, source, options)?;
+    writeln!(out, "{}", count)
+}
+
+// While following with --count, the total is printed again whenever it changes
+struct RunningCount {
+    last: Option<u64>,
+    overwrite: bool, // Go back to the start of the line, instead of starting a new one
+}
+
+impl RunningCount {
+    fn new(overwrite: bool) -> Self {
+        Self {
+            last: None,
+            overwrite,
+        }
+    }
+
+    fn update(&mut self, out: &mut impl Write, count: u64) -> io::Result<()> {
+        if self.last == Some(count) {
+            return Ok(());
+        }
+        self.last = Some(count);
+
+        if self.overwrite {
+            // Counts only ever grow, so the new one covers the old one completely
+            write!(out, "\r{}", count)
+        } else {
+            writeln!(out, "{}", count)
+        }
+    }
+
+    // The cursor is still behind an overwritten count, so the line has to be ended
+    fn finish(&mut self, out: &mut impl Write) -> io::Result<()> {
+        if self.overwrite && self.last.take().is_some() {
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+}
+
+// Formatted lines in the order they appear in the file, no matter which way they have been read
+fn format_lines(
+    mut lines: Vec<Line>,
+    reading_direction: ReadingDirection,
+    source: Option<&Path>,
+    options: &OutputOptions,
+) -> Vec<String> {
+    let source_name = source.map(|source| source.to_string_lossy());
+    let options = &OutputOptions {
+        file_name: source_name.as_deref().unwrap_or(options.file_name),
+        ..*options
+    };
+
+    if reading_direction == ReadingDirection::BottomToTop {
+        lines.reverse();
+    }
+
+    // All lines of a batch have been read at the same time
+    let time = options
+        .timestamp_format
+        .map(|format| chrono::Local::now().format(format).to_string());
+
+    let mut squeeze = options.squeeze.map(RefCell::borrow_mut);
+    let mut groups = options.groups.map(RefCell::borrow_mut);
+    let mut formatted = vec![];
+    for (line_number, line) in &lines {
+        let extracted;
+        let line = match options.extraction {
+            Some(extraction) => match extraction.extract(line) {
+                Some(part) => {
+                    extracted = part;
+                    &extracted
+                }
+                None => continue,
+            },
+            None => line,
+        };
+        // Lines left out by squeezing still belong to their group. Separators would break JSON lines, though
+        if let Some(groups) = &mut groups {
+            if groups.separates(options.file_name, *line_number)
+                && options.format == OutputFormat::Text
+            {
+                formatted.push(format_note("--", options));
+            }
+        }
+        if let Some(squeeze) = &mut squeeze {
+            match squeeze.push(options.file_name, line) {
+                Run::Repeated => continue,
+                Run::Started { ended_repeats: 0 } => {}
+                Run::Started { ended_repeats } => {
+                    formatted.push(format_repeats(ended_repeats, options))
+                }
+            }
+        }
+        formatted.push(format_line(*line_number, line, time.as_deref(), options));
+    }
+    formatted
+}
+
+// With --field or --json-field, only part of every line is printed
+#[derive(Debug, Clone, Copy)]
+enum Extraction<'a> {
+    // Like awk '{ print $N }'
+    Field {
+        number: usize,              // Counted from 1
+        delimiter: Option<&'a str>, // Runs of whitespace, if there is none
+    },
+    // For logs with a JSON object on every line
+    JsonField {
+        path: &'a str, // Dotted, e.g. error.code
+        strict: bool,  // Lines that aren't JSON are left out, instead of kept whole
+    },
+}
+
+impl Extraction<'_> {
+    // None if the line should be left out. The line ending stays, so that lines are still terminated like they would have been
+    fn extract(&self, line: &str) -> Option<String> {
+        let content = strip_line_ending(line);
+        let ending = &line[content.len()..];
+        let part = match *self {
+            Extraction::Field { number, delimiter } => match delimiter {
+                Some(delimiter) => content.split(delimiter).nth(number - 1),
+                None => content.split_whitespace().nth(number - 1),
+            }
+            .unwrap_or("")
+            .to_string(),
+            Extraction::JsonField { path, strict } => {
+                match serde_json::from_str::<serde_json::Value>(content) {
+                    Ok(value) => match json_field(&value, path) {
+                        Some(serde_json::Value::String(text)) => text.clone(),
+                        Some(serde_json::Value::Null) | None => String::new(),
+                        Some(value) => value.to_string(),
+                    },
+                    Err(_) if strict => return None,
+                    Err(_) => content.to_string(),
+                }
+            }
+        };
+        Some(format!("{}{}", part, ending))
+    }
+}
+
+fn json_field<'a>(value: &'a serde_json::Value, path: &str) -> Option<&'a serde_json::Value> {
+    path.split('.').try_fold(value, |value, key| match value {
+        serde_json::Value::Array(elements) => elements.get(key.parse::<usize>().ok()?),
+        value => value.get(key),
+    })
+}
+
+// With context lines, groups of lines that aren't next to each other in the file are separated, like grep does
+#[derive(Debug, Default)]
+struct Groups {
+    last_lines: HashMap<String, u64>, // The number of the last line of every file
+}
+
+impl Groups {
+    fn separates(&mut self, file_name: &str, line_number: u64) -> bool {
+        match self.last_lines.insert(file_name.to_string(), line_number) {
+            Some(last_line) => last_line.checked_add(1) != Some(line_number),
+            None => false,
+        }
+    }
+}
+
+// With --relative-numbers, lines are numbered from the end of the lines that have been printed at first, file by file
+#[derive(Debug, Default)]
+struct RelativeNumbers {
+    ends: HashMap<String, u64>, // The number of the line right after the first lines of every file
+}
+
+impl RelativeNumbers {
+    // Where the first lines ended. Without any, it's where reading has stopped
+    fn set_end(&mut self, file_name: &str, first_lines: &[Line], last_read_line: Option<&Line>) {
+        let last = first_lines
+            .iter()
+            .map(|(line_number, _)| *line_number)
+            .max()
+            .or(last_read_line.map(|(line_number, _)| *line_number))
+            .unwrap_or(0);
+        self.ends
+            .insert(file_name.to_string(), last.saturating_add(1));
+    }
+
+    // Files that show up while following are new from their first line on
+    fn offset(&self, file_name: &str, line_number: u64) -> i128 {
+        i128::from(line_number) - i128::from(self.ends.get(file_name).copied().unwrap_or(1))
+    }
+}
+
+// With --squeeze, only the first line of a run of identical lines is printed, along with its line number
+#[derive(Debug, Default)]
+struct Squeeze {
+    runs: HashMap<String, (String, u64)>, // The last line of every file, and how often it has been repeated since
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Run {
+    Repeated, // The line is left out
+    Started { ended_repeats: u64 },
+}
+
+impl Squeeze {
+    // Line endings don't count, so that an unfinished last line is still a repeat
+    fn push(&mut self, file_name: &str, line: &str) -> Run {
+        let content = strip_line_ending(line);
+        match self.runs.get_mut(file_name) {
+            Some((last, repeats)) if last == content => {
+                *repeats += 1;
+                Run::Repeated
+            }
+            Some((last, repeats)) => {
+                let ended_repeats = std::mem::take(repeats);
+                *last = content.to_string();
+                Run::Started { ended_repeats }
+            }
+            None => {
+                self.runs
+                    .insert(file_name.to_string(), (content.to_string(), 0));
+                Run::Started { ended_repeats: 0 }
+            }
+        }
+    }
+
+    // The runs that are still going once there are no more lines, sorted by file name
+    fn finish(&mut self) -> Vec<(String, u64)> {
+        let mut unfinished: Vec<_> = self
+            .runs
+            .drain()
+            .map(|(file_name, (_, repeats))| (file_name, repeats))
+            .filter(|(_, repeats)| *repeats > 0)
+            .collect();
+        unfinished.sort();
+        unfinished
+    }
+}
+
+fn format_repeats(repeats: u64, options: &OutputOptions) -> String {
+    match options.format {
+        OutputFormat::Text => format_note(&format!("... (repeated {} times)", repeats), options),
+        OutputFormat::Json => {
+            let json_repeats = JsonRepeats {
+                repeated: repeats,
+                file: options.file_name,
+            };
+
+            let json = serde_json::to_string(&json_repeats).unwrap(); // Unwrap is safe, since a struct of strings and numbers can always be serialized
+            terminate_line(&json, options.line_ending)
+        }
+    }
+}
+
+// A line of our own, in between the lines of the file
+fn format_note(text: &str, options: &OutputOptions) -> String {
+    let text = if options.color {
+        color::paint(text, color::DIM)
+    } else {
+        text.to_string()
+    };
+    match options.line_ending {
+        LineEnding::Raw => format!("{}\n", text), // There is no line in the file to take the ending from
+        line_ending => terminate_line(&text, line_ending),
+    }
+}
+
+// With --mark-live, where the lines that have been there end, and the lines arriving while following begin
+#[derive(Debug)]
+struct LiveMarker {
+    text: String,
+    printed: bool,
+}
+
+impl LiveMarker {
+    fn new(text: &str) -> Self {
+        Self {
+            text: text.to_string(),
+            printed: false,
+        }
+    }
+
+    // Only once, in front of the first fresh lines. JSON lines would be broken by a line of text
+    fn print(
+        &mut self,
+        out: &mut impl Write,
+        lines: &[Line],
+        options: &OutputOptions,
+    ) -> io::Result<()> {
+        if self.printed
+            || !options.fresh
+            || lines.is_empty()
+            || options.format != OutputFormat::Text
+        {
+            return Ok(());
+        }
+        self.printed = true;
+        out.write_all(format_note(&self.text, options).as_bytes())
+    }
+}
+
+// A run that lasts until the end would otherwise never be told about
+fn print_unfinished_runs(out: &mut impl Write, options: &OutputOptions) -> io::Result<()> {
+    let squeeze = match options.squeeze {
+        Some(squeeze) => squeeze,
+        None => return Ok(()),
+    };
+
+    for (file_name, repeats) in squeeze.borrow_mut().finish() {
+        print_header(out, Some(Path::new(&file_name)), options)?;
+        let options = OutputOptions {
+            file_name: &file_name,
+            ..*options
+        };
+        out.write_all(format_repeats(repeats, &options).as_bytes())?;
+    }
+    Ok(())
+}
+
+// With --tui, every line is a row of the view, and headers take up rows of their own
+fn format_rows(
+    lines: Vec<Line>,
+    reading_direction: ReadingDirection,
+    source: &Path,
+    options: &OutputOptions,
+) -> Vec<String> {
+    let mut rows = vec![];
+    if let Some(header) = next_header(Some(source), options) {
+        rows.extend(header.lines().map(str::to_string));
+    }
+    rows.extend(
+        format_lines(lines, reading_direction, Some(source), options)
+            .iter()
+            .map(|line| strip_line_ending(line).to_string()),
+    );
+    rows
+}
+
+// With --clear, the latest lines are redrawn on a cleared screen whenever something changes, like watch does, instead of scrolling
+struct ScreenWindow {
+    lines: VecDeque<(PathBuf, Instant, String)>, // Already formatted, in the order they appear in the file, with when they arrived
+    capacity: Option<usize>, // No limit with +NUMBER, since everything from that line on is wanted
+    changed: bool,
+    drawn: Option<Instant>,
+}
+
+impl ScreenWindow {
+    fn new(capacity: Option<usize>) -> Self {
+        Self {
+            lines: VecDeque::new(),
+            capacity,
+            changed: true, // Even an empty window should clear the screen once
+            drawn: None,
+        }
+    }
+
+    // Lines are formatted right away, so that they keep their timestamps and colors across redraws
+    fn push(
+        &mut self,
+        lines: Vec<Line>,
+        reading_direction: ReadingDirection,
+        source: &Path,
+        options: &OutputOptions,
+    ) {
+        if lines.is_empty() {
+            return;
+        }
+
+        // Ages are added on every redraw instead, as they keep changing
+        let options = &OutputOptions {
+            age: options.age.filter(|_| options.format != OutputFormat::Text),
+            ..*options
+        };
+        let arrival = Instant::now();
+        for line in format_lines(lines, reading_direction, Some(source), options) {
+            self.lines.push_back((source.to_path_buf(), arrival, line));
+        }
+        if let Some(capacity) = self.capacity {
+            while self.lines.len() > capacity {
+                self.lines.pop_front();
+            }
+        }
+        self.changed = true;
+    }
+
+    fn render(&self, options: &OutputOptions) -> String {
+        // The headers start over on every redraw
+        if let Some(last_source) = options.headers {
+            last_source.replace(None);
+        }
+
+        let mut lines: Vec<_> = self.lines.iter().collect();
+        if options.reverse_output {
+            lines.reverse();
+        }
+
+        let mut screen = color::CLEAR_SCREEN.to_string();
+        for (source, arrival, line) in lines {
+            if let Some(header) = next_header(Some(source), options) {
+                screen.push_str(&header);
+            }
+            if options.age.is_some() && options.format == OutputFormat::Text {
+                screen.push_str(&format!("[{} ago] ", format_age(arrival.elapsed())));
+            }
+            screen.push_str(line);
+        }
+        screen
+    }
+
+    fn redraw(&mut self, out: &mut impl Write, options: &OutputOptions) -> io::Result<()> {
+        let aged = options.age.is_some()
+            && self
+                .drawn
+                .is_some_and(|drawn| drawn.elapsed() >= AGE_REFRESH_INTERVAL);
+        if !std::mem::take(&mut self.changed) && !aged {
+            return Ok(());
+        }
+        self.drawn = Some(Instant::now());
+
+        // Everything is written at once, so that the screen doesn't flicker
+        out.write_all(self.render(options).as_bytes())?;
+        out.flush()
+    }
+}
+
+// Short enough to not push the lines aside much, e.g. "42s", "5m07s" or "3h12m"
+pub fn format_age(age: Duration) -> String {
+    let seconds = age.as_secs();
+    match seconds {
+        0..=59 => format!("{}s", seconds),
+        60..=3599 => format!("{}m{:02}s", seconds / 60, seconds % 60),
+        3600..=86399 => format!("{}h{:02}m", seconds / 3600, seconds % 3600 / 60),
+        _ => format!("{}d{:02}h", seconds / 86400, seconds % 86400 / 3600),
+    }
+}
+
+fn format_line(
+    line_number: u64,
+    line: &str,
+    time: Option<&str>,
+    options: &OutputOptions,
+) -> String {
+    match options.format {
+        OutputFormat::Text => {
+            let age = match options.age {
+                Some(start) => format!("[+{}] ", format_age(start.elapsed())),
+                None => String::new(),
+            };
+            let timestamp = match time {
+                Some(time) => format!("[{}] ", time),
+                None => String::new(),
+            };
+
+            let formatted_number = match options.relative_numbers {
+                Some(relative_numbers) => options.number_format.format_offset(
+                    relative_numbers
+                        .borrow()
+                        .offset(options.file_name, line_number),
+                ),
+                None => options.number_format.format(line_number),
+            };
+            let number_style = if options.fresh {
+                color::GREEN
+            } else {
+                color::DIM
+            };
+            let number = if !options.show_line_numbers {
+                String::new()
+            } else if options.color {
+                format!(
+                    "{}{}",
+                    color::paint(&format!("{}:", formatted_number), number_style),
+                    options.separator
+                )
+            } else {
+                format!("{}:{}", formatted_number, options.separator)
+            };
+
+            let content = match options.highlight.filter(|_| options.color) {
+                Some(regex) => {
+                    // The line ending of the original line is put back, so that terminate_line can keep it if wanted
+                    let content = strip_line_ending(line);
+                    color::highlight(content, regex, color::BOLD_RED) + &line[content.len()..]
+                }
+                None => line.to_string(),
+            };
+
+            if let Some(template) = options.template {
+                let number = if options.color {
+                    color::paint(&formatted_number, number_style)
+                } else {
+                    formatted_number
+                };
+                let age = options.age.map(|start| format_age(start.elapsed()));
+                let fields = TemplateFields {
+                    file: options.file_name,
+                    line: &number,
+                    time: time.unwrap_or_default(),
+                    age: age.as_deref().unwrap_or_default(),
+                    content: &content,
+                };
+                return terminate_line(&template.render(&fields), options.line_ending);
+            }
+
+            let source = if options.tag_sources {
+                format!("{}: ", options.file_name)
+            } else {
+                String::new()
+            };
+
+            format!(
+                "{}{}{}{}{}",
+                source,
+                age,
+                timestamp,
+                number,
+                terminate_line(&content, options.line_ending)
+            )
+        }
+        OutputFormat::Json => {
+            let json_line = JsonLine {
+                line: line_number,
+                content: strip_line_ending(line),
+                file: options.file_name,
+                time,
+                age: options.age.map(|start| start.elapsed().as_secs_f64()),
+            };
+
+            let json = serde_json::to_string(&json_line).unwrap(); // Unwrap is safe, since a struct of strings and numbers can always be serialized
+            terminate_line(&json, options.line_ending)
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum LineEnding {
+    Lf,
+    Crlf,
+    Nul,
+    Keep,
+    Raw, // Exactly as in the file, so an unfinished last line stays unfinished
+}
+
+// Like the text mode of Windows programs, which turns CRLF into LF. Elsewhere, line endings are left alone
+impl Default for LineEnding {
+    fn default() -> Self {
+        if cfg!(windows) {
+            LineEnding::Lf
+        } else {
+            LineEnding::Keep
+        }
+    }
+}
+
+// With --prefix, how every line is laid out. Braces that don't belong to a placeholder are doubled, like in format strings
+#[derive(Debug, Clone, PartialEq)]
+enum TemplatePart {
+    Text(String),
+    File,
+    Line,
+    Time,
+    Age,
+    Content,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Template {
+    parts: Vec<TemplatePart>,
+}
+
+// What the placeholders stand for, for a single line
+struct TemplateFields<'a> {
+    file: &'a str,
+    line: &'a str,
+    time: &'a str,
+    age: &'a str,
+    content: &'a str, // Still with its line ending
+}
+
+impl Template {
+    fn parse(value: &str) -> std::result::Result<Self, String> {
+        let mut parts = vec![];
+        let mut text = String::new();
+        let mut rest = value;
+
+        while let Some(character) = rest.chars().next() {
+            rest = &rest[character.len_utf8()..];
+            match character {
+                '{' if rest.starts_with('{') => {
+                    text.push('{');
+                    rest = &rest[1..];
+                }
+                '}' if rest.starts_with('}') => {
+                    text.push('}');
+                    rest = &rest[1..];
+                }
+                '{' => {
+                    let end = rest.find('}').ok_or_else(|| {
+                        format!(
+                            "A \"{{\" in \"{}\" is never closed. Write \"{{{{\" for a brace",
+                            value
+                        )
+                    })?;
+                    let part = match &rest[..end] {
+                        "file" => TemplatePart::File,
+                        "line" => TemplatePart::Line,
+                        "time" => TemplatePart::Time,
+                        "age" => TemplatePart::Age,
+                        "content" => TemplatePart::Content,
+                        name => {
+                            return Err(format!(
+                                "Unknown placeholder {{{}}}. Known ones are {{file}}, {{line}}, {{time}}, {{age}} and {{content}}",
+                                name
+                            ))
+                        }
+                    };
+                    if !text.is_empty() {
+                        parts.push(TemplatePart::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(part);
+                    rest = &rest[end + 1..];
+                }
+                '}' => {
+                    return Err(format!(
+                        "A \"}}\" in \"{}\" has never been opened. Write \"}}}}\" for a brace",
+                        value
+                    ))
+                }
+                character => text.push(character),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(TemplatePart::Text(text));
+        }
+
+        Ok(Self { parts })
+    }
+
+    fn uses(&self, part: &TemplatePart) -> bool {
+        self.parts.contains(part)
+    }
+
+    // The line ending comes last, even if the content is left out
+    fn render(&self, fields: &TemplateFields) -> String {
+        let content = strip_line_ending(fields.content);
+        let mut rendered = String::new();
+        for part in &self.parts {
+            rendered.push_str(match part {
+                TemplatePart::Text(text) => text,
+                TemplatePart::File => fields.file,
+                TemplatePart::Line => fields.line,
+                TemplatePart::Time => fields.time,
+                TemplatePart::Age => fields.age,
+                TemplatePart::Content => content,
+            });
+        }
+        rendered + &fields.content[content.len()..]
+    }
+}
+
+// Make sure the line ends in exactly one of the wanted terminator. Lines without any terminator (end of file) get one, too,
+// unless the raw lines are wanted
+fn terminate_line(line: &str, line_ending: LineEnding) -> String {
+    let content = strip_line_ending(line);
+
+    match line_ending {
+        LineEnding::Lf => format!("{}\n", content),
+        LineEnding::Crlf => format!("{}\r\n", content),
+        LineEnding::Nul => format!("{}\0", content),
+        LineEnding::Raw => line.to_string(),
+        LineEnding::Keep => {
+            // A lone CR would only return to the beginning of the line, and the next line would end up on top of it
+            if content.len() == line.len() || (line.ends_with('\r') && !line.ends_with("\r\n")) {
+                format!("{}\n", line)
+            } else {
+                line.to_string()
+            }
+        }
+    }
+}
+
+// The file a symlink points to, so that the file itself is watched, instead of the link. Paths that can't be resolved
+// (yet), e.g. because the file doesn't exist, are kept as they are
+fn resolve_symlinks(path: PathBuf) -> PathBuf {
+    path.canonicalize().unwrap_or(path)
+}
+
+// Relative paths are taken relative to the working directory, and "." and ".." are resolved without looking at the file system
+fn absolute_path(path_string: &str) -> std::result::Result<PathBuf, FileError> {
+    if path_string.trim().is_empty() {
+        return Err(FileError::Other(anyhow!("Supplied path is empty!")));
+    }
+
+    Ok(Path::new(path_string)
+        .absolutize()
+        .with_context(|| format!("Unable to turn \"{}\" into absolute path", path_string))?
+        .into())
+}
+
+// The sharing flags of CreateFileW, which tell what others may do with a file while it is open
+#[cfg(any(windows, test))]
+const FILE_SHARE_READ: u32 = 0x1;
+#[cfg(any(windows, test))]
+const FILE_SHARE_WRITE: u32 = 0x2;
+#[cfg(any(windows, test))]
+const FILE_SHARE_DELETE: u32 = 0x4;
+
+// With --open-mode, whether others may write to a file that is open for reading. Only Windows keeps them from doing so
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OpenMode {
+    Shared,
+    Exclusive,
+}
+
+impl OpenMode {
+    #[cfg(any(windows, test))]
+    fn share_mode(self) -> u32 {
+        match self {
+            OpenMode::Shared => FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
+            OpenMode::Exclusive => FILE_SHARE_READ,
+        }
+    }
+
+    fn open(self, path: &Path) -> io::Result<File> {
+        let mut options = OpenOptions::new();
+        options.read(true);
+        #[cfg(windows)]
+        {
+            use std::os::windows::fs::OpenOptionsExt;
+            options.share_mode(self.share_mode());
+        }
+        options.open(path)
+    }
+}
+
+fn validate_path(
+    path_string: &str,
+    follow_symlinks: bool,
+    open_mode: OpenMode,
+) -> std::result::Result<PathBuf, FileError> {
+    let path = absolute_path(path_string)?;
+    let path = if follow_symlinks {
+        resolve_symlinks(path)
+    } else {
+        path
+    };
+
+    if path.is_dir() {
+        return Err(FileError::Directory { path });
+    }
+    // Opening a named pipe waits for a writer, and closing it again right away would leave that writer without a reader
+    if is_fifo(&path) {
+        return Ok(path);
+    }
+
+    let file = open_mode.open(&path);
+    match file {
+        Ok(_) => Ok(path),
+        Err(error) => Err(FileError::Access {
+            path,
+            source: error,
+        }),
+    }
+}
+
+// Where reading starts and stops, for either lines or bytes
+fn count_positions(
+    n: u64,
+    from_start: bool,
+    all_but_last: bool,
+    head: bool,
+) -> (Position, Position, ReadingDirection) {
+    if from_start {
+        // Everything from line (or byte) n until the end. Line 0 is treated like line 1, same as GNU tail does
+        (
+            Position::FromBegin(n.saturating_sub(1)),
+            Position::FromEnd(0),
+            ReadingDirection::TopToBottom,
+        )
+    } else if head {
+        (
+            Position::FromBegin(0),
+            if all_but_last {
+                // The total number of lines has to be known for this, so the whole file is read
+                Position::FromEnd(n)
+            } else {
+                Position::FromBegin(n)
+            },
+            ReadingDirection::TopToBottom,
+        )
+    } else {
+        (
+            Position::FromEnd(0),
+            Position::FromEnd(n),
+            ReadingDirection::BottomToTop,
+        )
+    }
+}
+
+// START:END, both counted from 1 and included. Without START, lines are read from the first one, and without END up to the last
+fn parse_range(value: &str) -> std::result::Result<(Option<u64>, Option<u64>), String> {
+    let (start, end) = value
+        .split_once(':')
+        .ok_or_else(|| format!("\"{}\" should look like START:END, e.g. 100:150", value))?;
+    let parse = |line: &str| -> std::result::Result<Option<u64>, String> {
+        match line.trim() {
+            "" => Ok(None),
+            line => match line.parse::<u64>() {
+                Ok(0) => Err(String::from("Lines are counted from 1")),
+                Ok(line) => Ok(Some(line)),
+                Err(_) => Err(format!("\"{}\" is not a line number", line)),
+            },
+        }
+    };
+    let (start, end) = (parse(start)?, parse(end)?);
+
+    match (start, end) {
+        (Some(start), Some(end)) if start > end => Err(format!(
+            "The range should start before it ends, not at line {} after line {}",
+            start, end
+        )),
+        range => Ok(range),
+    }
+}
+
+fn range_positions(start: Option<u64>, end: Option<u64>) -> (Position, Position, ReadingDirection) {
+    (
+        Position::FromBegin(start.map_or(0, |start| start - 1)),
+        end.map_or(Position::FromEnd(0), Position::FromBegin),
+        ReadingDirection::TopToBottom,
+    )
+}
+
+#[cfg(unix)]
+fn is_fifo(path: &Path) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+
+    std::fs::metadata(path).is_ok_and(|metadata| metadata.file_type().is_fifo())
+}
+
+// Windows has named pipes, too, but they don't live among the files
+#[cfg(not(unix))]
+fn is_fifo(_path: &Path) -> bool {
+    false
+}
+
+// Whether both paths lead to the same file, also if it doesn't exist yet
+fn is_same_path(a: &Path, b: &Path) -> bool {
+    if let (Ok(a), Ok(b)) = (std::fs::metadata(a), std::fs::metadata(b)) {
+        return monitor::is_same_metadata(&a, &b).unwrap_or(false);
+    }
+
+    match (a.absolutize(), b.absolutize()) {
+        (Ok(a), Ok(b)) => resolve_symlinks(a.into_owned()) == resolve_symlinks(b.into_owned()),
+        _ => false,
+    }
+}
+
+// Whether both handles refer to the same file on disk
+fn is_same_file(a: &File, b: &File) -> io::Result<bool> {
+    monitor::is_same_metadata(&a.metadata()?, &b.metadata()?)
+}
+
+// Cut the lines off after the first one matching the expression, in the order they appear in the file. Returns whether there was a match
+fn truncate_after_match(
+    lines: &mut Vec<Line>,
+    regex: Option<&Regex>,
+    reading_direction: ReadingDirection,
+) -> bool {
+    let regex = match regex {
+        Some(regex) => regex,
+        None => return false,
+    };
+    let is_match = |(_, line): &Line| regex.is_match(strip_line_ending(line));
+
+    match reading_direction {
+        ReadingDirection::TopToBottom => match lines.iter().position(is_match) {
+            Some(index) => {
+                lines.truncate(index + 1);
+                true
+            }
+            None => false,
+        },
+        // The lines are in reverse, so the first line in the file is the last one here
+        ReadingDirection::BottomToTop => match lines.iter().rposition(is_match) {
+            Some(index) => {
+                lines.drain(..index);
+                true
+            }
+            None => false,
+        },
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum DropPolicy {
+    DropOldest,
+    Block, // The lines simply stay in the file until there is room for them
+}
+
+// How many seconds worth of lines are held back, before reading pauses or lines are dropped
+const OUTPUT_QUEUE_SECONDS: f64 = 10.0;
+
+struct OutputQueue {
+    lines: VecDeque<(PathBuf, Line)>, // In the order they appear in the files
+    capacity: usize,
+    drop_policy: DropPolicy,
+    lines_per_frame: f64,
+    allowance: f64, // How many lines may be printed right now. Fractions add up over the frames
+    dropped_count: usize,
+}
+
+impl OutputQueue {
+    fn new(lines_per_second: f64, frame_duration: Duration, drop_policy: DropPolicy) -> Self {
+        Self {
+            lines: VecDeque::new(),
+            capacity: (lines_per_second * OUTPUT_QUEUE_SECONDS).ceil() as usize,
+            drop_policy,
+            lines_per_frame: lines_per_second * frame_duration.as_secs_f64(),
+            allowance: 0.0,
+            dropped_count: 0,
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.drop_policy == DropPolicy::Block && self.lines.len() >= self.capacity
+    }
+
+    fn push(&mut self, mut lines: Vec<Line>, reading_direction: ReadingDirection, source: &Path) {
+        if reading_direction == ReadingDirection::BottomToTop {
+            lines.reverse();
+        }
+        self.lines
+            .extend(lines.into_iter().map(|line| (source.to_path_buf(), line)));
+
+        // Blocking only keeps further lines from being read, so the batch that has been read already is kept as a whole
+        if self.drop_policy == DropPolicy::DropOldest {
+            while self.lines.len() > self.capacity {
+                self.lines.pop_front();
+                self.dropped_count += 1;
+            }
+        }
+    }
+
+    // The lines that may be printed this frame. A slow trickle of lines is not saved up for a burst later on
+    fn next_frame(&mut self) -> Vec<(PathBuf, Line)> {
+        self.allowance = (self.allowance + self.lines_per_frame).min(self.lines_per_frame.max(1.0));
+        let count = ((self.allowance + 1e-9) as usize).min(self.lines.len()); // Sixths and the like don't add up to exactly 1
+        self.allowance -= count as f64;
+        self.lines.drain(..count).collect()
+    }
+
+    fn take_all(&mut self) -> Vec<(PathBuf, Line)> {
+        self.lines.drain(..).collect()
+    }
+}
+
+// With --merge, lines of all files are put in the order of their timestamps. While following, new lines are held back for a
+// while, in case a line with an earlier timestamp still comes in from another file
+struct MergeBuffer {
+    timestamps: TimeFilter, // Only for reading timestamps, nothing is filtered
+    window: Duration,
+    lines: VecDeque<MergedLine>, // Ordered by time, and by arrival for the same time
+    last_times: HashMap<PathBuf, NaiveDateTime>, // Of every file, for the lines without a timestamp
+}
+
+struct MergedLine {
+    time: Option<NaiveDateTime>, // Lines before the first timestamp of their file come first
+    arrival: Instant,
+    source: PathBuf,
+    line: Line,
+}
+
+impl MergeBuffer {
+    fn new(timestamps: TimeFilter, window: Duration) -> Self {
+        Self {
+            timestamps,
+            window,
+            lines: VecDeque::new(),
+            last_times: HashMap::new(),
+        }
+    }
+
+    fn push(
+        &mut self,
+        mut lines: Vec<Line>,
+        reading_direction: ReadingDirection,
+        source: &Path,
+        arrival: Instant,
+    ) {
+        if reading_direction == ReadingDirection::BottomToTop {
+            lines.reverse();
+        }
+
+        for line in lines {
+            let time = match self.timestamps.timestamp(strip_line_ending(&line.1)) {
+                Some(time) => {
+                    self.last_times.insert(source.to_path_buf(), time);
+                    Some(time)
+                }
+                None => self.last_times.get(source).copied(),
+            };
+            let index = self.lines.partition_point(|merged| merged.time <= time);
+            self.lines.insert(
+                index,
+                MergedLine {
+                    time,
+                    arrival,
+                    source: source.to_path_buf(),
+                    line,
+                },
+            );
+        }
+    }
+
+    // The first lines, as long as they have waited out the window. A line that has only just come in holds back the later ones
+    fn take_ready(&mut self, now: Instant) -> Vec<(PathBuf, Line)> {
+        let mut ready = vec![];
+        while let Some(merged) = self.lines.front() {
+            if now.saturating_duration_since(merged.arrival) < self.window {
+                break;
+            }
+            let merged = self.lines.pop_front().unwrap(); // Unwrap is safe, since there is a first line
+            ready.push((merged.source, merged.line));
+        }
+        ready
+    }
+
+    fn take_all(&mut self) -> Vec<(PathBuf, Line)> {
+        self.lines
+            .drain(..)
+            .map(|merged| (merged.source, merged.line))
+            .collect()
+    }
+}
+
+// Running totals of what has been printed, for --stats. Bytes are counted as read, before formatting
+#[derive(Debug, Default)]
+struct Stats {
+    lines: Cell<u64>,
+    bytes: Cell<u64>,
+}
+
+impl Stats {
+    fn count_lines(&self, lines: &[Line]) {
+        self.lines.set(self.lines.get() + lines.len() as u64);
+        self.bytes
+            .set(self.bytes.get() + lines.iter().map(|(_, line)| line.len() as u64).sum::<u64>());
+    }
+
+    fn count_bytes(&self, bytes: &[u8]) {
+        self.lines
+            .set(self.lines.get() + bytes.iter().filter(|&&byte| byte == b'\n').count() as u64);
+        self.bytes.set(self.bytes.get() + bytes.len() as u64);
+    }
+
+    fn summary(&self, elapsed: Duration) -> String {
+        format!(
+            "printed {} lines ({} bytes) in {:.3}s",
+            self.lines.get(),
+            self.bytes.get(),
+            elapsed.as_secs_f64()
+        )
+    }
+}
+
+// With --stats-interval, how much has been printed lately, now and then while following
+#[derive(Debug)]
+struct StatsInterval {
+    interval: Duration,
+    last_report: Duration, // Since the clock started
+    lines: u64,            // The totals back then
+    bytes: u64,
+}
+
+impl StatsInterval {
+    fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last_report: Duration::ZERO,
+            lines: 0,
+            bytes: 0,
+        }
+    }
+
+    // Nothing until the interval has passed since the last report
+    fn report(&mut self, elapsed: Duration, stats: &Stats) -> Option<String> {
+        let passed = elapsed.saturating_sub(self.last_report);
+        if passed < self.interval {
+            return None;
+        }
+
+        let (lines, bytes) = (stats.lines.get(), stats.bytes.get());
+        let seconds = passed.as_secs_f64();
+        let report = format!(
+            "{:.1} lines/s, {:.1} bytes/s, {} lines in total, running for {:.0}s",
+            (lines - self.lines) as f64 / seconds,
+            (bytes - self.bytes) as f64 / seconds,
+            lines,
+            elapsed.as_secs_f64()
+        );
+        self.last_report = elapsed;
+        self.lines = lines;
+        self.bytes = bytes;
+        Some(report)
+    }
+
+    // How long until the next report
+    fn remaining(&self, elapsed: Duration) -> Duration {
+        (self.last_report + self.interval).saturating_sub(elapsed)
+    }
+}
+
+// The options as they have been resolved from the arguments, for --print-config
+#[derive(Debug)]
+struct Config<'a> {
+    file_names: &'a [&'a str],
+    count: u64,
+    unit: &'static str, // Lines or bytes
+    start_position: Position,
+    stop_position: Position,
+    reading_direction: ReadingDirection,
+    frame_duration: Duration,
+    notification_delay: Duration,
+    follow: &'static str, // No, by descriptor or by name
+    retry: bool,
+    format: OutputFormat,
+    line_format: LineFormat,
+}
+
+impl std::fmt::Display for Config<'_> {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(formatter, "files: {:?}", self.file_names)?;
+        writeln!(formatter, "n: {} {}", self.count, self.unit)?;
+        writeln!(formatter, "start: {:?}", self.start_position)?;
+        writeln!(formatter, "stop: {:?}", self.stop_position)?;
+        writeln!(formatter, "direction: {:?}", self.reading_direction)?;
+        writeln!(
+            formatter,
+            "rate: {:.3} Hz ({:?} per frame)",
+            1.0 / self.frame_duration.as_secs_f64(),
+            self.frame_duration
+        )?;
+        writeln!(formatter, "delay: {:?}", self.notification_delay)?;
+        writeln!(formatter, "follow: {}", self.follow)?;
+        writeln!(formatter, "retry: {}", self.retry)?;
+        writeln!(formatter, "format: {:?}", self.format)?;
+        writeln!(
+            formatter,
+            "delimiter: {:?}",
+            char::from(self.line_format.delimiter)
+        )?;
+        writeln!(formatter, "encoding: {}", self.line_format.encoding.name())
+    }
+}
+
+// Every pattern has to match at least one file. Directories are skipped, since they can't be tailed anyway
+fn expand_globs(patterns: &[&str]) -> Result<Vec<String>> {
+    let mut file_names = vec![];
+
+    for &pattern in patterns {
+        if pattern == "-" {
+            file_names.push(pattern.to_string());
+            continue;
+        }
+
+        let mut paths = vec![];
+        for path in glob::glob(pattern).context(format!("Invalid pattern \"{}\"", pattern))? {
+            let path = path.context(format!("Unable to expand \"{}\"", pattern))?;
+            if !path.is_dir() {
+                paths.push(path.to_string_lossy().into_owned());
+            }
+        }
+
+        if paths.is_empty() {
+            return Err(anyhow!("No files matched \"{}\"", pattern));
+        }
+        file_names.append(&mut paths); // Already sorted alphabetically
+    }
+
+    Ok(file_names)
+}
+
+// Files right in the directory, not in subdirectories of it, and only if their name matches the pattern
+fn is_wanted_new_file(path: &Path, directory: &Path, pattern: Option<&glob::Pattern>) -> bool {
+    path.parent() == Some(directory)
+        && path.is_file()
+        && pattern.is_none_or(|pattern| {
+            path.file_name()
+                .is_some_and(|name| pattern.matches(&name.to_string_lossy()))
+        })
+}
+
+fn has_expired(clock: Instant, timeout: Option<Duration>) -> bool {
+    timeout.is_some_and(|timeout| clock.elapsed() >= timeout)
+}
+
+// With --heartbeat, a sign of life after a while without anything new
+#[derive(Debug)]
+struct Heartbeat {
+    interval: Duration,
+    last_activity: Duration, // Since the clock started, when something has last been printed, or the last heartbeat
+    printed: u64,            // How much had been printed back then
+}
+
+impl Heartbeat {
+    fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last_activity: Duration::ZERO,
+            printed: 0,
+        }
+    }
+
+    fn is_due(&mut self, elapsed: Duration, printed: u64) -> bool {
+        if printed != self.printed {
+            self.printed = printed;
+            self.last_activity = elapsed;
+            return false;
+        }
+        if elapsed.saturating_sub(self.last_activity) < self.interval {
+            return false;
+        }
+        self.last_activity = elapsed;
+        true
+    }
+
+    // How long until the next heartbeat, if nothing is printed in between
+    fn remaining(&self, elapsed: Duration) -> Duration {
+        (self.last_activity + self.interval).saturating_sub(elapsed)
+    }
+}
+
+// After waiting for a wakeup, frames continue from now on, instead of being made up for
+fn skip_missed_frames(clock: Instant, count: &mut u128, frame_duration: Duration) {
+    *count = clock.elapsed().as_nanos() / frame_duration.as_nanos().max(1);
+}
+
+fn sleep_remaining_frame(clock: Instant, count: &mut u128, frame_duration: Duration) {
+    *count += 1;
+
+    // Frames that took too long are made up for by not sleeping until the next one is due, so the cadence holds on average
+    let next_frame = frame_duration.as_nanos() * *count;
+    let elapsed = clock.elapsed().as_nanos();
+
+    if next_frame > elapsed {
+        thread::sleep(Duration::from_nanos((next_frame - elapsed) as u64));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Read;
+
+    use monitor::{FileMonitor, HotwatchMonitor};
+    use tail::{
+        follow_byte_stream, follow_stream, read_bytes, read_lines, read_lines_seekable,
+        read_matching_lines, read_new_lines, ReadCursor,
+    };
+
+    fn to_lines(lines: &[(u64, &str)]) -> Vec<Line> {
+        lines
+            .iter()
+            .map(|(number, line)| (*number, line.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_watch_notices_replaced_file() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let path = directory.path().join("replaced.log");
+        std::fs::write(&path, "old\n")?;
+
+        let mut file_monitor = HotwatchMonitor::new(Duration::from_millis(10), None, None)?;
+        file_monitor.watch(&path)?;
+
+        std::fs::rename(&path, directory.path().join("replaced.log.1"))?;
+        std::fs::write(&path, "new\n")?;
+
+        let clock = Instant::now();
+        while !file_monitor.is_replaced() && clock.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(file_monitor.is_replaced());
+
+        // Following by name continues with the new file
+        let mut file = OpenOptions::new().read(true).open(&path)?;
+        let lines = read_lines_seekable(
+            &mut file,
+            Position::FromEnd(0),
+            Position::FromEnd(10),
+            ReadingDirection::BottomToTop,
+            LineFormat::default(),
+        )?;
+        assert_eq!(lines, to_lines(&[(1, "new\n")]));
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_fifo_streams_lines() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let path = directory.path().join("pipe");
+        let c_path = std::ffi::CString::new(path.to_str().unwrap())?;
+        assert_eq!(unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) }, 0);
+
+        // Checking the path must not wait for a writer
+        assert!(is_fifo(&path));
+        assert_eq!(
+            validate_path(path.to_str().unwrap(), true, OpenMode::Shared)?,
+            path
+        );
+        assert!(!is_fifo(directory.path()));
+
+        // The second line is only written once the first one has come through, so nothing waits for the end of the pipe
+        let (received_sender, received_receiver) = crossbeam_channel::bounded::<()>(1);
+        let writer = {
+            let path = path.clone();
+            thread::spawn(move || -> io::Result<()> {
+                let mut fifo = OpenOptions::new().write(true).open(path)?;
+                fifo.write_all(b"first\n")?;
+                received_receiver
+                    .recv_timeout(Duration::from_secs(5))
+                    .map_err(|error| io::Error::new(io::ErrorKind::TimedOut, error))?;
+                fifo.write_all(b"second\n")
+            })
+        };
+
+        let interrupted = AtomicBool::new(false);
+        let mut received = vec![];
+        let line_count = follow_stream(
+            File::open(&path)?,
+            LineFormat::default(),
+            &interrupted,
+            |lines| {
+                received.extend(lines);
+                let _ = received_sender.try_send(());
+                Ok(())
+            },
+        )?;
+        writer.join().unwrap()?;
+
+        // The stream ends once the writer has closed the pipe
+        assert_eq!(line_count, 2);
+        assert_eq!(received, to_lines(&[(1, "first\n"), (2, "second\n")]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_byte_offsets() -> Result<()> {
+        // Multi-byte characters are cut wherever the offset falls, since bytes aren't decoded
+        let data = "0123456789abcdefghijklmnopqrstü".as_bytes();
+        let read = |n, from_start, all_but_last, head| {
+            let (start, stop, direction) = count_positions(n, from_start, all_but_last, head);
+            read_bytes(io::Cursor::new(data), start, stop, direction)
+        };
+
+        // -c +10
+        assert_eq!(read(10, true, false, false)?, &data[9..]);
+        assert_eq!(read(0, true, false, false)?, data);
+        // --head -c -10
+        assert_eq!(read(10, false, true, true)?, &data[..data.len() - 10]);
+        assert_eq!(read(100, false, true, true)?, b"");
+        // -c -10 without --head, like -c 10
+        assert_eq!(read(10, false, true, false)?, &data[data.len() - 10..]);
+        assert_eq!(read(1, false, false, false)?, b"\xbc");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_range() -> Result<()> {
+        let data = (1..=20)
+            .map(|number| format!("{}\n", number))
+            .collect::<String>();
+        let read = |range: &str| -> Result<Vec<u64>> {
+            let (start, end) = parse_range(range).map_err(|error| anyhow!(error))?;
+            let (start, stop, direction) = range_positions(start, end);
+            let lines = read_lines(
+                data.as_bytes(),
+                start,
+                stop,
+                direction,
+                LineFormat::default(),
+            )?;
+            Ok(lines.into_iter().map(|(number, _)| number).collect())
+        };
+
+        assert_eq!(read("5:8")?, [5, 6, 7, 8]);
+        assert_eq!(read("7:7")?, [7]);
+        assert_eq!(read("18:")?, [18, 19, 20]);
+        assert_eq!(read(":3")?, [1, 2, 3]);
+        assert_eq!(read(":")?.len(), 20);
+        assert_eq!(read("19:100")?, [19, 20]);
+
+        for invalid in ["8:5", "0:5", "5", "a:b", "-3:5"] {
+            assert!(
+                parse_range(invalid).is_err(),
+                "{} should be invalid",
+                invalid
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_absolute_path() -> Result<()> {
+        let working_directory = std::env::current_dir()?;
+        let parent_directory = working_directory.parent().unwrap(); // Unwrap is okay, since tests don't run from the root directory
+
+        let mut cases = vec![
+            ("foo.log", working_directory.join("foo.log")),
+            ("./foo.log", working_directory.join("foo.log")),
+            ("../foo.log", parent_directory.join("foo.log")),
+            ("logs/../foo.log", working_directory.join("foo.log")),
+        ];
+        if cfg!(windows) {
+            cases.extend([
+                (r"C:\foo\bar.log", PathBuf::from(r"C:\foo\bar.log")),
+                (r"..\foo.log", parent_directory.join("foo.log")),
+                (
+                    r".\logs/foo.log",
+                    working_directory.join("logs").join("foo.log"),
+                ),
+            ]);
+        } else {
+            cases.extend([
+                ("/abs/foo.log", PathBuf::from("/abs/foo.log")),
+                ("/abs/./logs/../foo.log", PathBuf::from("/abs/foo.log")),
+                // Backslashes are part of the file name here
+                (r"C:\foo\bar.log", working_directory.join(r"C:\foo\bar.log")),
+            ]);
+        }
+
+        for (path, expected) in cases {
+            assert_eq!(absolute_path(path)?, expected, "{}", path);
+        }
+        assert!(absolute_path(" ").is_err());
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_validate_path_resolves_symlinks() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let directory_path = directory.path().canonicalize()?;
+        let target = directory_path.join("app-1.log");
+        let link = directory_path.join("app.log");
+        std::fs::write(&target, "first\nsecond\n")?;
+        std::os::unix::fs::symlink(&target, &link)?;
+
+        // The file itself is what gets watched
+        let path = validate_path(link.to_str().unwrap(), true, OpenMode::Shared)?;
+        assert_eq!(path, target);
+        let lines = read_lines_seekable(
+            OpenOptions::new().read(true).open(&path)?,
+            Position::FromEnd(0),
+            Position::FromEnd(10),
+            ReadingDirection::BottomToTop,
+            LineFormat::default(),
+        )?;
+        assert_eq!(lines, to_lines(&[(2, "second\n"), (1, "first\n")]));
+
+        // Unless asked not to. Reading through the link still gets to the content
+        let path = validate_path(link.to_str().unwrap(), false, OpenMode::Shared)?;
+        assert_eq!(path, link);
+        let lines = read_lines_seekable(
+            OpenOptions::new().read(true).open(&path)?,
+            Position::FromEnd(0),
+            Position::FromEnd(1),
+            ReadingDirection::BottomToTop,
+            LineFormat::default(),
+        )?;
+        assert_eq!(lines, to_lines(&[(2, "second\n")]));
+
+        // Rotation points the link at a new file, which is what resolving it again finds
+        let rotated = directory_path.join("app-2.log");
+        std::fs::write(&rotated, "")?;
+        std::fs::remove_file(&link)?;
+        std::os::unix::fs::symlink(&rotated, &link)?;
+        assert_eq!(resolve_symlinks(link.clone()), rotated);
+
+        // Dangling links are kept, so that they can be waited for
+        std::fs::remove_file(&rotated)?;
+        assert_eq!(resolve_symlinks(link.clone()), link);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_mode() -> Result<()> {
+        assert_eq!(OpenMode::Shared.share_mode(), 0x7);
+        assert_eq!(OpenMode::Exclusive.share_mode(), FILE_SHARE_READ);
+
+        let directory = tempfile::tempdir()?;
+        let path = directory.path().join("app.log");
+        let mut writer = File::create(&path)?;
+        writer.write_all(b"written while open\n")?;
+
+        let mut content = String::new();
+        OpenMode::Shared.open(&path)?.read_to_string(&mut content)?;
+        assert_eq!(content, "written while open\n");
+
+        // Somebody is writing to it, which only Windows keeps from happening while it's open exclusively. Elsewhere, the mode
+        // doesn't change a thing
+        #[cfg(windows)]
+        assert!(OpenMode::Exclusive.open(&path).is_err());
+        #[cfg(not(windows))]
+        assert!(OpenMode::Exclusive.open(&path).is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_wanted_new_file() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let log = directory.path().join("app-2021-02-03.log");
+        let other = directory.path().join("app.pid");
+        let nested = directory.path().join("archive");
+        std::fs::write(&log, "")?;
+        std::fs::write(&other, "")?;
+        std::fs::create_dir(&nested)?;
+        std::fs::write(nested.join("app-2021-02-02.log"), "")?;
+
+        let pattern = glob::Pattern::new("*.log")?;
+        assert!(is_wanted_new_file(&log, directory.path(), Some(&pattern)));
+        assert!(!is_wanted_new_file(
+            &other,
+            directory.path(),
+            Some(&pattern)
+        ));
+        assert!(is_wanted_new_file(&other, directory.path(), None));
+        assert!(!is_wanted_new_file(&nested, directory.path(), None));
+        assert!(!is_wanted_new_file(
+            &nested.join("app-2021-02-02.log"),
+            directory.path(),
+            Some(&pattern)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_directory_monitor_notices_new_file() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        // Hotwatch reports paths with symbolic links resolved, e.g. on macOS
+        let directory_path = std::fs::canonicalize(directory.path())?;
+        let mut directory_monitor = monitor::monitor_directory(
+            &directory_path,
+            false,
+            Duration::from_millis(10),
+            None,
+            None,
+        )?;
+
+        // Created and written in one go, before the file could even be followed
+        let path = directory_path.join("new.log");
+        std::fs::write(&path, "first\nsecond\n")?;
+
+        let mut created = vec![];
+        for _ in 0..200 {
+            created.extend(directory_monitor.take_created());
+            if !created.is_empty() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(created, std::slice::from_ref(&path));
+
+        // Following it from the beginning still gets what has been written along with creating it
+        let mut file = OpenOptions::new().read(true).open(&path)?;
+        let mut cursor = ReadCursor::default();
+        let mut line_numbering = LineNumbering::new(None, OverflowPolicy::Saturate);
+        let lines = read_new_lines(
+            &mut file,
+            &mut cursor,
+            ReadingDirection::TopToBottom,
+            &mut line_numbering,
+            None,
+            LineFormat::default(),
+        )?;
+        assert_eq!(lines, to_lines(&[(1, "first\n"), (2, "second\n")]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_expand_globs() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        for name in ["b.log", "a.log", "c.txt"] {
+            std::fs::write(directory.path().join(name), "")?;
+        }
+        std::fs::create_dir(directory.path().join("old.log"))?;
+        let in_directory = |name: &str| directory.path().join(name).to_string_lossy().into_owned();
+
+        assert_eq!(
+            expand_globs(&[&in_directory("*.log"), "-", &in_directory("c.txt")])?,
+            [
+                in_directory("a.log"),
+                in_directory("b.log"),
+                "-".to_string(),
+                in_directory("c.txt")
+            ]
+        );
+
+        let error = expand_globs(&[&in_directory("*.gz")]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!("No files matched \"{}\"", in_directory("*.gz"))
+        );
+        assert!(expand_globs(&["[unclosed"]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_truncate_after_match() {
+        let until = Regex::new("DEPLOY COMPLETE").unwrap();
+
+        let mut lines = to_lines(&[(1, "copying\n"), (2, "cleaning up\n")]);
+        assert!(!truncate_after_match(
+            &mut lines,
+            Some(&until),
+            ReadingDirection::TopToBottom
+        ));
+        assert_eq!(lines.len(), 2);
+
+        let mut lines = to_lines(&[
+            (1, "copying\n"),
+            (2, "DEPLOY COMPLETE\n"),
+            (3, "cleaning up\n"),
+            (4, "DEPLOY COMPLETE\n"),
+        ]);
+        assert!(truncate_after_match(
+            &mut lines,
+            Some(&until),
+            ReadingDirection::TopToBottom
+        ));
+        assert_eq!(
+            lines,
+            to_lines(&[(1, "copying\n"), (2, "DEPLOY COMPLETE\n")])
+        );
+
+        // Newest first, but the first match in the file still wins
+        let mut lines = to_lines(&[
+            (4, "DEPLOY COMPLETE\n"),
+            (3, "cleaning up\n"),
+            (2, "DEPLOY COMPLETE\n"),
+            (1, "copying\n"),
+        ]);
+        assert!(truncate_after_match(
+            &mut lines,
+            Some(&until),
+            ReadingDirection::BottomToTop
+        ));
+        assert_eq!(
+            lines,
+            to_lines(&[(2, "DEPLOY COMPLETE\n"), (1, "copying\n")])
+        );
+
+        assert!(!truncate_after_match(
+            &mut lines,
+            None,
+            ReadingDirection::TopToBottom
+        ));
+    }
+
+    #[test]
+    fn test_merge_buffer() {
+        let timestamps = TimeFilter {
+            since: NaiveDate::from_ymd_opt(2024, 1, 1)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap(),
+            format: "%b %d %H:%M:%S".to_string(),
+        };
+        let window = Duration::from_millis(100);
+        let start = Instant::now();
+        let mut merge = MergeBuffer::new(timestamps, window);
+        let (api, db) = (Path::new("api.log"), Path::new("db.log"));
+
+        merge.push(
+            to_lines(&[
+                (1, "Jan 05 10:00:01 request\n"),
+                (2, "Jan 05 10:00:04 error\n"),
+                (3, "  at handler\n"),
+                (4, "Jan 05 10:00:05 done\n"),
+            ]),
+            ReadingDirection::TopToBottom,
+            api,
+            start,
+        );
+        merge.push(
+            to_lines(&[
+                (5, "Jan 05 10:00:04 deadlock\n"),
+                (4, "Jan 05 10:00:02 query\n"),
+            ]),
+            ReadingDirection::BottomToTop,
+            db,
+            start,
+        );
+        let merged = |lines: Vec<(PathBuf, Line)>| -> Vec<(String, u64)> {
+            lines
+                .into_iter()
+                .map(|(source, (number, _))| (source.to_string_lossy().into_owned(), number))
+                .collect()
+        };
+
+        // Nothing is ready before the window has passed
+        assert!(merge.take_ready(start).is_empty());
+        // The stack trace stays with its line, and the same time keeps the order of arrival
+        assert_eq!(
+            merged(merge.take_ready(start + window)),
+            [
+                ("api.log".to_string(), 1),
+                ("db.log".to_string(), 4),
+                ("api.log".to_string(), 2),
+                ("api.log".to_string(), 3),
+                ("db.log".to_string(), 5),
+                ("api.log".to_string(), 4),
+            ]
+        );
+
+        // A late line from another file still goes first, as long as it comes in within the window
+        let later = start + Duration::from_secs(1);
+        merge.push(
+            to_lines(&[(6, "Jan 05 10:00:07 next\n")]),
+            ReadingDirection::TopToBottom,
+            api,
+            later,
+        );
+        merge.push(
+            to_lines(&[(6, "Jan 05 10:00:06 slow\n")]),
+            ReadingDirection::TopToBottom,
+            db,
+            later + window / 2,
+        );
+        assert!(merge.take_ready(later + window).is_empty());
+        assert_eq!(
+            merged(merge.take_all()),
+            [("db.log".to_string(), 6), ("api.log".to_string(), 6)]
+        );
+    }
 
     #[test]
-    fn test_read_lines() -> Result<()> {
-        let file = r"In Hamburg lebten zwei Ameisen,
-        Die wollten nach Australien reisen.
-        Bei Altona auf der Chaussee
-        Da taten ihnen die Beine weh,
-        Und da verzichteten sie weise
-        Denn auf den letzten Teil der Reise.
-        
-        So will man oft und kann doch nicht
-        Und leistet dann recht gern Verzicht."
-            .to_string();
-
-        let data = file.clone();
-        let (a, b) = (0, 7);
-        let (start, stop) = (Position::FromBegin(a), Position::FromBegin(b));
-        let direction = ReadingDirection::TopToBottom;
-        let lines = read_lines(data.as_bytes(), start, stop, direction)?;
-        let expected: Vec<Line> = (a..b)
-            .map(|i| (i + 1, data.lines().nth(i).unwrap().to_string() + "\n"))
+    fn test_output_queue() {
+        let source = Path::new("burst.log");
+        let sixtieth = Duration::from_secs_f64(1.0 / 60.0);
+        let burst: Vec<Line> = (1..=1000)
+            .map(|number| (number, format!("{}\n", number)))
             .collect();
 
-        assert_eq!(lines, expected);
+        // 120 lines per second at 60 frames per second makes 2 lines per frame
+        let mut output_queue = OutputQueue::new(120.0, sixtieth, DropPolicy::DropOldest);
+        output_queue.push(burst.clone(), ReadingDirection::TopToBottom, source);
+        assert_eq!(output_queue.dropped_count, 0);
+        for frame in 0..100 {
+            let lines = output_queue.next_frame();
+            assert_eq!(lines.len(), 2);
+            assert_eq!(lines[0].1 .0, 2 * frame + 1);
+        }
+
+        // The queue holds 10 seconds worth of lines, so the oldest ones go
+        output_queue.push(burst.clone(), ReadingDirection::TopToBottom, source);
+        assert_eq!(output_queue.dropped_count, 800 + 1000 - 1200);
+        assert_eq!(output_queue.take_all().len(), 1200);
+        assert!(output_queue.next_frame().is_empty());
+
+        // Fractions of lines add up, but aren't saved up
+        let mut output_queue = OutputQueue::new(30.0, sixtieth, DropPolicy::DropOldest);
+        for _ in 0..10 {
+            assert!(output_queue.next_frame().is_empty());
+        }
+        output_queue.push(burst.clone(), ReadingDirection::BottomToTop, source);
+        let counts: Vec<_> = (0..4).map(|_| output_queue.next_frame().len()).collect();
+        assert_eq!(counts, [1, 0, 1, 0]);
+
+        let mut output_queue = OutputQueue::new(10.0, sixtieth, DropPolicy::Block);
+        assert!(!output_queue.is_full());
+        output_queue.push(burst, ReadingDirection::BottomToTop, source);
+        assert!(output_queue.is_full());
+        assert_eq!(output_queue.dropped_count, 0);
+        let lines: Vec<_> = (0..6).flat_map(|_| output_queue.next_frame()).collect();
+        assert_eq!(
+            lines,
+            [(source.to_path_buf(), (1000, "1000\n".to_string()))]
+        );
+    }
+
+    #[test]
+    fn test_examples() {
+        for (_, command) in EXAMPLES {
+            // Whatever feeds into tail is left to the shell. Quotes only ever wrap whole words here
+            let command = command.rsplit("| ").next().unwrap();
+            let mut arguments = vec![];
+            let mut quoted: Option<String> = None;
+            for word in command.split(' ') {
+                match quoted.as_mut() {
+                    Some(words) => {
+                        words.push(' ');
+                        words.push_str(word.trim_end_matches('\''));
+                        if word.ends_with('\'') {
+                            arguments.push(quoted.take().unwrap());
+                        }
+                    }
+                    None if word.starts_with('\'') && !word[1..].ends_with('\'') => {
+                        quoted = Some(word[1..].to_string())
+                    }
+                    None => arguments.push(word.trim_matches('\'').to_string()),
+                }
+            }
+            assert!(quoted.is_none(), "Unclosed quote in {:?}", command);
+            assert_eq!(arguments[0], "tail");
+
+            if let Err(error) = app().get_matches_from_safe(&arguments) {
+                panic!("{:?} doesn't work anymore: {}", command, error.message);
+            }
+        }
+    }
+
+    #[test]
+    fn test_completions() {
+        let mut script = vec![];
+        app().gen_completions_to("tail", Shell::Bash, &mut script);
+        let script = String::from_utf8(script).unwrap();
+
+        for flag in ["--follow", "--grep", "--max-lines-per-sec", "--config"] {
+            assert!(script.contains(flag), "{} is missing", flag);
+        }
+    }
+
+    #[test]
+    fn test_heartbeat() {
+        let mut heartbeat = Heartbeat::new(Duration::from_secs(1));
+        let frame = Duration::from_millis(100);
+
+        // A quiet second and a half, checked every frame, makes one heartbeat
+        let beats = (0..=15)
+            .filter(|&frame_count| heartbeat.is_due(frame * frame_count, 0))
+            .count();
+        assert_eq!(beats, 1);
+        assert_eq!(heartbeat.remaining(frame * 15), frame * 5);
+
+        // Something being printed starts the wait over
+        assert!(!heartbeat.is_due(frame * 19, 42));
+        assert!(!heartbeat.is_due(frame * 25, 42));
+        assert!(heartbeat.is_due(frame * 29, 42));
+    }
+
+    #[test]
+    fn test_timeout() {
+        let clock = Instant::now();
+        let mut refresh_count = 0;
+        let timeout = Some(Duration::from_secs(1));
+
+        assert!(!has_expired(clock, None));
+        while !has_expired(clock, timeout) {
+            sleep_remaining_frame(
+                clock,
+                &mut refresh_count,
+                Duration::from_secs_f64(1.0 / 60.0),
+            );
+        }
+
+        // Only a frame or so late
+        assert!(clock.elapsed() < Duration::from_millis(1500));
+        assert!(!has_expired(clock, None));
+    }
+
+    #[test]
+    fn test_sleep_remaining_frame() {
+        let clock = Instant::now();
+        let mut refresh_count = 0;
+
+        for _ in 0..4 {
+            sleep_remaining_frame(clock, &mut refresh_count, Duration::from_millis(50));
+        }
+        assert!(clock.elapsed() >= Duration::from_millis(200));
+
+        // A slow frame is made up for by the next ones
+        thread::sleep(Duration::from_millis(120));
+        for _ in 0..2 {
+            sleep_remaining_frame(clock, &mut refresh_count, Duration::from_millis(50));
+        }
+        assert!(clock.elapsed() < Duration::from_millis(400));
+    }
+
+    // Like stdout, after whoever reads from the pipe has gone away
+    struct ClosedPipe;
+
+    impl Write for ClosedPipe {
+        fn write(&mut self, _buffer: &[u8]) -> io::Result<usize> {
+            Err(io::Error::from(io::ErrorKind::BrokenPipe))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_relative_numbers() -> Result<()> {
+        let lines = || to_lines(&[(8, "eight\n"), (9, "nine\n"), (10, "ten\n")]);
+        let print = |options: &OutputOptions| -> Result<String> {
+            let mut out = vec![];
+            print_lines(
+                &mut out,
+                lines(),
+                ReadingDirection::TopToBottom,
+                Some(Path::new("app.log")),
+                options,
+            )?;
+            Ok(String::from_utf8(out)?)
+        };
+
+        // Absolute numbers by default
+        assert_eq!(print(&text_options())?, "8:\teight\n9:\tnine\n10:\tten\n");
+
+        // Counted back from the end of the first lines
+        let relative_numbers = RefCell::new(RelativeNumbers::default());
+        relative_numbers
+            .borrow_mut()
+            .set_end("app.log", &lines(), None);
+        let options = OutputOptions {
+            relative_numbers: Some(&relative_numbers),
+            extraction: None,
+            ..text_options()
+        };
+        assert_eq!(print(&options)?, "-3:\teight\n-2:\tnine\n-1:\tten\n");
+
+        // Lines that come in later continue from there
+        assert_eq!(
+            format_line(
+                11,
+                "eleven\n",
+                None,
+                &OutputOptions {
+                    file_name: "app.log",
+                    ..options
+                }
+            ),
+            "0:\televen\n"
+        );
+        assert_eq!(
+            format_line(
+                13,
+                "thirteen\n",
+                None,
+                &OutputOptions {
+                    file_name: "app.log",
+                    number_format: NumberFormat::parse("03").unwrap(),
+                    ..options
+                }
+            ),
+            "+02:\tthirteen\n"
+        );
+
+        // Without first lines, the end is where reading stopped, and a new file is new from the start
+        relative_numbers
+            .borrow_mut()
+            .set_end("empty.log", &[], Some(&(4, "four\n".to_string())));
+        assert_eq!(relative_numbers.borrow().offset("empty.log", 5), 0);
+        assert_eq!(relative_numbers.borrow().offset("new.log", 1), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_closed_pipe() -> Result<()> {
+        let options = OutputOptions {
+            reverse_output: false,
+            line_ending: LineEnding::Keep,
+            timestamp_format: None,
+            age: None,
+            format: OutputFormat::Text,
+            file_name: "-",
+            tag_sources: false,
+            color: false,
+            highlight: None,
+            fresh: false,
+            show_line_numbers: true,
+            separator: "\t",
+            number_format: NumberFormat::default(),
+            headers: None,
+            squeeze: None,
+            groups: None,
+            relative_numbers: None,
+            extraction: None,
+            template: None,
+        };
+        let lines = || to_lines(&[(1, "first\n"), (2, "second\n")]);
+
+        // Without line buffering, nothing is written until the end
+        let mut out = Output::new(ClosedPipe, false);
+        print_lines(
+            &mut out,
+            lines(),
+            ReadingDirection::TopToBottom,
+            None,
+            &options,
+        )?;
+        out.end_batch()?;
+        assert_eq!(out.flush().unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+
+        let mut out = Output::new(ClosedPipe, true);
+        print_lines(
+            &mut out,
+            lines(),
+            ReadingDirection::TopToBottom,
+            None,
+            &options,
+        )?;
+        let error =
+            anyhow::Error::from(out.end_batch().unwrap_err()).context("Unable to write to stdout");
+        assert!(is_broken_pipe(&error));
+
+        // Also when it comes out of reading stdin
+        let interrupted = AtomicBool::new(false);
+        let error = follow_stream(
+            &b"first\n"[..],
+            LineFormat::default(),
+            &interrupted,
+            |lines| {
+                print_lines(
+                    &mut out,
+                    lines,
+                    ReadingDirection::TopToBottom,
+                    None,
+                    &options,
+                )?;
+                out.end_batch()
+            },
+        )
+        .unwrap_err();
+        assert!(is_broken_pipe(&anyhow::Error::from(error)));
+
+        assert!(!is_broken_pipe(&anyhow!("Something else")));
+
+        Ok(())
+    }
+
+    // Plain text with line numbers, like by default
+    fn text_options<'a>() -> OutputOptions<'a> {
+        OutputOptions {
+            reverse_output: false,
+            line_ending: LineEnding::Keep,
+            timestamp_format: None,
+            age: None,
+            format: OutputFormat::Text,
+            file_name: "-",
+            tag_sources: false,
+            color: false,
+            highlight: None,
+            fresh: false,
+            show_line_numbers: true,
+            separator: "\t",
+            number_format: NumberFormat::default(),
+            headers: None,
+            squeeze: None,
+            groups: None,
+            relative_numbers: None,
+            extraction: None,
+            template: None,
+        }
+    }
+
+    #[test]
+    fn test_print_lines() -> Result<()> {
+        let print = |lines: &[(u64, &str)],
+                     reading_direction: ReadingDirection,
+                     options: &OutputOptions|
+         -> io::Result<String> {
+            let mut out = vec![];
+            print_lines(&mut out, to_lines(lines), reading_direction, None, options)?;
+            Ok(String::from_utf8(out).unwrap()) // Unwrap is okay, since only text goes in
+        };
+
+        // The last line of a file might not be finished yet, but it still ends the output line
+        let lines = [(9, "nine\n"), (10, "ten")];
+        assert_eq!(
+            print(&lines, ReadingDirection::TopToBottom, &text_options())?,
+            "9:\tnine\n10:\tten\n"
+        );
+        assert_eq!(
+            print(
+                &[(10, "ten"), (9, "nine\n")],
+                ReadingDirection::BottomToTop,
+                &text_options()
+            )?,
+            "9:\tnine\n10:\tten\n"
+        );
+
+        let options = OutputOptions {
+            reverse_output: true,
+            line_ending: LineEnding::Crlf,
+            separator: " ",
+            number_format: NumberFormat {
+                width: 3,
+                zero_padded: true,
+            },
+            ..text_options()
+        };
+        assert_eq!(
+            print(&lines, ReadingDirection::TopToBottom, &options)?,
+            "010: ten\r\n009: nine\r\n"
+        );
+
+        let options = OutputOptions {
+            show_line_numbers: false,
+            ..text_options()
+        };
+        assert_eq!(
+            print(&lines, ReadingDirection::TopToBottom, &options)?,
+            "nine\nten\n"
+        );
+        assert_eq!(print(&[], ReadingDirection::TopToBottom, &options)?, "");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_count() -> Result<()> {
+        let data = (1..=20)
+            .map(|i| match i % 3 {
+                0 => format!("ERROR {}\n", i),
+                _ => format!("ok {}\n", i),
+            })
+            .collect::<String>();
+        let line_filter = LineFilter {
+            regex: Some(Regex::new("^ERROR")?),
+            invert: false,
+            context: ContextLines::default(),
+            since: None,
+        };
+
+        // --grep ERROR -n 10 --count
+        let lines = read_matching_lines(
+            data.as_bytes(),
+            Position::FromEnd(0),
+            Position::FromEnd(10),
+            ReadingDirection::BottomToTop,
+            LineFormat::default(),
+            Some(&line_filter),
+        )?;
+        let mut out = vec![];
+        print_count(&mut out, lines.len() as u64, None, &text_options())?;
+        assert_eq!(out, b"6\n");
+
+        // Only the last 10 lines, but only 3 of them match
+        let lines = read_lines(
+            data.as_bytes(),
+            Position::FromEnd(0),
+            Position::FromEnd(10),
+            ReadingDirection::BottomToTop,
+            LineFormat::default(),
+        )?;
+        let mut out = vec![];
+        print_count(
+            &mut out,
+            line_filter.apply(lines).len() as u64,
+            None,
+            &text_options(),
+        )?;
+        assert_eq!(out, b"3\n");
+
+        // With headers, every file gets its own count
+        let last_source = RefCell::new(None);
+        let options = OutputOptions {
+            headers: Some(&last_source),
+            ..text_options()
+        };
+        let mut out = vec![];
+        print_count(&mut out, 6, Some(Path::new("a.log")), &options)?;
+        print_count(&mut out, 0, Some(Path::new("b.log")), &options)?;
+        assert_eq!(
+            String::from_utf8(out)?,
+            "==> a.log <==\n6\n\n==> b.log <==\n0\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_running_count() -> io::Result<()> {
+        let mut out = vec![];
+        let mut running_count = RunningCount::new(false);
+        running_count.update(&mut out, 3)?;
+        running_count.update(&mut out, 3)?;
+        running_count.update(&mut out, 5)?;
+        running_count.finish(&mut out)?;
+        assert_eq!(out, b"3\n5\n");
+
+        let mut out = vec![];
+        let mut running_count = RunningCount::new(true);
+        running_count.finish(&mut out)?;
+        assert_eq!(out, b"");
+        running_count.update(&mut out, 9)?;
+        running_count.update(&mut out, 10)?;
+        running_count.finish(&mut out)?;
+        assert_eq!(out, b"\r9\r10\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_group_separators() -> Result<()> {
+        let groups = RefCell::new(Groups::default());
+        let options = OutputOptions {
+            groups: Some(&groups),
+            ..text_options()
+        };
+        let print = |lines: &[(u64, &str)], options: &OutputOptions| -> Result<String> {
+            let mut out = vec![];
+            print_lines(
+                &mut out,
+                to_lines(lines),
+                ReadingDirection::TopToBottom,
+                None,
+                options,
+            )?;
+            Ok(String::from_utf8(out)?)
+        };
+
+        assert_eq!(
+            print(&[(3, "ok\n"), (4, "ERROR\n"), (8, "ERROR\n")], &options)?,
+            "3:\tok\n4:\tERROR\n--\n8:\tERROR\n"
+        );
+        // Groups go on across batches
+        assert_eq!(
+            print(&[(9, "ok\n"), (12, "ok\n")], &options)?,
+            "9:\tok\n--\n12:\tok\n"
+        );
+
+        // Nothing in between JSON lines
+        let options = OutputOptions {
+            format: OutputFormat::Json,
+            ..options
+        };
+        assert_eq!(
+            print(&[(20, "ERROR\n")], &options)?,
+            "{\"line\":20,\"content\":\"ERROR\",\"file\":\"-\"}\n"
+        );
+
+        // Every file's line numbers are their own
+        let mut groups = Groups::default();
+        assert!(!groups.separates("first", 5));
+        assert!(!groups.separates("second", 1));
+        assert!(!groups.separates("first", 6));
+        assert!(groups.separates("second", 3));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_field() -> Result<()> {
+        let data = "time,level,message\n10:00,INFO,started\n10:01,ERROR\n10:02,ERROR,disk full\n";
+        let options = OutputOptions {
+            extraction: Some(Extraction::Field {
+                number: 3,
+                delimiter: Some(","),
+            }),
+            ..text_options()
+        };
+        let print = |lines: Vec<Line>| -> Result<String> {
+            let mut out = vec![];
+            print_lines(
+                &mut out,
+                lines,
+                ReadingDirection::TopToBottom,
+                None,
+                &options,
+            )?;
+            Ok(String::from_utf8(out)?)
+        };
+
+        // The line without a message prints empty, but keeps its number
+        let lines = read_lines(
+            data.as_bytes(),
+            Position::FromBegin(1),
+            Position::FromEnd(0),
+            ReadingDirection::TopToBottom,
+            LineFormat::default(),
+        )?;
+        assert_eq!(print(lines)?, "2:\tstarted\n3:\t\n4:\tdisk full\n");
+
+        // The whole line is matched, before the field is taken out of it
+        let line_filter = LineFilter {
+            regex: Some(Regex::new("ERROR")?),
+            invert: false,
+            context: ContextLines::default(),
+            since: None,
+        };
+        let lines = read_matching_lines(
+            data.as_bytes(),
+            Position::FromBegin(0),
+            Position::FromEnd(0),
+            ReadingDirection::TopToBottom,
+            LineFormat::default(),
+            Some(&line_filter),
+        )?;
+        assert_eq!(print(lines)?, "3:\t\n4:\tdisk full\n");
+
+        // Without a delimiter, fields are separated by whitespace
+        let field = Extraction::Field {
+            number: 2,
+            delimiter: None,
+        };
+        assert_eq!(field.extract("  a \t b c\r\n").as_deref(), Some("b\r\n"));
+        assert_eq!(field.extract("a").as_deref(), Some(""));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_field() -> Result<()> {
+        let lines = to_lines(&[
+            (1, "{\"message\": \"started\", \"error\": null}\n"),
+            (2, "not json\n"),
+            (3, "{\"message\": \"failed\", \"error\": {\"code\": 28}}\n"),
+            (4, "{\"message\": \"retrying\"\n"),
+            (5, "{\"error\": {\"code\": [3, 4]}}\n"),
+        ]);
+        let print = |path: &str, strict: bool| -> Result<String> {
+            let options = OutputOptions {
+                extraction: Some(Extraction::JsonField { path, strict }),
+                ..text_options()
+            };
+            let mut out = vec![];
+            print_lines(
+                &mut out,
+                lines.clone(),
+                ReadingDirection::TopToBottom,
+                None,
+                &options,
+            )?;
+            Ok(String::from_utf8(out)?)
+        };
+
+        assert_eq!(
+            print("message", false)?,
+            "1:\tstarted\n2:\tnot json\n3:\tfailed\n4:\t{\"message\": \"retrying\"\n5:\t\n"
+        );
+        assert_eq!(print("message", true)?, "1:\tstarted\n3:\tfailed\n5:\t\n");
+        assert_eq!(print("error.code", true)?, "1:\t\n3:\t28\n5:\t[3,4]\n");
+        assert_eq!(print("error.code.1", true)?, "1:\t\n3:\t\n5:\t4\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_live_marker() -> Result<()> {
+        let mut live_marker = LiveMarker::new(DEFAULT_LIVE_MARKER);
+        let fresh_options = OutputOptions {
+            fresh: true,
+            ..text_options()
+        };
+        let mut out = vec![];
+        let mut show = |lines: Vec<Line>, options: &OutputOptions| -> io::Result<()> {
+            live_marker.print(&mut out, &lines, options)?;
+            print_lines(
+                &mut out,
+                lines,
+                ReadingDirection::TopToBottom,
+                None,
+                options,
+            )
+        };
+
+        // The lines that have been there, then a wakeup without new lines, then new lines twice
+        show(to_lines(&[(1, "old\n")]), &text_options())?;
+        show(vec![], &fresh_options)?;
+        show(to_lines(&[(2, "new\n")]), &fresh_options)?;
+        show(to_lines(&[(3, "newer\n")]), &fresh_options)?;
+        assert_eq!(
+            String::from_utf8(out)?,
+            "1:\told\n--- live ---\n2:\tnew\n3:\tnewer\n"
+        );
+
+        // Nothing new, no marker
+        let mut live_marker = LiveMarker::new(DEFAULT_LIVE_MARKER);
+        let mut out = vec![];
+        live_marker.print(&mut out, &[], &fresh_options)?;
+        live_marker.print(&mut out, &to_lines(&[(1, "old\n")]), &text_options())?;
+        assert!(out.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_squeeze() -> Result<()> {
+        let data = "a\na\na\nb\nc\nc\nb\nb";
+        let squeeze = RefCell::new(Squeeze::default());
+        let options = OutputOptions {
+            squeeze: Some(&squeeze),
+            ..text_options()
+        };
+        let print = |lines: Vec<Line>, reading_direction: ReadingDirection| -> Result<String> {
+            let mut out = vec![];
+            print_lines(&mut out, lines, reading_direction, None, &options)?;
+            Ok(String::from_utf8(out)?)
+        };
+
+        let lines = read_lines(
+            data.as_bytes(),
+            Position::FromEnd(0),
+            Position::FromEnd(6),
+            ReadingDirection::BottomToTop,
+            LineFormat::default(),
+        )?;
+        assert_eq!(
+            print(lines, ReadingDirection::BottomToTop)?,
+            "3:\ta\n4:\tb\n5:\tc\n... (repeated 1 times)\n7:\tb\n"
+        );
+
+        // Runs go on across batches, like when following, and are only told about once they end
+        let new_lines = to_lines(&[(9, "b\n"), (10, "b\n")]);
+        assert_eq!(print(new_lines, ReadingDirection::TopToBottom)?, "");
+        let new_lines = to_lines(&[(11, "d\n")]);
+        assert_eq!(
+            print(new_lines, ReadingDirection::TopToBottom)?,
+            "... (repeated 3 times)\n11:\td\n"
+        );
+
+        let new_lines = to_lines(&[(12, "d\n")]);
+        assert_eq!(print(new_lines, ReadingDirection::TopToBottom)?, "");
+        let mut out = vec![];
+        print_unfinished_runs(&mut out, &options)?;
+        assert_eq!(out, b"... (repeated 1 times)\n");
+
+        // Every file has runs of its own
+        let mut squeeze = Squeeze::default();
+        assert_eq!(
+            squeeze.push("first", "x\n"),
+            Run::Started { ended_repeats: 0 }
+        );
+        assert_eq!(
+            squeeze.push("second", "x\n"),
+            Run::Started { ended_repeats: 0 }
+        );
+        assert_eq!(squeeze.push("first", "x"), Run::Repeated);
+        assert_eq!(
+            squeeze.push("second", "y\n"),
+            Run::Started { ended_repeats: 0 }
+        );
+        assert_eq!(squeeze.finish(), [("first".to_string(), 1)]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_final_newline() -> Result<()> {
+        let print = |data: &str, direction: ReadingDirection, options: &OutputOptions| {
+            let (start, stop) = match direction {
+                ReadingDirection::TopToBottom => (Position::FromBegin(0), Position::FromEnd(0)),
+                ReadingDirection::BottomToTop => (Position::FromEnd(0), Position::FromBegin(0)),
+            };
+            let lines = read_lines(
+                data.as_bytes(),
+                start,
+                stop,
+                direction,
+                LineFormat::default(),
+            )?;
+            let mut out = vec![];
+            print_lines(&mut out, lines, direction, None, options)?;
+            Ok::<_, anyhow::Error>(String::from_utf8(out)?)
+        };
+
+        // Every line ends in exactly one newline, whether the file does or not, and whichever way it's read or printed
+        for data in ["one\ntwo\n", "one\ntwo"] {
+            for direction in [ReadingDirection::TopToBottom, ReadingDirection::BottomToTop] {
+                assert_eq!(
+                    print(data, direction, &text_options())?,
+                    "1:\tone\n2:\ttwo\n"
+                );
+
+                let options = OutputOptions {
+                    reverse_output: true,
+                    ..text_options()
+                };
+                assert_eq!(print(data, direction, &options)?, "2:\ttwo\n1:\tone\n");
+            }
+        }
+
+        // Unless the lines are wanted exactly as they are
+        let options = OutputOptions {
+            line_ending: LineEnding::Raw,
+            ..text_options()
+        };
+        for direction in [ReadingDirection::TopToBottom, ReadingDirection::BottomToTop] {
+            assert_eq!(
+                print("one\ntwo\n", direction, &options)?,
+                "1:\tone\n2:\ttwo\n"
+            );
+            assert_eq!(print("one\ntwo", direction, &options)?, "1:\tone\n2:\ttwo");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_output_file() -> Result<()> {
+        let mut input = tempfile::NamedTempFile::new()?;
+        write!(input, "first\nsecond\n")?;
+        input.flush()?;
+        let output = tempfile::NamedTempFile::new()?;
+        assert!(is_same_path(input.path(), input.path()));
+        assert!(!is_same_path(input.path(), output.path()));
+
+        let options = OutputOptions {
+            show_line_numbers: false,
+            ..text_options()
+        };
+        let mut out = Output::new(
+            Tee {
+                stdout: None,
+                file: Some(output.reopen()?),
+            },
+            true,
+        );
+
+        // Follow the input, while lines keep being appended to it
+        let mut file = OpenOptions::new().read(true).open(input.path())?;
+        let mut cursor = ReadCursor::default();
+        let mut line_numbering = LineNumbering::new(None, OverflowPolicy::Saturate);
+        for appended in ["third\n", "fourth\nfifth\n", ""] {
+            let lines = read_new_lines(
+                &mut file,
+                &mut cursor,
+                ReadingDirection::TopToBottom,
+                &mut line_numbering,
+                None,
+                LineFormat::default(),
+            )?;
+            print_lines(
+                &mut out,
+                lines,
+                ReadingDirection::TopToBottom,
+                None,
+                &options,
+            )?;
+            out.end_batch()?;
+
+            write!(input, "{}", appended)?;
+            input.flush()?;
+        }
+
+        assert_eq!(
+            std::fs::read_to_string(output.path())?,
+            std::fs::read_to_string(input.path())?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stop_on_broken_pipe() {
+        let interrupted = AtomicBool::new(false);
+        let mut out = Output::new(ClosedPipe, true);
+
+        let written = out
+            .write_all(b"first\n")
+            .and_then(|_| out.end_batch())
+            .context("Unable to write to stdout");
+        assert!(!stop_on_broken_pipe(written.map(|_| true), &interrupted).unwrap());
+        assert!(interrupted.load(Ordering::SeqCst));
+
+        let interrupted = AtomicBool::new(false);
+        assert!(stop_on_broken_pipe(Ok(true), &interrupted).unwrap());
+        assert!(stop_on_broken_pipe::<bool>(Err(anyhow!("Disk on fire")), &interrupted).is_err());
+        assert!(!interrupted.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_stats() -> Result<()> {
+        let input: &[u8] = b"first\nsecond\nthird";
+        let interrupted = AtomicBool::new(false);
+
+        let stats = Stats::default();
+        follow_stream(input, LineFormat::default(), &interrupted, |lines| {
+            stats.count_lines(&lines);
+            Ok(())
+        })?;
+        assert_eq!(stats.lines.get(), 3);
+        assert_eq!(stats.bytes.get(), input.len() as u64);
+
+        // In byte mode, only complete lines count
+        let stats = Stats::default();
+        follow_byte_stream(input, &interrupted, |bytes| {
+            stats.count_bytes(bytes);
+            Ok(())
+        })?;
+        assert_eq!(stats.lines.get(), 2);
+        assert_eq!(stats.bytes.get(), input.len() as u64);
+
+        assert_eq!(
+            stats.summary(Duration::from_millis(1500)),
+            "printed 2 lines (18 bytes) in 1.500s"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stats_interval() {
+        let mut stats_interval = StatsInterval::new(Duration::from_secs(2));
+        let stats = Stats::default();
+        stats.count_lines(&[(1, "first\n".to_string()), (2, "second\n".to_string())]);
+
+        assert_eq!(stats_interval.report(Duration::from_secs(1), &stats), None);
+        assert_eq!(
+            stats_interval.remaining(Duration::from_secs(1)),
+            Duration::from_secs(1)
+        );
+        assert_eq!(
+            stats_interval
+                .report(Duration::from_secs(2), &stats)
+                .as_deref(),
+            Some("1.0 lines/s, 6.5 bytes/s, 2 lines in total, running for 2s")
+        );
+
+        // The rates only cover what has been printed since then
+        stats.count_lines(&[(3, "third\n".to_string())]);
+        assert_eq!(
+            stats_interval
+                .report(Duration::from_secs(5), &stats)
+                .as_deref(),
+            Some("0.3 lines/s, 2.0 bytes/s, 3 lines in total, running for 5s")
+        );
+    }
+
+    #[test]
+    fn test_parse_count() {
+        assert_eq!(parse_count("10"), Ok(10));
+        assert_eq!(parse_count("0"), Ok(0));
+        assert_eq!(parse_count("1k"), Ok(1024));
+        assert_eq!(parse_count("2K"), Ok(2048));
+        assert_eq!(parse_count("1M"), Ok(1024 * 1024));
+        assert_eq!(parse_count("3b"), Ok(3 * 512));
+        assert_eq!(parse_count("1g"), Ok(1024 * 1024 * 1024));
+        assert!(parse_count("5x").is_err());
+        assert!(parse_count("k").is_err());
+        assert!(parse_count("1.5k").is_err());
+        assert!(parse_count("-3").is_err());
+        assert!(parse_count(&format!("{}k", u64::MAX)).is_err());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        // Bare numbers keep their old meaning
+        assert_eq!(
+            parse_duration("60", BareNumber::Hertz),
+            Ok(Duration::from_secs_f64(1.0 / 60.0))
+        );
+        assert_eq!(
+            parse_duration("100", BareNumber::Milliseconds),
+            Ok(Duration::from_millis(100))
+        );
+
+        for bare_number in [BareNumber::Hertz, BareNumber::Milliseconds] {
+            assert_eq!(
+                parse_duration("500ms", bare_number),
+                Ok(Duration::from_millis(500))
+            );
+            assert_eq!(
+                parse_duration("1.5s", bare_number),
+                Ok(Duration::from_millis(1500))
+            );
+            assert_eq!(
+                parse_duration("0.5S", bare_number),
+                Ok(Duration::from_millis(500))
+            );
+            assert_eq!(
+                parse_duration("2m", bare_number),
+                Ok(Duration::from_secs(120))
+            );
+            assert_eq!(
+                parse_duration("4hz", bare_number),
+                Ok(Duration::from_millis(250))
+            );
+
+            assert!(parse_duration("fast", bare_number).is_err());
+            assert!(parse_duration("", bare_number).is_err());
+            assert!(parse_duration("-1s", bare_number).is_err());
+            assert!(parse_duration("1.5x", bare_number).is_err());
+            assert!(parse_duration("1s500ms", bare_number).is_err());
+            assert!(parse_duration("NaN", bare_number).is_err());
+            assert!(parse_duration("99999999999999999999999m", bare_number).is_err());
+            assert!(parse_duration("0hz", bare_number).is_err());
+        }
+        assert!(parse_duration("0", BareNumber::Hertz).is_err());
+        assert_eq!(
+            parse_duration("0", BareNumber::Milliseconds),
+            Ok(Duration::ZERO)
+        );
+        assert_eq!(
+            parse_duration("2.5", BareNumber::Seconds),
+            Ok(Duration::from_millis(2500))
+        );
+    }
+
+    #[test]
+    fn test_headers() -> Result<()> {
+        let first = tempfile::NamedTempFile::new()?;
+        let second = tempfile::NamedTempFile::new()?;
+        let last_source = RefCell::new(None);
+        let mut options = OutputOptions {
+            reverse_output: false,
+            line_ending: LineEnding::Keep,
+            timestamp_format: None,
+            age: None,
+            format: OutputFormat::Text,
+            file_name: "-",
+            tag_sources: false,
+            color: false,
+            highlight: None,
+            fresh: false,
+            show_line_numbers: true,
+            separator: "\t",
+            number_format: NumberFormat::default(),
+            headers: Some(&last_source),
+            squeeze: None,
+            groups: None,
+            relative_numbers: None,
+            extraction: None,
+            template: None,
+        };
+
+        let header_of =
+            |file: &tempfile::NamedTempFile| format!("==> {} <==\n", file.path().display());
+        assert_eq!(
+            next_header(Some(first.path()), &options),
+            Some(header_of(&first))
+        );
+        assert_eq!(next_header(Some(first.path()), &options), None);
+        assert_eq!(
+            next_header(Some(second.path()), &options),
+            Some(format!("\n{}", header_of(&second)))
+        );
+        assert_eq!(
+            next_header(Some(first.path()), &options),
+            Some(format!("\n{}", header_of(&first)))
+        );
+        assert_eq!(
+            next_header(Some(Path::new("-")), &options),
+            Some("\n==> standard input <==\n".to_string())
+        );
+
+        options.headers = None;
+        assert_eq!(next_header(Some(second.path()), &options), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_screen_window() {
+        let last_source = RefCell::new(None);
+        let mut options = OutputOptions {
+            reverse_output: false,
+            line_ending: LineEnding::Keep,
+            timestamp_format: None,
+            age: None,
+            format: OutputFormat::Text,
+            file_name: "-",
+            tag_sources: false,
+            color: false,
+            highlight: None,
+            fresh: false,
+            show_line_numbers: true,
+            separator: " ",
+            number_format: NumberFormat::default(),
+            headers: None,
+            squeeze: None,
+            groups: None,
+            relative_numbers: None,
+            extraction: None,
+            template: None,
+        };
+        let first = Path::new("first.log");
+        let second = Path::new("second.log");
+
+        let mut screen = ScreenWindow::new(Some(3));
+        screen.push(
+            to_lines(&[(2, "b\n"), (1, "a\n")]),
+            ReadingDirection::BottomToTop,
+            first,
+            &options,
+        );
+        assert_eq!(screen.render(&options), "\x1b[2J\x1b[H1: a\n2: b\n");
+
+        // Only the latest lines stay on screen
+        screen.push(
+            to_lines(&[(3, "c\n"), (4, "d\n")]),
+            ReadingDirection::TopToBottom,
+            first,
+            &options,
+        );
+        assert_eq!(screen.render(&options), "\x1b[2J\x1b[H2: b\n3: c\n4: d\n");
+
+        options.reverse_output = true;
+        assert_eq!(screen.render(&options), "\x1b[2J\x1b[H4: d\n3: c\n2: b\n");
+
+        // Every redraw starts with a header of its own
+        options.reverse_output = false;
+        options.headers = Some(&last_source);
+        screen.push(
+            to_lines(&[(1, "x\n")]),
+            ReadingDirection::TopToBottom,
+            second,
+            &options,
+        );
+        for _ in 0..2 {
+            assert_eq!(
+                screen.render(&options),
+                "\x1b[2J\x1b[H==> first.log <==\n3: c\n4: d\n\n==> second.log <==\n1: x\n"
+            );
+        }
+
+        // Ages are how long ago the lines arrived, as of the redraw
+        options.headers = None;
+        options.age = Some(Instant::now() - Duration::from_secs(60));
+        screen.push(
+            to_lines(&[(2, "y\n")]),
+            ReadingDirection::TopToBottom,
+            second,
+            &options,
+        );
+        assert_eq!(
+            screen.render(&options),
+            "\x1b[2J\x1b[H[0s ago] 4: d\n[0s ago] 1: x\n[0s ago] 2: y\n"
+        );
+    }
+
+    #[test]
+    fn test_format_age() {
+        assert_eq!(format_age(Duration::from_millis(999)), "0s");
+        assert_eq!(format_age(Duration::from_secs(42)), "42s");
+        assert_eq!(format_age(Duration::from_secs(5 * 60 + 7)), "5m07s");
+        assert_eq!(
+            format_age(Duration::from_secs(3 * 3600 + 12 * 60 + 59)),
+            "3h12m"
+        );
+        assert_eq!(format_age(Duration::from_secs(2 * 86400 + 3600)), "2d01h");
+    }
+
+    #[test]
+    fn test_header_mode() {
+        for (header_mode, one_file, two_files) in [
+            (HeaderMode::Auto, false, true),
+            (HeaderMode::Always, true, true),
+            (HeaderMode::Never, false, false),
+        ] {
+            assert_eq!(header_mode.shows_headers(1), one_file);
+            assert_eq!(header_mode.shows_headers(2), two_files);
+        }
+    }
+
+    #[test]
+    fn test_decode_escapes() {
+        assert_eq!(decode_escapes("\\t"), Ok("\t".to_string()));
+        assert_eq!(decode_escapes("a\\0b\\\\n"), Ok("a\0b\\n".to_string()));
+        assert_eq!(decode_escapes(" | "), Ok(" | ".to_string()));
+        assert_eq!(decode_escapes(""), Ok(String::new()));
+        assert!(decode_escapes("\\x").is_err());
+        assert!(decode_escapes("tab\\").is_err());
+    }
+
+    #[test]
+    fn test_format_line_json() -> Result<()> {
+        let mut options = OutputOptions {
+            reverse_output: false,
+            line_ending: LineEnding::Keep,
+            timestamp_format: None,
+            age: None,
+            format: OutputFormat::Json,
+            file_name: "app.log",
+            tag_sources: false,
+            color: false,
+            highlight: None,
+            fresh: false,
+            show_line_numbers: true,
+            separator: "\t",
+            number_format: NumberFormat::default(),
+            headers: None,
+            squeeze: None,
+            groups: None,
+            relative_numbers: None,
+            extraction: None,
+            template: None,
+        };
+
+        let output = format_line(42, "Say \"hello\"\t\\o/\r\n", None, &options);
+        assert!(output.ends_with('\n'));
+        let json: serde_json::Value = serde_json::from_str(&output)?;
+        assert_eq!(json["line"], 42);
+        assert_eq!(json["content"], "Say \"hello\"\t\\o/");
+        assert_eq!(json["file"], "app.log");
+        assert!(json.get("time").is_none());
+
+        options.line_ending = LineEnding::Crlf;
+        let output = format_line(7, "no newline", Some("03:04:05"), &options);
+        assert!(output.ends_with("}\r\n"));
+        let json: serde_json::Value = serde_json::from_str(&output)?;
+        assert_eq!(json["content"], "no newline");
+        assert_eq!(json["time"], "03:04:05");
+        assert!(json.get("age").is_none());
+
+        // Ages are counted from when tail started
+        options.age = Some(Instant::now() - Duration::from_secs(90));
+        let json: serde_json::Value = serde_json::from_str(&format_line(7, "", None, &options))?;
+        assert!(json["age"].as_f64().is_some_and(|age| age >= 90.0));
+        options.format = OutputFormat::Text;
+        assert_eq!(
+            format_line(7, "no newline", Some("03:04:05"), &options),
+            "[+1m30s] [03:04:05] 7:\tno newline\r\n"
+        );
+        options.age = None;
+
+        assert_eq!(
+            format_line(7, "no newline", Some("03:04:05"), &options),
+            "[03:04:05] 7:\tno newline\r\n"
+        );
+
+        let regex = Regex::new("new")?;
+        options.color = true;
+        options.highlight = Some(&regex);
+        assert_eq!(
+            format_line(7, "no newline\n", None, &options),
+            "\x1b[2m7:\x1b[0m\tno \x1b[1;31mnew\x1b[0mline\r\n"
+        );
+        options.fresh = true;
+        assert!(format_line(7, "no newline\n", None, &options).starts_with("\x1b[32m7:"));
+
+        options.color = false;
+        options.separator = ",";
+        options.number_format = NumberFormat::parse("04").unwrap();
+        assert_eq!(
+            format_line(7, "no newline\n", None, &options),
+            "0007:,no newline\r\n"
+        );
+        options.number_format = NumberFormat::parse("3").unwrap();
+        assert_eq!(
+            format_line(7, "no newline\n", None, &options),
+            "  7:,no newline\r\n"
+        );
+
+        options.show_line_numbers = false;
+        assert_eq!(
+            format_line(7, "no newline\n", Some("03:04:05"), &options),
+            "[03:04:05] no newline\r\n"
+        );
+
+        // Merged lines tell which file they came from
+        options.tag_sources = true;
+        options.file_name = "app.log";
+        assert_eq!(
+            format_line(7, "no newline\n", None, &options),
+            "app.log: no newline\r\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_prefix_template() -> Result<()> {
+        let format = |template: &str, line_number: u64, line: &str| -> Result<String> {
+            let template = Template::parse(template).map_err(|error| anyhow!(error))?;
+            let options = OutputOptions {
+                file_name: "app.log",
+                template: Some(&template),
+                ..text_options()
+            };
+            Ok(format_line(line_number, line, Some("12:00:00"), &options))
+        };
+
+        // Without a template, it's the same as the default one
+        for line in ["started\n", "no line ending", "\r\n"] {
+            assert_eq!(
+                format("{line}:\t{content}", 7, line)?,
+                format_line(7, line, None, &text_options())
+            );
+        }
+
+        assert_eq!(
+            format("[{time}] {file}:{line} {content}", 3, "disk full\n")?,
+            "[12:00:00] app.log:3 disk full\n"
+        );
+        assert_eq!(format("{{{line}}} {content}", 3, "x\n")?, "{3} x\n");
+        // Without the content, the line still ends
+        assert_eq!(format("{file}#{line}", 3, "disk full\n")?, "app.log#3\n");
+        assert_eq!(format("", 3, "disk full\n")?, "\n");
+
+        assert!(Template::parse("{line}").is_ok_and(|template| template.uses(&TemplatePart::Line)));
+        for invalid in ["{level} {content}", "{content", "content}", "{}"] {
+            assert!(
+                Template::parse(invalid).is_err(),
+                "{} should be invalid",
+                invalid
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_terminate_line() {
+        let lines = ["unix\n", "windows\r\n", "end of file", "lonely\r"];
+
+        let normalize = |line_ending| {
+            lines
+                .iter()
+                .map(|line| terminate_line(line, line_ending))
+                .collect::<Vec<String>>()
+        };
+
+        assert_eq!(
+            normalize(LineEnding::Lf),
+            ["unix\n", "windows\n", "end of file\n", "lonely\n"]
+        );
+        assert_eq!(
+            normalize(LineEnding::Crlf),
+            ["unix\r\n", "windows\r\n", "end of file\r\n", "lonely\r\n"]
+        );
+        assert_eq!(
+            normalize(LineEnding::Keep),
+            ["unix\n", "windows\r\n", "end of file\n", "lonely\r\n"]
+        );
+        assert_eq!(terminate_line("four", LineEnding::Nul), "four\0");
+    }
+
+    #[test]
+    fn test_binary_and_text_modes() -> Result<()> {
+        let data = "one\r\ntwo\r\nthree";
+        let print = |line_ending| -> Result<String> {
+            let lines = read_lines(
+                data.as_bytes(),
+                Position::FromBegin(0),
+                Position::FromEnd(0),
+                ReadingDirection::TopToBottom,
+                LineFormat::default(),
+            )?;
+            let options = OutputOptions {
+                line_ending,
+                show_line_numbers: false,
+                ..text_options()
+            };
+            let mut out = vec![];
+            print_lines(
+                &mut out,
+                lines,
+                ReadingDirection::TopToBottom,
+                None,
+                &options,
+            )?;
+            Ok(String::from_utf8(out)?)
+        };
+
+        // --binary
+        assert_eq!(print(LineEnding::Raw)?, data);
+        // --text
+        assert_eq!(print(LineEnding::Lf)?, "one\ntwo\nthree\n");
+
         Ok(())
     }
 }
@@ -0,0 +1,863 @@
+// Ways of noticing that the followed file has changed. Hotwatch gets told by the operating system, which is cheap.
+// That doesn't work everywhere, though, e.g. on network file systems, so there is also the option to just look at the file every frame
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs::Metadata,
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Context, Result};
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender};
+use crossbeam_utils::atomic::AtomicCell;
+use hotwatch::{Event, Hotwatch};
+
+pub trait FileMonitor {
+    fn watch(&mut self, path: &Path) -> Result<()>;
+    fn unwatch(&mut self, path: &Path);
+    // Whether the file has been written to since the last time this has been asked
+    fn take_changed(&mut self) -> bool;
+    // Whether the file has been deleted, renamed or replaced by another one
+    fn is_replaced(&mut self) -> bool;
+    fn clear_replaced(&mut self);
+    // Whether every change is announced through a Wakeup, so that there is no need to look at the file until then
+    fn wakes_up(&self) -> bool {
+        false
+    }
+}
+
+// Lets the follow loop sleep until a monitor has something to tell, instead of looking at every file every frame
+#[derive(Clone)]
+pub struct Wakeup {
+    sender: Sender<()>,
+    receiver: Receiver<()>,
+}
+
+impl Wakeup {
+    pub fn new() -> Self {
+        // However many changes there are while nobody is waiting, they add up to a single wakeup
+        let (sender, receiver) = crossbeam_channel::bounded(1);
+        Self { sender, receiver }
+    }
+
+    pub fn notify(&self) {
+        let _ = self.sender.try_send(()); // Full means a wakeup is already pending
+    }
+
+    // Returns whether there has been a notification. Without a timeout, this waits until there is one
+    pub fn wait(&self, timeout: Option<Duration>) -> bool {
+        match timeout {
+            Some(timeout) => match self.receiver.recv_timeout(timeout) {
+                Ok(()) => true,
+                Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => false,
+            },
+            None => self.receiver.recv().is_ok(),
+        }
+    }
+}
+
+impl Default for Wakeup {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Counts the events Hotwatch hands over, and prints them as they come in, if wanted. Editors saving "atomically" show up as a
+// rename or a create, instead of a write, for example
+#[derive(Clone, Default)]
+pub struct EventLog {
+    print: bool,
+    counts: Arc<Mutex<BTreeMap<&'static str, u64>>>,
+}
+
+impl EventLog {
+    pub fn new(print: bool) -> Self {
+        Self {
+            print,
+            counts: Arc::default(),
+        }
+    }
+
+    pub fn record(&self, event: &Event) {
+        let (name, paths) = match event {
+            Event::NoticeWrite(path) => ("NoticeWrite", vec![path]),
+            Event::NoticeRemove(path) => ("NoticeRemove", vec![path]),
+            Event::Create(path) => ("Create", vec![path]),
+            Event::Write(path) => ("Write", vec![path]),
+            Event::Chmod(path) => ("Chmod", vec![path]),
+            Event::Remove(path) => ("Remove", vec![path]),
+            Event::Rename(from, to) => ("Rename", vec![from, to]),
+            Event::Rescan => ("Rescan", vec![]),
+            Event::Error(_, path) => ("Error", path.iter().collect()),
+        };
+        *self.counts.lock().unwrap().entry(name).or_default() += 1; // Unwrap is okay, since nothing panics while holding the lock
+
+        if self.print {
+            let details = match event {
+                Event::Error(error, _) => format!(" ({})", error),
+                _ => String::new(),
+            };
+            eprintln!(
+                "tail: [{}] {} {:?}{}",
+                chrono::Local::now().format("%H:%M:%S%.3f"),
+                name,
+                paths,
+                details
+            );
+        }
+    }
+
+    // Every kind of event that has come in, with how often it has
+    pub fn counts(&self) -> Vec<(&'static str, u64)> {
+        self.counts
+            .lock()
+            .unwrap() // Unwrap is okay, since nothing panics while holding the lock
+            .iter()
+            .map(|(name, count)| (*name, *count))
+            .collect()
+    }
+}
+
+pub struct HotwatchMonitor {
+    file_watcher: Hotwatch,
+    file_changed: Arc<AtomicCell<bool>>,
+    file_replaced: Arc<AtomicCell<bool>>,
+    wakeup: Option<Wakeup>,
+    events: Option<EventLog>,
+}
+
+impl HotwatchMonitor {
+    pub fn new(
+        notification_delay: Duration,
+        wakeup: Option<&Wakeup>,
+        events: Option<&EventLog>,
+    ) -> Result<Self> {
+        Ok(Self {
+            file_watcher: Hotwatch::new_with_custom_delay(notification_delay)
+                .context("Hotwatch failed to initialize")?,
+            file_changed: Arc::new(AtomicCell::new(false)),
+            file_replaced: Arc::new(AtomicCell::new(false)),
+            wakeup: wakeup.cloned(),
+            events: events.cloned(),
+        })
+    }
+}
+
+impl FileMonitor for HotwatchMonitor {
+    fn watch(&mut self, path: &Path) -> Result<()> {
+        let file_changed = Arc::clone(&self.file_changed);
+        let file_replaced = Arc::clone(&self.file_replaced);
+        let wakeup = self.wakeup.clone();
+        let events = self.events.clone();
+
+        self.file_watcher
+            .watch(path, move |event| {
+                if let Some(events) = &events {
+                    events.record(&event);
+                }
+                match event {
+                    // However many writes there are until the next frame, they add up to a single change
+                    Event::Write(_path) => file_changed.store(true),
+                    // Depending on how quickly a new file shows up, the old one being removed might only be announced in
+                    // advance. Deleting a file that we still have open only shows up as a change of its metadata
+                    Event::NoticeRemove(_path)
+                    | Event::Chmod(_path)
+                    | Event::Remove(_path)
+                    | Event::Rename(_path, _)
+                    | Event::Create(_path) => file_replaced.store(true),
+                    _ => return,
+                }
+                if let Some(wakeup) = &wakeup {
+                    wakeup.notify();
+                }
+            })
+            .context(format!("Failed to watch {:?}!", path))
+    }
+
+    fn unwatch(&mut self, path: &Path) {
+        // Not much we can do if this fails, since the file is of no interest anymore
+        let _ = self.file_watcher.unwatch(path);
+    }
+
+    fn take_changed(&mut self) -> bool {
+        self.file_changed.compare_exchange(true, false).is_ok()
+    }
+
+    fn is_replaced(&mut self) -> bool {
+        self.file_replaced.load()
+    }
+
+    fn clear_replaced(&mut self) {
+        self.file_replaced.store(false);
+    }
+
+    fn wakes_up(&self) -> bool {
+        self.wakeup.is_some()
+    }
+}
+
+// Some file systems don't let the file itself be watched, but its directory. Only the events about the file are of interest,
+// which also makes a new file being created in its place show up
+pub struct ParentDirectoryMonitor {
+    directory_watcher: Hotwatch,
+    directory: Option<PathBuf>,
+    file_changed: Arc<AtomicCell<bool>>,
+    file_replaced: Arc<AtomicCell<bool>>,
+    wakeup: Option<Wakeup>,
+    events: Option<EventLog>,
+}
+
+impl ParentDirectoryMonitor {
+    pub fn new(
+        notification_delay: Duration,
+        wakeup: Option<&Wakeup>,
+        events: Option<&EventLog>,
+    ) -> Result<Self> {
+        Ok(Self {
+            directory_watcher: Hotwatch::new_with_custom_delay(notification_delay)
+                .context("Hotwatch failed to initialize")?,
+            directory: None,
+            file_changed: Arc::new(AtomicCell::new(false)),
+            file_replaced: Arc::new(AtomicCell::new(false)),
+            wakeup: wakeup.cloned(),
+            events: events.cloned(),
+        })
+    }
+}
+
+impl FileMonitor for ParentDirectoryMonitor {
+    fn watch(&mut self, path: &Path) -> Result<()> {
+        let name = path
+            .file_name()
+            .context(format!("{:?} doesn't name a file", path))?;
+        let directory = match path.parent() {
+            Some(parent) if parent != Path::new("") => parent,
+            _ => Path::new("."),
+        };
+        // Events name paths below the watched directory just like it's been given. Hotwatch also reports what happens in
+        // subdirectories, so only the exact path counts
+        let directory = directory
+            .canonicalize()
+            .context(format!("Failed to find the directory of {:?}", path))?;
+        let target = directory.join(name);
+        let file_changed = Arc::clone(&self.file_changed);
+        let file_replaced = Arc::clone(&self.file_replaced);
+        let wakeup = self.wakeup.clone();
+        let events = self.events.clone();
+
+        self.directory_watcher
+            .watch(&directory, move |event| {
+                if let Some(events) = &events {
+                    events.record(&event);
+                }
+                match event {
+                    Event::Write(path) if path == target => file_changed.store(true),
+                    Event::NoticeRemove(path)
+                    | Event::Chmod(path)
+                    | Event::Remove(path)
+                    | Event::Create(path)
+                        if path == target =>
+                    {
+                        file_replaced.store(true)
+                    }
+                    Event::Rename(from, to) if from == target || to == target => {
+                        file_replaced.store(true)
+                    }
+                    _ => return,
+                }
+                if let Some(wakeup) = &wakeup {
+                    wakeup.notify();
+                }
+            })
+            .context(format!("Failed to watch {:?}!", directory))?;
+        self.directory = Some(directory);
+        Ok(())
+    }
+
+    fn unwatch(&mut self, _path: &Path) {
+        if let Some(directory) = self.directory.take() {
+            let _ = self.directory_watcher.unwatch(directory);
+        }
+    }
+
+    fn take_changed(&mut self) -> bool {
+        self.file_changed.compare_exchange(true, false).is_ok()
+    }
+
+    fn is_replaced(&mut self) -> bool {
+        self.file_replaced.load()
+    }
+
+    fn clear_replaced(&mut self) {
+        self.file_replaced.store(false);
+    }
+
+    fn wakes_up(&self) -> bool {
+        self.wakeup.is_some()
+    }
+}
+
+// Size and modification time. If either of them differs from last time, something has been written
+type FileState = (u64, Option<SystemTime>);
+
+#[derive(Default)]
+pub struct PollingMonitor {
+    path: Option<PathBuf>,
+    watched_file: Option<Metadata>,
+    last_state: Option<FileState>,
+}
+
+impl PollingMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+fn file_state(metadata: &Metadata) -> FileState {
+    (metadata.len(), metadata.modified().ok())
+}
+
+impl FileMonitor for PollingMonitor {
+    fn watch(&mut self, path: &Path) -> Result<()> {
+        let metadata = std::fs::metadata(path).context(format!("Failed to watch {:?}!", path))?;
+
+        self.path = Some(path.to_path_buf());
+        self.last_state = Some(file_state(&metadata));
+        self.watched_file = Some(metadata);
+        Ok(())
+    }
+
+    fn unwatch(&mut self, _path: &Path) {
+        *self = Self::default();
+    }
+
+    fn take_changed(&mut self) -> bool {
+        let state = match self.path.as_ref().map(std::fs::metadata) {
+            Some(Ok(metadata)) => file_state(&metadata),
+            _ => return false,
+        };
+
+        if self.last_state == Some(state) {
+            return false;
+        }
+        self.last_state = Some(state);
+        true
+    }
+
+    fn is_replaced(&mut self) -> bool {
+        let (path, watched_file) = match (&self.path, &self.watched_file) {
+            (Some(path), Some(watched_file)) => (path, watched_file),
+            _ => return false,
+        };
+
+        match std::fs::metadata(path) {
+            Ok(metadata) => !is_same_metadata(watched_file, &metadata).unwrap_or(true),
+            Err(_) => true, // Gone
+        }
+    }
+
+    fn clear_replaced(&mut self) {
+        // Nothing is remembered, so there is nothing to clear. Whether the file has been replaced is checked anew every time
+    }
+}
+
+// Events can get lost, e.g. on network file systems, so once another monitor hasn't noticed anything for a while, look at the
+// file by name. Like GNU tail's --max-unchanged-stats, this catches a rotation that has gone by silently
+pub struct UnchangedStatsMonitor {
+    file_monitor: Box<dyn FileMonitor>,
+    max_unchanged_stats: u64,
+    unchanged_stats: u64, // Checks in a row that found nothing new
+    path: Option<PathBuf>,
+    watched_file: Option<Metadata>,
+    replaced: bool,
+}
+
+impl UnchangedStatsMonitor {
+    // Takes over a monitor that is already watching the file at the path
+    pub fn new(
+        file_monitor: Box<dyn FileMonitor>,
+        path: &Path,
+        max_unchanged_stats: u64,
+    ) -> Result<Self> {
+        Ok(Self {
+            file_monitor,
+            max_unchanged_stats,
+            unchanged_stats: 0,
+            path: Some(path.to_path_buf()),
+            watched_file: Some(
+                std::fs::metadata(path).context(format!("Failed to watch {:?}!", path))?,
+            ),
+            replaced: false,
+        })
+    }
+}
+
+impl FileMonitor for UnchangedStatsMonitor {
+    fn watch(&mut self, path: &Path) -> Result<()> {
+        self.file_monitor.watch(path)?;
+
+        self.path = Some(path.to_path_buf());
+        self.watched_file =
+            Some(std::fs::metadata(path).context(format!("Failed to watch {:?}!", path))?);
+        self.unchanged_stats = 0;
+        self.replaced = false;
+        Ok(())
+    }
+
+    fn unwatch(&mut self, path: &Path) {
+        self.file_monitor.unwatch(path);
+        self.path = None;
+        self.watched_file = None;
+    }
+
+    fn take_changed(&mut self) -> bool {
+        if self.file_monitor.take_changed() {
+            self.unchanged_stats = 0;
+            return true;
+        }
+
+        self.unchanged_stats += 1;
+        if self.unchanged_stats < self.max_unchanged_stats {
+            return false;
+        }
+        self.unchanged_stats = 0;
+
+        let (path, watched_file) = match (&self.path, &self.watched_file) {
+            (Some(path), Some(watched_file)) => (path, watched_file),
+            _ => return false,
+        };
+        match std::fs::metadata(path) {
+            Ok(metadata) if is_same_metadata(watched_file, &metadata).unwrap_or(true) => {
+                // Still the same file, but a write might have gone unnoticed
+                let resized = metadata.len() != watched_file.len();
+                self.watched_file = Some(metadata);
+                resized
+            }
+            _ => {
+                self.replaced = true;
+                false
+            }
+        }
+    }
+
+    fn is_replaced(&mut self) -> bool {
+        self.replaced || self.file_monitor.is_replaced()
+    }
+
+    fn clear_replaced(&mut self) {
+        self.replaced = false;
+        self.file_monitor.clear_replaced();
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum WatchMode {
+    File,
+    ParentDirectory,
+    Poll,
+}
+
+// Hotwatch if possible, since it's cheaper. If the file itself can't be watched, its directory might be. Polling if asked for,
+// or if Hotwatch doesn't work here at all. Only Hotwatch can wake anybody up
+pub fn monitor_file(
+    path: &Path,
+    mode: WatchMode,
+    notification_delay: Duration,
+    wakeup: Option<&Wakeup>,
+    events: Option<&EventLog>,
+) -> Result<Box<dyn FileMonitor>> {
+    let mut mode = mode;
+    loop {
+        let file_monitor: Result<Box<dyn FileMonitor>> = match mode {
+            WatchMode::File => HotwatchMonitor::new(notification_delay, wakeup, events)
+                .map(|file_monitor| Box::new(file_monitor) as Box<dyn FileMonitor>),
+            WatchMode::ParentDirectory => {
+                ParentDirectoryMonitor::new(notification_delay, wakeup, events)
+                    .map(|file_monitor| Box::new(file_monitor) as Box<dyn FileMonitor>)
+            }
+            WatchMode::Poll => Ok(Box::new(PollingMonitor::new())),
+        };
+        let error = match file_monitor {
+            Ok(mut file_monitor) => match file_monitor.watch(path) {
+                Ok(()) => return Ok(file_monitor),
+                Err(error) => error,
+            },
+            Err(error) => error,
+        };
+
+        mode = match mode {
+            WatchMode::File => {
+                eprintln!("tail: {:#}. Watching its directory instead", error);
+                WatchMode::ParentDirectory
+            }
+            WatchMode::ParentDirectory => {
+                eprintln!("tail: {:#}. Polling {:?} instead", error, path);
+                WatchMode::Poll
+            }
+            WatchMode::Poll => return Err(error),
+        };
+    }
+}
+
+// Notices files showing up in a directory, e.g. when a service starts a new, timestamped log file
+pub trait DirectoryMonitor {
+    fn watch(&mut self, directory: &Path) -> Result<()>;
+    // Paths that have been created or moved there since the last time this has been asked. Might include subdirectories
+    fn take_created(&mut self) -> Vec<PathBuf>;
+    // Same as for files
+    fn wakes_up(&self) -> bool {
+        false
+    }
+}
+
+pub struct HotwatchDirectoryMonitor {
+    directory_watcher: Hotwatch,
+    created: Arc<Mutex<Vec<PathBuf>>>,
+    wakeup: Option<Wakeup>,
+    events: Option<EventLog>,
+}
+
+impl HotwatchDirectoryMonitor {
+    pub fn new(
+        notification_delay: Duration,
+        wakeup: Option<&Wakeup>,
+        events: Option<&EventLog>,
+    ) -> Result<Self> {
+        Ok(Self {
+            directory_watcher: Hotwatch::new_with_custom_delay(notification_delay)
+                .context("Hotwatch failed to initialize")?,
+            created: Arc::new(Mutex::new(vec![])),
+            wakeup: wakeup.cloned(),
+            events: events.cloned(),
+        })
+    }
+}
+
+impl DirectoryMonitor for HotwatchDirectoryMonitor {
+    fn watch(&mut self, directory: &Path) -> Result<()> {
+        let created = Arc::clone(&self.created);
+        let wakeup = self.wakeup.clone();
+        let events = self.events.clone();
+
+        self.directory_watcher
+            .watch(directory, move |event| {
+                if let Some(events) = &events {
+                    events.record(&event);
+                }
+                match event {
+                    // Log rotation often renames a file into place, instead of creating it there
+                    Event::Create(path) | Event::Rename(_, path) => {
+                        created.lock().unwrap().push(path); // Unwrap is okay, since nothing panics while holding the lock
+                        if let Some(wakeup) = &wakeup {
+                            wakeup.notify();
+                        }
+                    }
+                    _ => {}
+                }
+            })
+            .context(format!("Failed to watch {:?}!", directory))
+    }
+
+    fn take_created(&mut self) -> Vec<PathBuf> {
+        std::mem::take(&mut *self.created.lock().unwrap()) // Unwrap is okay, since nothing panics while holding the lock
+    }
+
+    fn wakes_up(&self) -> bool {
+        self.wakeup.is_some()
+    }
+}
+
+// Compares the contents of the directory with what has been there before
+#[derive(Default)]
+pub struct PollingDirectoryMonitor {
+    directory: Option<PathBuf>,
+    known_paths: HashSet<PathBuf>,
+}
+
+impl PollingDirectoryMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn list(&self) -> Vec<PathBuf> {
+        let entries = match self.directory.as_ref().map(std::fs::read_dir) {
+            Some(Ok(entries)) => entries,
+            _ => return vec![],
+        };
+
+        let mut paths: Vec<_> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .collect();
+        paths.sort(); // Timestamped names show up in the order they have been created in
+        paths
+    }
+}
+
+impl DirectoryMonitor for PollingDirectoryMonitor {
+    fn watch(&mut self, directory: &Path) -> Result<()> {
+        std::fs::read_dir(directory).context(format!("Failed to watch {:?}!", directory))?;
+
+        self.directory = Some(directory.to_path_buf());
+        self.known_paths = self.list().into_iter().collect();
+        Ok(())
+    }
+
+    fn take_created(&mut self) -> Vec<PathBuf> {
+        let paths = self.list();
+        // Paths that have gone away are forgotten, so that they count as new if they show up again
+        self.known_paths.retain(|path| paths.contains(path));
+        paths
+            .into_iter()
+            .filter(|path| self.known_paths.insert(path.clone()))
+            .collect()
+    }
+}
+
+// Same as for files, Hotwatch unless told otherwise or unless it doesn't work here
+pub fn monitor_directory(
+    directory: &Path,
+    poll: bool,
+    notification_delay: Duration,
+    wakeup: Option<&Wakeup>,
+    events: Option<&EventLog>,
+) -> Result<Box<dyn DirectoryMonitor>> {
+    if !poll {
+        match HotwatchDirectoryMonitor::new(notification_delay, wakeup, events).and_then(
+            |mut directory_monitor| {
+                directory_monitor.watch(directory)?;
+                Ok(directory_monitor)
+            },
+        ) {
+            Ok(directory_monitor) => return Ok(Box::new(directory_monitor)),
+            Err(error) => eprintln!("tail: {:#}. Polling {:?} instead", error, directory),
+        }
+    }
+
+    let mut directory_monitor = PollingDirectoryMonitor::new();
+    directory_monitor.watch(directory)?;
+    Ok(Box::new(directory_monitor))
+}
+
+// Whether both belong to the same file on disk
+#[cfg(unix)]
+pub fn is_same_metadata(a: &Metadata, b: &Metadata) -> io::Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    Ok(a.dev() == b.dev() && a.ino() == b.ino())
+}
+
+// Windows doesn't give us a stable file ID, so go by the creation time instead
+#[cfg(not(unix))]
+pub fn is_same_metadata(a: &Metadata, b: &Metadata) -> io::Result<bool> {
+    Ok(a.created()? == b.created()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+
+    #[test]
+    fn test_polling_monitor() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let path = directory.path().join("polled.log");
+        std::fs::write(&path, "first\n")?;
+
+        let mut monitor = PollingMonitor::new();
+        monitor.watch(&path)?;
+        assert!(!monitor.take_changed());
+        assert!(!monitor.is_replaced());
+
+        let mut file = std::fs::OpenOptions::new().append(true).open(&path)?;
+        writeln!(file, "second")?;
+        assert!(monitor.take_changed());
+        assert!(!monitor.take_changed());
+
+        std::fs::rename(&path, directory.path().join("polled.log.1"))?;
+        assert!(monitor.is_replaced());
+        std::fs::write(&path, "new\n")?;
+        assert!(monitor.is_replaced());
+
+        // Watching the new file starts over
+        monitor.unwatch(&path);
+        monitor.watch(&path)?;
+        assert!(!monitor.is_replaced());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_hotwatch_wakes_up_on_changes_only() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let path = directory.path().join("quiet.log");
+        std::fs::write(&path, "first\n")?;
+
+        let wakeup = Wakeup::new();
+        let events = EventLog::new(false);
+        let mut monitor =
+            HotwatchMonitor::new(Duration::from_millis(10), Some(&wakeup), Some(&events))?;
+        monitor.watch(&path)?;
+        assert!(monitor.wakes_up());
+
+        // Nothing happens, so nothing wakes up
+        let quiet = Duration::from_millis(300);
+        let clock = std::time::Instant::now();
+        assert!(!wakeup.wait(Some(quiet)));
+        assert!(clock.elapsed() >= quiet);
+
+        let mut file = std::fs::OpenOptions::new().append(true).open(&path)?;
+        writeln!(file, "second")?;
+        assert!(wakeup.wait(Some(Duration::from_secs(5))));
+        assert!(monitor.take_changed());
+        // Counted before waking anybody up
+        assert!(events
+            .counts()
+            .iter()
+            .any(|&(name, count)| name == "Write" && count >= 1));
+
+        // Polling can't tell anybody
+        assert!(!PollingMonitor::new().wakes_up());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parent_directory_monitor() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let path = directory.path().join("app.log");
+
+        let wakeup = Wakeup::new();
+        let mut monitor =
+            ParentDirectoryMonitor::new(Duration::from_millis(10), Some(&wakeup), None)?;
+        // The file doesn't have to be there yet
+        monitor.watch(&path)?;
+
+        // Other files in the directory don't matter
+        std::fs::write(directory.path().join("other.log"), "other\n")?;
+        assert!(!wakeup.wait(Some(Duration::from_millis(300))));
+        assert!(!monitor.take_changed());
+        assert!(!monitor.is_replaced());
+
+        std::fs::write(&path, "first\n")?;
+        assert!(wakeup.wait(Some(Duration::from_secs(5))));
+        assert!(monitor.is_replaced());
+        monitor.clear_replaced();
+
+        let mut file = std::fs::OpenOptions::new().append(true).open(&path)?;
+        writeln!(file, "second")?;
+        let clock = std::time::Instant::now();
+        while !monitor.take_changed() {
+            assert!(clock.elapsed() < Duration::from_secs(5));
+            wakeup.wait(Some(Duration::from_millis(100)));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_event_log_counts() {
+        let events = EventLog::new(false);
+        let path = PathBuf::from("app.log");
+        events.record(&Event::Write(path.clone()));
+        events.record(&Event::Rename(path.clone(), PathBuf::from("app.log.1")));
+        events.clone().record(&Event::Write(path)); // Clones count into the same log
+
+        assert_eq!(events.counts(), [("Rename", 1), ("Write", 2)]);
+        assert!(EventLog::default().counts().is_empty());
+    }
+
+    // Stands in for a monitor that misses every event
+    struct DeafMonitor;
+
+    impl FileMonitor for DeafMonitor {
+        fn watch(&mut self, _path: &Path) -> Result<()> {
+            Ok(())
+        }
+        fn unwatch(&mut self, _path: &Path) {}
+        fn take_changed(&mut self) -> bool {
+            false
+        }
+        fn is_replaced(&mut self) -> bool {
+            false
+        }
+        fn clear_replaced(&mut self) {}
+    }
+
+    #[test]
+    fn test_unchanged_stats_monitor() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let path = directory.path().join("app.log");
+        std::fs::write(&path, "first\n")?;
+
+        let mut monitor = UnchangedStatsMonitor::new(Box::new(DeafMonitor), &path, 3)?;
+
+        // A write without an event is found on the third check
+        let mut file = std::fs::OpenOptions::new().append(true).open(&path)?;
+        writeln!(file, "second")?;
+        assert!(!monitor.take_changed());
+        assert!(!monitor.take_changed());
+        assert!(monitor.take_changed());
+        assert!(!monitor.take_changed());
+
+        // So is a new file swapped in silently
+        let new_path = directory.path().join("app.log.new");
+        std::fs::write(&new_path, "new\n")?;
+        std::fs::rename(&new_path, &path)?;
+        assert!(!monitor.take_changed());
+        assert!(!monitor.is_replaced());
+        assert!(!monitor.take_changed());
+        assert!(!monitor.take_changed());
+        assert!(monitor.is_replaced());
+        assert!(monitor.is_replaced());
+
+        // Watching the new file starts over
+        monitor.clear_replaced();
+        monitor.unwatch(&path);
+        monitor.watch(&path)?;
+        for _ in 0..3 {
+            assert!(!monitor.take_changed());
+        }
+        assert!(!monitor.is_replaced());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_polling_directory_monitor() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        std::fs::write(directory.path().join("app-1.log"), "old\n")?;
+
+        let mut monitor = PollingDirectoryMonitor::new();
+        monitor.watch(directory.path())?;
+        assert!(monitor.take_created().is_empty());
+
+        std::fs::write(directory.path().join("app-3.log"), "")?;
+        std::fs::write(directory.path().join("app-2.log"), "")?;
+        assert_eq!(
+            monitor.take_created(),
+            [
+                directory.path().join("app-2.log"),
+                directory.path().join("app-3.log")
+            ]
+        );
+        assert!(monitor.take_created().is_empty());
+
+        // Moved away and back again
+        std::fs::rename(
+            directory.path().join("app-1.log"),
+            directory.path().join("archived"),
+        )?;
+        assert_eq!(monitor.take_created(), [directory.path().join("archived")]);
+        std::fs::rename(
+            directory.path().join("archived"),
+            directory.path().join("app-1.log"),
+        )?;
+        assert_eq!(monitor.take_created(), [directory.path().join("app-1.log")]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,131 @@
+// With --on-match, a shell command is run for every new line matching --grep, e.g. to send an alert.
+//
+// The command runs with the same rights as tail itself, so it should only ever come from whoever runs tail. The matching line
+// is never pasted into the command, since anybody able to write to the file could inject commands that way. It is passed in
+// environment variables instead, which have to be quoted like any other shell variable, e.g. notify-send "$TAIL_LINE"
+
+use std::{
+    io,
+    path::Path,
+    process::{Child, Command, Stdio},
+};
+
+// A storm of matching lines shouldn't turn into a storm of processes
+pub const MAX_RUNNING: usize = 8;
+
+pub struct MatchCommand {
+    command: String,
+    running: Vec<Child>,
+    max_running: usize,
+    pub skipped_count: u64, // Matches that came in while too many commands were still running
+}
+
+impl MatchCommand {
+    pub fn new(command: &str, max_running: usize) -> Self {
+        Self {
+            command: command.to_string(),
+            running: vec![],
+            max_running,
+            skipped_count: 0,
+        }
+    }
+
+    // Doesn't wait for the command, so that a slow one doesn't hold up following
+    pub fn run(&mut self, line_number: u64, line: &str, source: &Path) -> io::Result<()> {
+        self.reap();
+        if self.running.len() >= self.max_running {
+            self.skipped_count += 1;
+            return Ok(());
+        }
+
+        let child = shell(&self.command)
+            .env("TAIL_LINE", line)
+            .env("TAIL_LINE_NUMBER", line_number.to_string())
+            .env("TAIL_FILE", source)
+            .stdin(Stdio::null())
+            .stdout(io::stderr()) // Whatever it prints would get mixed up with the lines otherwise
+            .spawn()?;
+        self.running.push(child);
+        Ok(())
+    }
+
+    // Commands that have finished don't count towards the limit anymore
+    fn reap(&mut self) {
+        self.running
+            .retain_mut(|child| matches!(child.try_wait(), Ok(None)));
+    }
+}
+
+#[cfg(unix)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(not(unix))]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::{Duration, Instant};
+
+    #[cfg(unix)]
+    #[test]
+    fn test_line_is_passed_along() -> anyhow::Result<()> {
+        let directory = tempfile::tempdir()?;
+        let output = directory.path().join("output");
+        let mut match_command = MatchCommand::new(
+            &format!(
+                "printf '%s|%s|%s' \"$TAIL_LINE_NUMBER\" \"$TAIL_FILE\" \"$TAIL_LINE\" > '{}'",
+                output.display()
+            ),
+            MAX_RUNNING,
+        );
+
+        // Quotes in the line stay part of it, instead of ending up in the command
+        match_command.run(7, "ERROR 'quoted'; exit 1", Path::new("app.log"))?;
+        for child in &mut match_command.running {
+            child.wait()?;
+        }
+
+        assert_eq!(
+            std::fs::read_to_string(output)?,
+            "7|app.log|ERROR 'quoted'; exit 1"
+        );
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_running_commands_are_limited() -> anyhow::Result<()> {
+        let mut match_command = MatchCommand::new("sleep 10", 2);
+
+        // Spawning doesn't wait for the commands to finish
+        let clock = Instant::now();
+        for line_number in 1..=5 {
+            match_command.run(line_number, "ERROR", Path::new("app.log"))?;
+        }
+        assert!(clock.elapsed() < Duration::from_secs(5));
+        assert_eq!(match_command.running.len(), 2);
+        assert_eq!(match_command.skipped_count, 3);
+
+        // Once they are done, there is room again
+        for child in &mut match_command.running {
+            child.kill()?;
+            child.wait()?;
+        }
+        match_command.command = "true".to_string();
+        match_command.run(6, "ERROR", Path::new("app.log"))?;
+        assert_eq!(match_command.running.len(), 1);
+        assert_eq!(match_command.skipped_count, 3);
+
+        Ok(())
+    }
+}
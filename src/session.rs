@@ -0,0 +1,2002 @@
+// Reading the files, and following them afterwards. run() makes sense of the arguments, and whatever the files have in
+// common ends up in a session, along with where their lines are printed
+
+use std::{
+    cell::{Cell, RefCell},
+    fs::File,
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use path_absolutize::*;
+use regex::Regex;
+use tail::monitor::{self, EventLog, FileMonitor, UnchangedStatsMonitor, Wakeup, WatchMode};
+use tail::{
+    auto_decompress, count_lines, cursor_at, decompressing_reader, detect_size_change,
+    follow_byte_stream, follow_stream, read_bytes, read_lines_seekable, read_matching_lines,
+    read_new_lines, sniff_compression, sniff_delimiter, sniff_encoding, stream_lines,
+    stream_lines_reversed, strip_line_ending, Compression, ContextState, FileError, Line,
+    LineCounter, LineFilter, LineFormat, LineNumbering, Position, ReadCursor, ReadingDirection,
+    SizeChange,
+};
+
+use crate::on_match::{self, MatchCommand};
+use crate::{
+    format_rows, has_expired, is_fifo, is_same_file, is_same_path, is_wanted_new_file, print_bytes,
+    print_count, print_header, print_lines, print_unfinished_runs, resolve_symlinks, signals,
+    skip_missed_frames, sleep_remaining_frame, stop_on_broken_pipe, truncate_after_match, tui,
+    validate_path, Heartbeat, LiveMarker, MergeBuffer, OpenMode, Output, OutputFormat,
+    OutputOptions, OutputQueue, RunningCount, ScreenWindow, Stats, StatsInterval, Tee, UsageError,
+    EXIT_UNTIL_NOT_FOUND, HOUSEKEEPING_INTERVAL,
+};
+
+pub(crate) struct Session<'a> {
+    pub(crate) clock: Instant,
+    pub(crate) frame_duration: Duration,
+    pub(crate) refresh_count: Cell<u128>,
+    pub(crate) interrupted: Arc<AtomicBool>,
+    pub(crate) wakeup: Wakeup, // Monitors that are told about changes wake the follow loop up
+    pub(crate) events: Option<EventLog>,
+
+    // What is read of every file, and how
+    pub(crate) byte_count: Option<u64>,
+    pub(crate) start_position: Position,
+    pub(crate) stop_position: Position,
+    pub(crate) reading_direction: ReadingDirection,
+    pub(crate) streamed_range: Option<(u64, Option<u64>)>, // Lines can be printed as soon as they have been read
+    pub(crate) tac_streamed: bool,
+    pub(crate) line_format: LineFormat,
+    pub(crate) detect_line_ending: bool,
+    pub(crate) line_filter: Option<&'a LineFilter>,
+    pub(crate) line_numbering: LineNumbering,
+    pub(crate) gzip: bool,
+    pub(crate) decompress: bool, // Unless told otherwise, compressed files are recognized by their first bytes
+    pub(crate) count_total: bool,
+
+    // How the files are followed afterwards
+    pub(crate) follow: bool,
+    pub(crate) follow_name: bool,
+    pub(crate) follow_symlinks: bool,
+    pub(crate) retry: bool,
+    pub(crate) retry_backoff: Backoff,
+    pub(crate) transient_retry: TransientRetry,
+    pub(crate) open_mode: OpenMode,
+    pub(crate) watch_mode: WatchMode,
+    pub(crate) notification_delay: Duration,
+    pub(crate) max_unchanged_stats: Option<u64>,
+    pub(crate) also: bool,
+    pub(crate) directory: Option<PathBuf>,
+    pub(crate) file_pattern: Option<glob::Pattern>,
+    pub(crate) output_path: Option<&'a Path>,
+    pub(crate) until: Option<Regex>,
+    pub(crate) pid: Option<u32>,
+    pub(crate) reopen_signal: bool,
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) timeout_exit_code: Option<i32>,
+    pub(crate) heartbeat: Option<Duration>,
+    pub(crate) stats_interval: Option<Duration>,
+    pub(crate) stats: bool,
+
+    // Where the lines end up
+    pub(crate) output_options: OutputOptions<'a>,
+    pub(crate) fresh_output_options: OutputOptions<'a>, // Lines arriving while following stand out from the initial ones
+    pub(crate) stdout: RefCell<Output<Tee>>,
+    pub(crate) emitted: Stats,
+    pub(crate) count_only: bool,
+    pub(crate) viewer: Option<RefCell<tui::Viewer>>,
+    pub(crate) screen: Option<RefCell<ScreenWindow>>,
+    pub(crate) live_marker: Option<RefCell<LiveMarker>>,
+    pub(crate) running_count: RefCell<RunningCount>,
+    pub(crate) match_command: Option<RefCell<MatchCommand>>,
+    pub(crate) merge: Option<RefCell<MergeBuffer>>,
+    pub(crate) output_queue: Option<RefCell<OutputQueue>>,
+    pub(crate) until_found: Cell<bool>,
+}
+
+impl Session<'_> {
+    // Read all files, and follow them afterwards if wanted
+    pub(crate) fn tail(&self, file_names: &[&str]) -> Result<()> {
+        if file_names == ["-"] && self.follow {
+            self.follow_pipe(&mut io::stdin().lock(), Path::new("-"), "stdin")?;
+            return self.finish();
+        }
+
+        let mut watched_files = vec![];
+        let mut streamed_input = None;
+        for &file_name in file_names {
+            if file_name == "-" {
+                if self.follow && self.also {
+                    streamed_input = Some(StreamedInput::spawn(
+                        io::stdin(),
+                        Path::new(file_name),
+                        self.line_format,
+                        &self.wakeup,
+                    ));
+                    continue;
+                }
+                if self.follow {
+                    return Err(UsageError(
+                        "Standard input can only be followed on its own, not together with files, unless they are given with --also"
+                            .to_string(),
+                    )
+                    .into());
+                }
+                if self.decompress {
+                    self.print_stream(
+                        &mut auto_decompress(io::stdin().lock())
+                            .context("Unable to decompress standard input")?,
+                        Path::new("-"),
+                    )?;
+                } else {
+                    self.print_stream(&mut io::stdin().lock(), Path::new("-"))?;
+                }
+                continue;
+            }
+
+            let file_path = match self.find_file(file_name)? {
+                Some(file_path) => file_path,
+                None => return self.finish(), // Interrupted before the file showed up
+            };
+            if let Some(watched_file) = self.read_file(file_name, file_path)? {
+                watched_files.push(watched_file);
+            }
+            if self.until_found.get() {
+                break; // Already there, so there is no need to follow anything
+            }
+        }
+        if let Some(merge) = &self.merge {
+            let lines = merge.borrow_mut().take_all();
+            self.show_queued(lines, &self.output_options)
+                .context("Unable to write to stdout")?;
+        }
+        self.redraw().context("Unable to write to stdout")?;
+
+        if self.follow && !self.until_found.get() {
+            self.follow_files(watched_files, streamed_input)?;
+        }
+
+        self.finish()
+    }
+
+    pub(crate) fn show_lines(
+        &self,
+        lines: Vec<Line>,
+        reading_direction: ReadingDirection,
+        source: &Path,
+        options: &OutputOptions,
+    ) -> io::Result<()> {
+        self.emitted.count_lines(&lines);
+        if self.count_only {
+            // While following, the running count is updated once per frame instead
+            if self.follow {
+                return Ok(());
+            }
+            let mut stdout = self.stdout.borrow_mut();
+            print_count(&mut *stdout, lines.len() as u64, Some(source), options)?;
+            return stdout.end_batch();
+        }
+        if let Some(viewer) = &self.viewer {
+            // The view adds the ages itself, as they keep changing
+            let options = &OutputOptions {
+                age: options.age.filter(|_| options.format != OutputFormat::Text),
+                ..*options
+            };
+            let rows = format_rows(lines, reading_direction, source, options);
+            viewer.borrow_mut().scrollback.push(rows);
+            return Ok(());
+        }
+        match &self.screen {
+            Some(screen) => {
+                screen
+                    .borrow_mut()
+                    .push(lines, reading_direction, source, options);
+                Ok(())
+            }
+            None => {
+                let mut stdout = self.stdout.borrow_mut();
+                if let Some(live_marker) = &self.live_marker {
+                    live_marker
+                        .borrow_mut()
+                        .print(&mut *stdout, &lines, options)?;
+                }
+                print_lines(
+                    &mut *stdout,
+                    lines,
+                    reading_direction,
+                    Some(source),
+                    options,
+                )?;
+                // Without following, these are all the lines of the file, so its runs end here
+                if !self.follow {
+                    print_unfinished_runs(&mut *stdout, options)?;
+                }
+                stdout.end_batch()
+            }
+        }
+    }
+
+    fn show_bytes(&self, bytes: &[u8], source: &Path, options: &OutputOptions) -> io::Result<()> {
+        self.emitted.count_bytes(bytes);
+        let mut stdout = self.stdout.borrow_mut();
+        print_header(&mut *stdout, Some(source), options)?;
+        print_bytes(&mut *stdout, bytes)?;
+        stdout.end_batch()
+    }
+
+    // Lines that have been queued up or merged, already in the order they are printed in
+    fn show_queued(&self, queued: Vec<(PathBuf, Line)>, options: &OutputOptions) -> io::Result<()> {
+        // Lines of the same file are printed together, so that they share a header
+        let mut queued = queued.into_iter().peekable();
+        while let Some((source, line)) = queued.next() {
+            let mut lines = vec![line];
+            while let Some((_, line)) = queued.next_if(|(next_source, _)| *next_source == source) {
+                lines.push(line);
+            }
+            self.show_lines(lines, ReadingDirection::TopToBottom, &source, options)?;
+        }
+        Ok(())
+    }
+
+    fn redraw(&self) -> io::Result<()> {
+        if self.count_only && self.follow {
+            let mut stdout = self.stdout.borrow_mut();
+            self.running_count
+                .borrow_mut()
+                .update(&mut *stdout, self.emitted.lines.get())?;
+            return stdout.end_batch();
+        }
+        if let Some(viewer) = &self.viewer {
+            return viewer.borrow_mut().redraw();
+        }
+        match &self.screen {
+            Some(screen) => screen
+                .borrow_mut()
+                .redraw(&mut *self.stdout.borrow_mut(), &self.output_options),
+            None => Ok(()),
+        }
+    }
+
+    // Once following has stopped by itself, the view stays open until it's closed. Messages after this are only readable
+    // on the restored terminal
+    fn close_viewer(&self) -> Result<()> {
+        if let Some(viewer) = &self.viewer {
+            let mut viewer = viewer.borrow_mut();
+            if !self.interrupted.load(Ordering::SeqCst) {
+                viewer
+                    .wait_for_quit()
+                    .context("Unable to read keys from the terminal")?;
+            }
+            viewer.close().context("Unable to restore the terminal")?;
+        }
+        Ok(())
+    }
+
+    // Stats are only printed once everything else has been, so they end up last
+    fn finish(&self) -> Result<()> {
+        self.close_viewer()?;
+        self.running_count
+            .borrow_mut()
+            .finish(&mut *self.stdout.borrow_mut())
+            .context("Unable to write to stdout")?;
+        if self.viewer.is_none() {
+            print_unfinished_runs(&mut *self.stdout.borrow_mut(), &self.output_options)
+                .context("Unable to write to stdout")?;
+        }
+        let flushed = self.stdout.borrow_mut().flush();
+        if let Some(match_command) = &self.match_command {
+            let skipped_count = match_command.borrow().skipped_count;
+            if skipped_count > 0 {
+                eprintln!(
+                    "tail: skipped the --on-match command for {} lines, since {} commands were still running",
+                    skipped_count,
+                    on_match::MAX_RUNNING
+                );
+            }
+        }
+        if self.stats {
+            eprintln!("tail: {}", self.emitted.summary(self.clock.elapsed()));
+            let event_counts = self
+                .events
+                .as_ref()
+                .map(EventLog::counts)
+                .unwrap_or_default();
+            if !event_counts.is_empty() {
+                eprintln!(
+                    "tail: received watch events: {}",
+                    event_counts
+                        .iter()
+                        .map(|(name, count)| format!("{} {}", count, name))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+            }
+        }
+        flushed.context("Unable to write to stdout")
+    }
+
+    // Context lines don't count, only actual matches do. Failing to start the command is no reason to stop following
+    fn run_on_match(&self, lines: &[Line], reading_direction: ReadingDirection, source: &Path) {
+        let (match_command, line_filter) = match (&self.match_command, self.line_filter) {
+            (Some(match_command), Some(line_filter)) => (match_command, line_filter),
+            _ => return,
+        };
+
+        let mut lines: Vec<_> = lines
+            .iter()
+            .filter(|(_, line)| line_filter.matches(line))
+            .collect();
+        if reading_direction == ReadingDirection::BottomToTop {
+            lines.reverse();
+        }
+        for (line_number, line) in lines {
+            if let Err(error) =
+                match_command
+                    .borrow_mut()
+                    .run(*line_number, strip_line_ending(line), source)
+            {
+                eprintln!("tail: unable to run the --on-match command: {}", error);
+            }
+        }
+    }
+
+    fn sleep_remaining_frame(&self) {
+        let mut refresh_count = self.refresh_count.get();
+        sleep_remaining_frame(self.clock, &mut refresh_count, self.frame_duration);
+        self.refresh_count.set(refresh_count);
+    }
+
+    fn skip_missed_frames(&self) {
+        let mut refresh_count = self.refresh_count.get();
+        skip_missed_frames(self.clock, &mut refresh_count, self.frame_duration);
+        self.refresh_count.set(refresh_count);
+    }
+
+    // Data that can only be read from front to back, like stdin or compressed files, is read in a single pass
+    fn print_stream(&self, input: &mut dyn Read, source: &Path) -> Result<()> {
+        if self.byte_count.is_some() {
+            let mut buffer = vec![];
+            input
+                .read_to_end(&mut buffer)
+                .context(format!("Unable to read from {:?}", source))?;
+            let bytes = read_bytes(
+                io::Cursor::new(buffer),
+                self.start_position,
+                self.stop_position,
+                self.reading_direction,
+            )?;
+            self.show_bytes(&bytes, source, &self.output_options)
+                .context("Unable to write to stdout")?;
+        } else {
+            // The lines are counted on the way, instead of reading the input a second time
+            let mut input = LineCounter::new(input, self.line_format.delimiter);
+            let lines = read_matching_lines(
+                &mut input,
+                self.start_position,
+                self.stop_position,
+                self.reading_direction,
+                self.line_format,
+                self.line_filter,
+            )?;
+            if self.count_total {
+                // Reading stops early with --head, but the rest of the input still counts
+                io::copy(&mut input, &mut io::sink())
+                    .context(format!("Unable to read from {:?}", source))?;
+                eprintln!("{}: {} lines", source.display(), input.count());
+            }
+            if let Some(relative_numbers) = self.output_options.relative_numbers {
+                relative_numbers
+                    .borrow_mut()
+                    .set_end(&source.to_string_lossy(), &lines, None);
+            }
+            self.show_lines(lines, self.reading_direction, source, &self.output_options)
+                .context("Unable to write to stdout")?;
+        }
+        Ok(())
+    }
+
+    // Pipes can neither be seeked nor watched, so they get their own, simpler handling. There is no end to wait for, so
+    // everything is passed along as soon as it arrives
+    fn follow_pipe(&self, input: &mut dyn Read, source: &Path, description: &str) -> Result<()> {
+        let emitted_count = self
+            .pass_along(input, source, &mut ReadCursor::default())
+            .context(format!("Unable to pass {} along to stdout", description))?;
+
+        eprintln!(
+            "tail: stopped following {} after printing {} {}",
+            description,
+            emitted_count,
+            if self.byte_count.is_some() {
+                "bytes"
+            } else {
+                "lines"
+            }
+        );
+        Ok(())
+    }
+
+    // Everything up to the end of the input, as soon as it arrives. The cursor is left at the last line that has been read.
+    // Returns how many lines or bytes have been read
+    fn pass_along(
+        &self,
+        input: &mut dyn Read,
+        source: &Path,
+        cursor: &mut ReadCursor,
+    ) -> Result<u64> {
+        if self.byte_count.is_some() {
+            return Ok(follow_byte_stream(input, &self.interrupted, |bytes| {
+                self.show_bytes(bytes, source, &self.output_options)
+            })?);
+        }
+
+        let mut context_state = ContextState::default();
+        Ok(follow_stream(
+            input,
+            self.line_format,
+            &self.interrupted,
+            |mut lines| {
+                if let Some((line_number, _)) = lines.last() {
+                    cursor.line_number = *line_number;
+                }
+                if let Some(line_filter) = self.line_filter {
+                    lines = line_filter.select(lines, &mut context_state);
+                }
+                if truncate_after_match(
+                    &mut lines,
+                    self.until.as_ref(),
+                    ReadingDirection::TopToBottom,
+                ) {
+                    self.interrupted.store(true, Ordering::SeqCst); // Nothing more to wait for
+                }
+                self.run_on_match(&lines, ReadingDirection::TopToBottom, source);
+                self.show_lines(
+                    lines,
+                    ReadingDirection::TopToBottom,
+                    source,
+                    &self.fresh_output_options,
+                )?;
+                self.redraw()
+            },
+        )?)
+    }
+
+    // The path of the file, waiting for it with --retry. Returns nothing if interrupted before the file showed up
+    fn find_file(&self, file_name: &str) -> Result<Option<PathBuf>> {
+        let waiting = match validate_path(file_name, self.follow_symlinks, self.open_mode) {
+            Ok(path) => return Ok(Some(path)),
+            Err(FileError::Access { path, source }) if self.retry => {
+                eprintln!(
+                    "tail: waiting for {:?} to become accessible: {}",
+                    path, source
+                );
+                path
+            }
+            // Might still be replaced by a file, so this is worth waiting for, too, like GNU tail does
+            Err(FileError::Directory { path }) if self.retry => {
+                eprintln!("tail: waiting for {:?} to become a file", path);
+                path
+            }
+            Err(error) => return Err(error.into()),
+        };
+
+        let found = wait_for_file(
+            &waiting,
+            self.open_mode,
+            &self.interrupted,
+            &mut self.retry_backoff.clone(),
+            || self.sleep_remaining_frame(),
+        );
+        Ok(found.map(|_| {
+            // A dangling symlink only has something to resolve to now
+            if self.follow_symlinks {
+                resolve_symlinks(waiting)
+            } else {
+                waiting
+            }
+        }))
+    }
+
+    // Read the file once. If it is followed afterwards, returns what following it needs to know
+    fn read_file(&self, file_name: &str, file_path: PathBuf) -> Result<Option<WatchedFile>> {
+        let source = Path::new(file_name);
+
+        // A named pipe only has what its writers send from now on, so it is always followed, until the last writer closes it.
+        // Opening it waits for the first writer to show up
+        if is_fifo(&file_path) {
+            let mut fifo = File::open(&file_path).map_err(|error| FileError::Access {
+                path: file_path.clone(),
+                source: error,
+            })?;
+            self.follow_pipe(&mut fifo, source, &format!("{:?}", file_path))?;
+            return Ok(None);
+        }
+
+        // Read once, and then monitor if wanted
+        let mut file = self
+            .open_mode
+            .open(&file_path)
+            .map_err(|error| FileError::Access {
+                path: file_path.clone(),
+                source: error,
+            })?;
+
+        // Devices have no end to seek to, so like pipes, they are read for as long as they have something to say. Files
+        // generated on every read, like the ones in /proc, don't have a size to go by, so they are read in one go, too
+        let file_kind =
+            file_kind(&mut file).context(format!("Unable to read from {:?}", file_path))?;
+        if file_kind != FileKind::Regular && !self.follow {
+            self.print_stream(&mut file, source)?;
+            return Ok(None);
+        }
+        if file_kind == FileKind::Device {
+            // Until the device has nothing more to say for now. It might have later on, though, so from then on, it's read
+            // every frame. There's no going back to its beginning to sniff the encoding
+            let mut cursor = ReadCursor::default();
+            let mut device = DeviceReader::new(&mut file, 0);
+            self.pass_along(&mut device, source, &mut cursor)
+                .context(format!("Unable to pass {:?} along to stdout", file_path))?;
+            cursor.offset = device.position;
+            return self
+                .watch_file(
+                    file_name,
+                    file_path,
+                    file,
+                    self.line_format,
+                    cursor,
+                    file_kind,
+                )
+                .map(Some);
+        }
+
+        let compression = if self.gzip {
+            Some(Compression::Gzip)
+        } else if !self.decompress || file_kind == FileKind::Generated {
+            None
+        } else {
+            sniff_compression(&mut file).context(format!("Unable to read from {:?}", file_path))?
+        };
+        if let Some(compression) = compression {
+            if self.follow {
+                return Err(UsageError(format!(
+                    "{:?} is compressed, so it can't be followed. Anything appended to it wouldn't make sense before decompressing it as a whole",
+                    file_path
+                )).into());
+            }
+            self.print_stream(
+                &mut decompressing_reader(file, compression)
+                    .context(format!("Unable to decompress {:?}", file_path))?,
+                source,
+            )?;
+            return Ok(None);
+        }
+
+        // Later reads start in the middle of the file, where there is no byte order mark to tell the encoding
+        let line_format = LineFormat {
+            encoding: sniff_encoding(&mut file, self.line_format.encoding)
+                .context(format!("Unable to read from {:?}", file_path))?,
+            delimiter: if self.detect_line_ending {
+                sniff_delimiter(&mut file)
+                    .context(format!("Unable to read from {:?}", file_path))?
+            } else {
+                self.line_format.delimiter
+            },
+            ..self.line_format
+        };
+
+        if self.count_total && file_kind != FileKind::Generated {
+            let count = count_lines(&mut file, line_format.delimiter)
+                .context(format!("Unable to count the lines of {:?}", file_path))?;
+            eprintln!("{}: {} lines", file_name, count);
+        }
+
+        let cursor = if file_kind == FileKind::Generated {
+            // Following picks up wherever this first read has stopped
+            self.print_stream(&mut file, source)?;
+            let end = file
+                .stream_position()
+                .context(format!("Unable to get read position in {:?}", file_path))?;
+            if self.byte_count.is_some() {
+                ReadCursor {
+                    offset: end,
+                    ..ReadCursor::default()
+                }
+            } else {
+                cursor_at(&mut file, end, line_format)?
+            }
+        } else {
+            match self.read_seekable_file(&mut file, file_name, &file_path, line_format)? {
+                Some(cursor) => cursor,
+                None => return Ok(None), // Already there, so there is no need to follow anything
+            }
+        };
+
+        if !self.follow {
+            return Ok(None);
+        }
+        self.watch_file(file_name, file_path, file, line_format, cursor, file_kind)
+            .map(Some)
+    }
+
+    // Following the file picks up where the cursor is
+    fn watch_file(
+        &self,
+        file_name: &str,
+        file_path: PathBuf,
+        file: File,
+        line_format: LineFormat,
+        cursor: ReadCursor,
+        kind: FileKind,
+    ) -> Result<WatchedFile> {
+        let file_monitor = self.monitor_file(&file_path)?;
+
+        // Log rotation might point the symlink at another file later on
+        let link = match Path::new(file_name).absolutize() {
+            Ok(path) if self.follow_symlinks && self.follow_name && path.is_symlink() => {
+                Some(path.into_owned())
+            }
+            _ => None,
+        };
+
+        Ok(WatchedFile {
+            name: PathBuf::from(file_name),
+            path: file_path,
+            link,
+            file,
+            file_monitor,
+            line_format,
+            line_numbering: self.line_numbering.clone(),
+            cursor,
+            waiting_for_file: false,
+            retry_backoff: self.retry_backoff.clone(),
+            transient_retry: self.transient_retry.clone(),
+            reread: true, // Whatever has been written before watching started isn't announced
+            kind,
+        })
+    }
+
+    // The wanted lines or bytes of a file that can be seeked. Returns where following continues, or nothing if --until has
+    // already been found
+    fn read_seekable_file(
+        &self,
+        file: &mut File,
+        file_name: &str,
+        file_path: &Path,
+        line_format: LineFormat,
+    ) -> Result<Option<ReadCursor>> {
+        let source = Path::new(file_name);
+        if self.byte_count.is_some() {
+            let bytes = read_bytes(
+                &mut *file,
+                self.start_position,
+                self.stop_position,
+                self.reading_direction,
+            )?;
+            self.show_bytes(&bytes, source, &self.output_options)
+                .context("Unable to write to stdout")?;
+            // Following continues at the end of the file, no matter which bytes have been printed
+            return Ok(Some(ReadCursor {
+                offset: file
+                    .seek(SeekFrom::End(0))
+                    .context(format!("Unable to get the size of {:?}", file_path))?,
+                ..ReadCursor::default()
+            }));
+        }
+
+        let reading_direction = self.reading_direction;
+        let (start_position, stop_position) = (self.start_position, self.stop_position);
+        // Matches can be anywhere in the file, so jumping straight to the last lines doesn't help when filtering
+        let mut lines = match (self.line_filter, self.streamed_range) {
+            (None, _) if self.tac_streamed => {
+                // Already in the order they are printed in
+                let options = OutputOptions {
+                    reverse_output: false,
+                    ..self.output_options
+                };
+                stream_lines_reversed(&mut *file, line_format, |lines| {
+                    self.show_lines(lines, ReadingDirection::TopToBottom, source, &options)
+                        .context("Unable to write to stdout")
+                })?;
+                vec![] // All of them have been printed already
+            }
+            (_, Some((start, stop))) => {
+                stream_lines(
+                    &mut *file,
+                    start,
+                    stop,
+                    line_format,
+                    self.line_filter,
+                    |lines| {
+                        self.show_lines(
+                            lines,
+                            ReadingDirection::TopToBottom,
+                            source,
+                            &self.output_options,
+                        )
+                        .context("Unable to write to stdout")
+                    },
+                )?;
+                vec![] // All of them have been printed already
+            }
+            (Some(line_filter), None) => read_matching_lines(
+                &mut *file,
+                start_position,
+                stop_position,
+                reading_direction,
+                line_format,
+                Some(line_filter),
+            )?,
+            (None, None) => read_lines_seekable(
+                &mut *file,
+                start_position,
+                stop_position,
+                reading_direction,
+                line_format,
+            )?,
+        };
+        let last_read_line = match reading_direction {
+            ReadingDirection::TopToBottom => lines.last().cloned(),
+            ReadingDirection::BottomToTop => lines.first().cloned(),
+        };
+        if self.follow && truncate_after_match(&mut lines, self.until.as_ref(), reading_direction) {
+            self.until_found.set(true);
+        }
+        if let Some(relative_numbers) = self.output_options.relative_numbers {
+            relative_numbers
+                .borrow_mut()
+                .set_end(file_name, &lines, last_read_line.as_ref());
+        }
+        match &self.merge {
+            // The files are only merged once all of them have been read
+            Some(merge) => {
+                merge
+                    .borrow_mut()
+                    .push(lines, reading_direction, source, Instant::now())
+            }
+            None => self
+                .show_lines(lines, reading_direction, source, &self.output_options)
+                .context("Unable to write to stdout")?,
+        }
+        if self.until_found.get() {
+            return Ok(None);
+        }
+
+        // Unless only the first lines have been read, the file has been read up to its end. That's where following
+        // continues, even if more has been written since. With -n 0, nothing has been read at all, but following still
+        // starts at the end
+        let end = match stop_position {
+            Position::FromBegin(_) => file.seek(SeekFrom::End(0)),
+            Position::FromEnd(_) if start_position == stop_position => file.seek(SeekFrom::End(0)),
+            Position::FromEnd(_) => file.stream_position(),
+        }
+        .context(format!("Unable to get read position in {:?}", file_path))?;
+        Ok(Some(
+            if self.follow
+                && (reading_direction == ReadingDirection::TopToBottom
+                    || self.line_filter.is_some()
+                    || last_read_line.is_none())
+            {
+                // Only the first lines, only matching ones or none at all have been read. New lines will show up at the
+                // end, though, so skip ahead to the last line
+                cursor_at(&mut *file, end, line_format)?
+            } else {
+                ReadCursor::after(last_read_line.as_ref(), end, line_format.delimiter)
+            },
+        ))
+    }
+
+    fn monitor_file(&self, path: &Path) -> Result<Box<dyn FileMonitor>> {
+        let file_monitor = monitor::monitor_file(
+            path,
+            self.watch_mode,
+            self.notification_delay,
+            Some(&self.wakeup),
+            self.events.as_ref(),
+        )?;
+        Ok(match self.max_unchanged_stats {
+            Some(max_unchanged_stats) => Box::new(UnchangedStatsMonitor::new(
+                file_monitor,
+                path,
+                max_unchanged_stats,
+            )?),
+            None => file_monitor,
+        })
+    }
+
+    // New files are read from their beginning, so that whatever has been written along with creating them is printed, too.
+    // Returns nothing if the file is followed already, e.g. because it has just been renamed
+    fn follow_new_file(
+        &self,
+        path: PathBuf,
+        watched_files: &[WatchedFile],
+    ) -> Result<Option<WatchedFile>> {
+        let mut file = self
+            .open_mode
+            .open(&path)
+            .map_err(|error| FileError::Access {
+                path: path.clone(),
+                source: error,
+            })?;
+        if watched_files.iter().any(|watched_file| {
+            watched_file.path == path || is_same_file(&watched_file.file, &file).unwrap_or(false)
+        }) {
+            return Ok(None);
+        }
+        // The output would be read back in, again and again
+        if self
+            .output_path
+            .is_some_and(|output_path| is_same_path(&path, output_path))
+        {
+            return Ok(None);
+        }
+
+        let line_format = LineFormat {
+            encoding: sniff_encoding(&mut file, self.line_format.encoding)
+                .context(format!("Unable to read from {:?}", path))?,
+            delimiter: if self.detect_line_ending {
+                sniff_delimiter(&mut file).context(format!("Unable to read from {:?}", path))?
+            } else {
+                self.line_format.delimiter
+            },
+            ..self.line_format
+        };
+        let file_monitor = self.monitor_file(&path)?;
+
+        Ok(Some(WatchedFile {
+            name: path.clone(),
+            path,
+            link: None,
+            file,
+            file_monitor,
+            line_format,
+            line_numbering: self.line_numbering.clone(),
+            cursor: ReadCursor::default(),
+            waiting_for_file: false,
+            retry_backoff: self.retry_backoff.clone(),
+            transient_retry: self.transient_retry.clone(),
+            reread: true,
+            kind: FileKind::Regular,
+        }))
+    }
+
+    // New lines go wherever they have to wait before being printed, if anywhere
+    fn pass_on_lines(
+        &self,
+        mut lines: Vec<Line>,
+        direction: ReadingDirection,
+        source: &Path,
+    ) -> Result<()> {
+        if truncate_after_match(&mut lines, self.until.as_ref(), direction) {
+            self.until_found.set(true);
+        }
+        self.run_on_match(&lines, direction, source);
+        match (&self.output_queue, &self.merge) {
+            (Some(output_queue), _) => output_queue.borrow_mut().push(lines, direction, source),
+            (None, Some(merge)) => {
+                merge
+                    .borrow_mut()
+                    .push(lines, direction, source, Instant::now())
+            }
+            (None, None) if !lines.is_empty() => {
+                self.show_lines(lines, direction, source, &self.fresh_output_options)
+                    .context("Unable to write to stdout")?;
+            }
+            (None, None) => {}
+        }
+        Ok(())
+    }
+
+    // Everything from the cursor up to the end of the file
+    fn read_new_data(
+        &self,
+        mut data: impl Read + Seek,
+        cursor: &mut ReadCursor,
+        line_numbering: &mut LineNumbering,
+        line_format: LineFormat,
+        name: &Path,
+    ) -> Result<()> {
+        if self.byte_count.is_some() {
+            // Read up to wherever the data ends, since generated files and devices have no size to tell
+            let mut bytes = vec![];
+            data.seek(SeekFrom::Start(cursor.offset))
+                .and_then(|_| data.read_to_end(&mut bytes))
+                .map_err(|error| FileError::ReadBytes {
+                    offset: cursor.offset,
+                    source: error,
+                })?;
+            cursor.offset += bytes.len() as u64;
+            if !bytes.is_empty() {
+                self.show_bytes(&bytes, name, &self.fresh_output_options)
+                    .context("Unable to write to stdout")?;
+            }
+        } else {
+            let lines = read_new_lines(
+                data,
+                cursor,
+                self.reading_direction,
+                line_numbering,
+                self.line_filter,
+                line_format,
+            )?;
+            self.pass_on_lines(lines, self.reading_direction, name)?;
+        }
+        Ok(())
+    }
+
+    // Returns whether the file is still worth following
+    fn follow_file(&self, watched_file: &mut WatchedFile, reopen_signaled: bool) -> Result<bool> {
+        let WatchedFile {
+            name,
+            path: file_path,
+            link,
+            file,
+            file_monitor,
+            line_format,
+            line_numbering,
+            cursor,
+            waiting_for_file,
+            retry_backoff,
+            transient_retry: _,
+            reread,
+            kind,
+        } = watched_file;
+
+        // A symlink that points to another file now counts as replaced, too. Until the new file exists, there's nothing to switch to
+        let mut retargeted = false;
+        if let Some(target) = link.as_ref().and_then(|link| link.canonicalize().ok()) {
+            if target != *file_path {
+                eprintln!(
+                    "tail: {:?} now points to {:?}; following new file",
+                    link.as_ref().unwrap(), // Unwrap is safe, since the target came from it
+                    target
+                );
+                file_monitor.unwatch(file_path);
+                *file_path = target;
+                retargeted = true;
+            }
+        }
+
+        // Following by descriptor, a file that has been renamed away is still followed, like after log rotation. An editor
+        // saving the file renames a new one over it, though, and then nothing is ever going to be written to the old one
+        let saved_over =
+            !self.follow_name && file_monitor.is_replaced() && is_saved_over(file, file_path);
+
+        // Reopen by name if an external log rotator asked us to, or if the file has been replaced while following by name
+        // While the file is missing, looking for it again can wait a little longer every time
+        if (reopen_signaled
+            || retargeted
+            || saved_over
+            || (self.follow_name && file_monitor.is_replaced()))
+            && (!*waiting_for_file || reopen_signaled || retry_backoff.is_due())
+        {
+            match self.open_mode.open(file_path) {
+                Ok(new_file)
+                    if !reopen_signaled && is_same_file(file, &new_file).unwrap_or(false) =>
+                {
+                    // False alarm, e.g. the new file has already been picked up before its creation was announced
+                    file_monitor.clear_replaced();
+                }
+                Ok(new_file) => {
+                    if reopen_signaled {
+                        eprintln!("tail: reopening {:?}", file_path);
+                    } else if saved_over {
+                        eprintln!(
+                            "tail: {:?} has been saved over; following new file",
+                            file_path
+                        );
+                    } else if !retargeted {
+                        eprintln!(
+                            "tail: {:?} has been replaced; following new file",
+                            file_path
+                        );
+                    }
+                    // What has been written to the old file right before it has been rotated away would be lost otherwise.
+                    // A file that has been saved over doesn't have anything new, just another version
+                    if !saved_over
+                        && detect_size_change(file, cursor.offset).ok() == Some(SizeChange::Grown)
+                    {
+                        self.read_new_data(&mut *file, cursor, line_numbering, *line_format, name)?;
+                    }
+                    *file = new_file;
+                    file_monitor.clear_replaced();
+                    *waiting_for_file = false;
+                    *cursor = ReadCursor::default(); // The new file is read from its beginning, so numbering starts over
+
+                    // The watcher is still attached to the old file, so point it at the new one
+                    file_monitor.unwatch(file_path);
+                    file_monitor.watch(file_path)?;
+                    *reread = true;
+                }
+                Err(error) => {
+                    if !self.follow_name || !file_monitor.is_replaced() {
+                        eprintln!(
+                            "tail: unable to reopen {:?}, continuing with the old file: {}",
+                            file_path, error
+                        );
+                    } else if self.retry {
+                        if !*waiting_for_file {
+                            eprintln!(
+                                "tail: {:?} has become inaccessible, waiting for it to reappear",
+                                file_path
+                            );
+                            *waiting_for_file = true;
+                            retry_backoff.reset();
+                            retry_backoff.is_due(); // This has been the first attempt
+                        } else if retry_backoff.take_grown() {
+                            eprintln!(
+                                "tail: still waiting for {:?}, looking again in {:?}",
+                                file_path, retry_backoff.interval
+                            );
+                        }
+                    } else {
+                        eprintln!("tail: {:?} has become inaccessible: {}", file_path, error);
+                        return Ok(false);
+                    }
+                }
+            }
+        }
+
+        // If the output can't keep up, new lines are left in the file for now, unless they have to be read right away
+        if !*reread
+            && self
+                .output_queue
+                .as_ref()
+                .is_some_and(|output_queue| output_queue.borrow().is_full())
+        {
+            return Ok(true);
+        }
+
+        // Monitor file. Generated files and devices change without anything announcing it, so they are polled by reading them
+        let polled = *kind != FileKind::Regular;
+        if file_monitor.take_changed() || std::mem::take(reread) || polled {
+            if !polled {
+                match detect_size_change(file, cursor.offset)
+                    .context(format!("Unable to check the size of {:?}", file_path))?
+                {
+                    SizeChange::Unchanged => return Ok(true),
+                    SizeChange::Truncated => {
+                        eprintln!("tail: {:?}: file truncated", file_path);
+                        *cursor = ReadCursor::default();
+                    }
+                    SizeChange::Grown => {}
+                }
+            }
+
+            if *kind == FileKind::Device {
+                let device = DeviceReader::new(file, cursor.offset);
+                self.read_new_data(device, cursor, line_numbering, *line_format, name)?;
+            } else {
+                self.read_new_data(&mut *file, cursor, line_numbering, *line_format, name)?;
+            }
+        }
+
+        Ok(true)
+    }
+
+    // Monitor continuously, until interrupted or there is nothing left to follow
+    fn follow_files(
+        &self,
+        mut watched_files: Vec<WatchedFile>,
+        mut streamed_input: Option<StreamedInput>,
+    ) -> Result<()> {
+        let reopen_requested = Arc::new(AtomicBool::new(false));
+        if self.reopen_signal {
+            signals::register_reopen(&reopen_requested)
+                .context("Failed to register handler for the reopen signal")?;
+        }
+
+        let mut directory_monitor = match &self.directory {
+            Some(directory) => Some(monitor::monitor_directory(
+                directory,
+                self.watch_mode == WatchMode::Poll,
+                self.notification_delay,
+                Some(&self.wakeup),
+                self.events.as_ref(),
+            )?),
+            None => None,
+        };
+        let mut heartbeat = self.heartbeat.map(Heartbeat::new);
+        let mut stats_interval = self.stats_interval.map(StatsInterval::new);
+
+        let mut timed_out = false;
+        let followed = match (&watched_files[..], &self.directory) {
+            ([watched_file], None) => format!("{:?}", watched_file.path),
+            (_, None) => format!("{} files", watched_files.len()),
+            (_, Some(directory)) => format!("the files in {:?}", directory),
+        };
+        let followed = match &streamed_input {
+            Some(_) => format!("standard input and {}", followed),
+            None => followed,
+        };
+
+        while !self.interrupted.load(Ordering::SeqCst)
+            && (!watched_files.is_empty()
+                || directory_monitor.is_some()
+                || streamed_input.is_some())
+        {
+            if has_expired(self.clock, self.timeout) {
+                eprintln!(
+                    "tail: stopping after the timeout of {:?}",
+                    self.timeout.unwrap() // Unwrap is safe, since nothing expires otherwise
+                );
+                timed_out = true;
+                break;
+            }
+
+            if let Some(viewer) = &self.viewer {
+                let quit = viewer
+                    .borrow_mut()
+                    .handle_events()
+                    .context("Unable to read keys from the terminal")?;
+                if quit {
+                    self.interrupted.store(true, Ordering::SeqCst);
+                    break;
+                }
+            }
+
+            if let (Some(directory_monitor), Some(directory)) =
+                (&mut directory_monitor, &self.directory)
+            {
+                for path in directory_monitor.take_created() {
+                    if !is_wanted_new_file(&path, directory, self.file_pattern.as_ref()) {
+                        continue;
+                    }
+
+                    // The file might already be gone again, which is no reason to stop following the others
+                    match self.follow_new_file(path, &watched_files) {
+                        Ok(Some(watched_file)) => {
+                            eprintln!("tail: following new file {:?}", watched_file.path);
+                            watched_files.push(watched_file);
+                        }
+                        Ok(None) => {}
+                        Err(error) => eprintln!("tail: {:#}", error),
+                    }
+                }
+            }
+
+            let reopen_signaled = reopen_requested.swap(false, Ordering::Relaxed);
+            // Checked before reading, so that everything the process wrote before exiting is still printed
+            let process_exited = self.pid.is_some_and(|pid| !signals::is_process_alive(pid));
+            if process_exited {
+                // Its last writes might not have been announced yet
+                for watched_file in &mut watched_files {
+                    watched_file.reread = true;
+                }
+            }
+
+            let mut index = 0;
+            while index < watched_files.len() && !self.interrupted.load(Ordering::SeqCst) {
+                let following =
+                    follow_with_retry(&mut watched_files[index], Instant::now(), |watched_file| {
+                        self.follow_file(watched_file, reopen_signaled)
+                    });
+                if stop_on_broken_pipe(following, &self.interrupted)? {
+                    index += 1;
+                } else {
+                    let mut watched_file = watched_files.remove(index);
+                    watched_file.file_monitor.unwatch(&watched_file.path);
+                }
+            }
+
+            if let Some(input) = &mut streamed_input {
+                match input.take_lines() {
+                    Some(mut lines) => {
+                        if let Some(line_filter) = self.line_filter {
+                            lines = line_filter.select(lines, &mut input.context_state);
+                        }
+                        let passed =
+                            self.pass_on_lines(lines, ReadingDirection::TopToBottom, &input.name);
+                        stop_on_broken_pipe(passed, &self.interrupted)?;
+                    }
+                    None => {
+                        eprintln!("tail: standard input has ended");
+                        streamed_input = None;
+                    }
+                }
+            }
+
+            if let Some(output_queue) = &self.output_queue {
+                let lines = output_queue.borrow_mut().next_frame();
+                let shown = self
+                    .show_queued(lines, &self.fresh_output_options)
+                    .context("Unable to write to stdout");
+                stop_on_broken_pipe(shown, &self.interrupted)?;
+            }
+            if let Some(merge) = &self.merge {
+                let lines = merge.borrow_mut().take_ready(Instant::now());
+                let shown = self
+                    .show_queued(lines, &self.fresh_output_options)
+                    .context("Unable to write to stdout");
+                stop_on_broken_pipe(shown, &self.interrupted)?;
+            }
+            let redrawn = self.redraw().context("Unable to write to stdout");
+            stop_on_broken_pipe(redrawn, &self.interrupted)?;
+
+            if process_exited {
+                eprintln!("tail: process {} has exited", self.pid.unwrap()); // Unwrap is safe, since there is no process to exit otherwise
+                break;
+            }
+
+            // Everything printed so far has been flushed at the end of its batch, so this comes after it
+            if let Some(heartbeat) = &mut heartbeat {
+                if heartbeat.is_due(self.clock.elapsed(), self.emitted.bytes.get()) {
+                    eprintln!("tail: -- still following, no new data --");
+                }
+            }
+            if let Some(stats_interval) = &mut stats_interval {
+                if let Some(report) = stats_interval.report(self.clock.elapsed(), &self.emitted) {
+                    eprintln!("tail: stats: {}", report);
+                }
+            }
+
+            if self.until_found.get() {
+                break;
+            }
+
+            // Only what has to be looked at every frame keeps the loop going while nothing happens
+            let event_driven = self.viewer.is_none()
+                && self.output_queue.is_none()
+                && self.merge.is_none()
+                && !(self.screen.is_some() && self.output_options.age.is_some())
+                && directory_monitor
+                    .as_ref()
+                    .is_none_or(|directory_monitor| directory_monitor.wakes_up())
+                && watched_files.iter().all(|watched_file| {
+                    watched_file.file_monitor.wakes_up()
+                        && watched_file.link.is_none()
+                        && !watched_file.waiting_for_file
+                        && !watched_file.reread
+                        && watched_file.kind == FileKind::Regular
+                        && watched_file.transient_retry.next_attempt.is_none()
+                });
+            if event_driven {
+                // Neither the process of --pid nor the reopen signal can wake us up, so they are checked now and then
+                let housekeeping =
+                    (self.pid.is_some() || self.reopen_signal).then_some(HOUSEKEEPING_INTERVAL);
+                let until_timeout = self
+                    .timeout
+                    .map(|timeout| timeout.saturating_sub(self.clock.elapsed()));
+                let until_heartbeat = heartbeat
+                    .as_ref()
+                    .map(|heartbeat| heartbeat.remaining(self.clock.elapsed()));
+                let until_stats = stats_interval
+                    .as_ref()
+                    .map(|stats_interval| stats_interval.remaining(self.clock.elapsed()));
+                self.wakeup.wait(
+                    [housekeeping, until_timeout, until_heartbeat, until_stats]
+                        .iter()
+                        .flatten()
+                        .min()
+                        .copied(),
+                );
+                self.skip_missed_frames();
+            } else {
+                self.sleep_remaining_frame();
+            }
+        }
+
+        // Whatever is still waiting is printed, unless following has been cut short
+        if !self.interrupted.load(Ordering::SeqCst) {
+            let mut lines = vec![];
+            if let Some(output_queue) = &self.output_queue {
+                lines = output_queue.borrow_mut().take_all();
+            }
+            if let Some(merge) = &self.merge {
+                lines = merge.borrow_mut().take_all();
+            }
+            if !lines.is_empty() {
+                self.show_queued(lines, &self.fresh_output_options)
+                    .context("Unable to write to stdout")?;
+                self.redraw().context("Unable to write to stdout")?;
+            }
+        }
+
+        for watched_file in &mut watched_files {
+            watched_file.file_monitor.unwatch(&watched_file.path);
+        }
+
+        self.close_viewer()?;
+        eprintln!(
+            "tail: stopped following {} after printing {} {}",
+            followed,
+            if self.byte_count.is_some() {
+                self.emitted.bytes.get()
+            } else {
+                self.emitted.lines.get()
+            },
+            if self.byte_count.is_some() {
+                "bytes"
+            } else {
+                "lines"
+            }
+        );
+        if let Some(output_queue) = &self.output_queue {
+            let dropped_count = output_queue.borrow().dropped_count;
+            if dropped_count > 0 {
+                eprintln!(
+                    "tail: dropped {} lines that came in faster than --max-lines-per-sec allowed",
+                    dropped_count
+                );
+            }
+        }
+
+        if timed_out {
+            let exit_code = match self.timeout_exit_code {
+                Some(exit_code) => exit_code,
+                None if self.until.is_some() => EXIT_UNTIL_NOT_FOUND,
+                None => 0,
+            };
+            if exit_code != 0 {
+                self.finish()?;
+                std::process::exit(exit_code);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Following several files at once, the headers tell which file lines are from
+struct WatchedFile {
+    name: PathBuf, // As given on the command line
+    path: PathBuf,
+    link: Option<PathBuf>, // The symlink that path has been resolved from, when following by name
+    file: File,
+    file_monitor: Box<dyn FileMonitor>,
+    line_format: LineFormat,
+    line_numbering: LineNumbering,
+    cursor: ReadCursor, // In byte mode, only its offset is used
+    waiting_for_file: bool,
+    retry_backoff: Backoff, // While waiting for the file
+    transient_retry: TransientRetry,
+    reread: bool,   // Read again without being told to, e.g. after switching files
+    kind: FileKind, // Unless it's a regular file, its size says nothing, so it is read every frame
+}
+
+// Standard input, followed along with files. It can't be watched like them, so a thread reads it and hands its lines over
+// as they arrive, waking up the loop that follows the files
+struct StreamedInput {
+    name: PathBuf,
+    receiver: crossbeam_channel::Receiver<Vec<Line>>,
+    context_state: ContextState,
+}
+
+impl StreamedInput {
+    fn spawn(
+        input: impl Read + Send + 'static,
+        name: &Path,
+        line_format: LineFormat,
+        wakeup: &Wakeup,
+    ) -> Self {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let wakeup = wakeup.clone();
+        let description = name.display().to_string();
+
+        thread::spawn(move || {
+            // Stops once the input ends, or when there is nobody left to take the lines
+            let followed = follow_stream(input, line_format, &AtomicBool::new(false), |lines| {
+                sender
+                    .send(lines)
+                    .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+                wakeup.notify();
+                Ok(())
+            });
+            if let Err(error) = followed {
+                eprintln!("tail: unable to read {}: {:#}", description, error);
+            }
+            drop(sender); // So that the end is noticed right away
+            wakeup.notify();
+        });
+
+        Self {
+            name: name.to_path_buf(),
+            receiver,
+            context_state: ContextState::default(),
+        }
+    }
+
+    // Everything that has arrived so far, or nothing once the input has ended and all of it has been taken
+    fn take_lines(&self) -> Option<Vec<Line>> {
+        let mut lines = vec![];
+        loop {
+            match self.receiver.try_recv() {
+                Ok(mut new_lines) => lines.append(&mut new_lines),
+                Err(crossbeam_channel::TryRecvError::Empty) => return Some(lines),
+                Err(crossbeam_channel::TryRecvError::Disconnected) if lines.is_empty() => {
+                    return None
+                }
+                Err(crossbeam_channel::TryRecvError::Disconnected) => return Some(lines),
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum FileKind {
+    Regular,
+    Device,    // Character or block device, or socket
+    Generated, // Empty by its size, but not when read, like the files in /proc and /sys
+}
+
+// Peeking at an empty file's first byte tells whether it is generated. The file is rewound afterwards
+fn file_kind(file: &mut File) -> io::Result<FileKind> {
+    let metadata = file.metadata()?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+
+        let file_type = metadata.file_type();
+        if file_type.is_char_device() || file_type.is_block_device() || file_type.is_socket() {
+            return Ok(FileKind::Device);
+        }
+    }
+    if metadata.len() > 0 {
+        return Ok(FileKind::Regular);
+    }
+
+    let read = file.read(&mut [0])?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(if read > 0 {
+        FileKind::Generated
+    } else {
+        FileKind::Regular
+    })
+}
+
+// Devices can't be seeked. New data is read from wherever the last read has stopped, though, which is where the device is
+// at anyway, so seeking there is all that's needed
+struct DeviceReader<'a> {
+    file: &'a mut File,
+    position: u64, // How much has been read so far
+}
+
+impl<'a> DeviceReader<'a> {
+    fn new(file: &'a mut File, position: u64) -> Self {
+        Self { file, position }
+    }
+}
+
+impl Read for DeviceReader<'_> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let read = self.file.read(buffer)?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for DeviceReader<'_> {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        match position {
+            SeekFrom::Start(offset) if offset == self.position => Ok(self.position),
+            SeekFrom::Current(0) => Ok(self.position),
+            _ => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "a device can only be read from where it is at",
+            )),
+        }
+    }
+}
+
+// Keep trying to open the file until it shows up, whenever the backoff says so. Gives up once interrupted
+fn wait_for_file(
+    path: &Path,
+    open_mode: OpenMode,
+    interrupted: &AtomicBool,
+    backoff: &mut Backoff,
+    mut wait: impl FnMut(),
+) -> Option<File> {
+    while !interrupted.load(Ordering::SeqCst) {
+        if backoff.is_due() {
+            // Directories can be opened, too, but not read
+            if let Ok(file) = open_mode.open(path) {
+                if file.metadata().is_ok_and(|metadata| !metadata.is_dir()) {
+                    return Some(file);
+                }
+            }
+            if backoff.take_grown() {
+                eprintln!(
+                    "tail: still waiting for {:?}, looking again in {:?}",
+                    path, backoff.interval
+                );
+            }
+        }
+        wait();
+    }
+
+    None
+}
+
+// With --retry, a missing file is looked for less and less often, so that waiting for a long time doesn't keep the file
+// system busy. Every attempt doubles the time until the next one, up to --follow-retry-interval
+#[derive(Debug, Clone)]
+pub(crate) struct Backoff {
+    initial: Duration,
+    max: Duration,
+    interval: Duration, // Until the next attempt
+    next_attempt: Option<Instant>,
+    grown: bool, // Since the last time this has been asked
+}
+
+impl Backoff {
+    pub(crate) fn new(initial: Duration, max: Duration) -> Self {
+        Self {
+            initial: initial.min(max),
+            max,
+            interval: initial.min(max),
+            next_attempt: None,
+            grown: false,
+        }
+    }
+
+    // The first attempt is due right away
+    fn is_due(&mut self) -> bool {
+        let now = Instant::now();
+        if let Some(next_attempt) = self.next_attempt {
+            if now < next_attempt {
+                return false;
+            }
+            let interval = self.interval.saturating_mul(2).min(self.max);
+            self.grown = interval > self.interval;
+            self.interval = interval;
+        }
+        self.next_attempt = Some(now + self.interval);
+        true
+    }
+
+    // Whether the time between attempts has grown, which is worth telling about once per step
+    fn take_grown(&mut self) -> bool {
+        std::mem::take(&mut self.grown)
+    }
+
+    fn reset(&mut self) {
+        *self = Self::new(self.initial, self.max);
+    }
+}
+
+// Windows reports a file that another process has locked for a moment like this
+const ERROR_SHARING_VIOLATION: i32 = 32;
+const ERROR_LOCK_VIOLATION: i32 = 33;
+const TRANSIENT_RETRY_DELAY: Duration = Duration::from_millis(50);
+
+// Errors that are likely to go away by themselves, unlike a file that is gone
+fn is_transient(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        cause.downcast_ref::<io::Error>().is_some_and(|error| {
+            matches!(
+                error.kind(),
+                io::ErrorKind::PermissionDenied
+                    | io::ErrorKind::WouldBlock
+                    | io::ErrorKind::TimedOut
+                    | io::ErrorKind::Interrupted
+            ) || (cfg!(windows)
+                && matches!(
+                    error.raw_os_error(),
+                    Some(ERROR_SHARING_VIOLATION | ERROR_LOCK_VIOLATION)
+                ))
+        })
+    })
+}
+
+// With --transient-retries, how many more chances a file gets after such errors. The waits in between are shuffled a little,
+// so that files that have run into the same lock don't all try again at the same moment
+#[derive(Debug, Clone)]
+pub(crate) struct TransientRetry {
+    max_retries: u32,
+    failures: u32, // In a row
+    next_attempt: Option<Instant>,
+}
+
+impl TransientRetry {
+    pub(crate) fn new(max_retries: u32) -> Self {
+        Self {
+            max_retries,
+            failures: 0,
+            next_attempt: None,
+        }
+    }
+
+    // How long to wait before trying again, or nothing once it's time to give up
+    fn retry(&mut self, now: Instant) -> Option<Duration> {
+        if self.failures >= self.max_retries {
+            return None;
+        }
+        let delay = jitter(TRANSIENT_RETRY_DELAY.saturating_mul(1 << self.failures.min(10)));
+        self.failures += 1;
+        self.next_attempt = Some(now + delay);
+        Some(delay)
+    }
+
+    fn is_due(&self, now: Instant) -> bool {
+        self.next_attempt
+            .is_none_or(|next_attempt| now >= next_attempt)
+    }
+
+    fn succeeded(&mut self) {
+        self.failures = 0;
+        self.next_attempt = None;
+    }
+}
+
+// Follows the file once it's due. Errors that are likely to go away by themselves are tried again later, as long as
+// --transient-retries allows. Returns whether the file is still worth following
+fn follow_with_retry(
+    watched_file: &mut WatchedFile,
+    now: Instant,
+    follow: impl FnOnce(&mut WatchedFile) -> Result<bool>,
+) -> Result<bool> {
+    if !watched_file.transient_retry.is_due(now) {
+        return Ok(true);
+    }
+    match follow(watched_file) {
+        Err(error) if is_transient(&error) => match watched_file.transient_retry.retry(now) {
+            Some(delay) => {
+                eprintln!(
+                    "tail: {:?}: {:#}; trying again in {:?}",
+                    watched_file.path, error, delay
+                );
+                watched_file.reread = true; // Whatever has been missed is still there
+                Ok(true)
+            }
+            None => Err(error),
+        },
+        Ok(following) => {
+            watched_file.transient_retry.succeeded();
+            Ok(following)
+        }
+        Err(error) => Err(error),
+    }
+}
+
+// Somewhere between half of the delay and all of it
+fn jitter(delay: Duration) -> Duration {
+    use std::hash::BuildHasher;
+
+    // Every RandomState has its own keys, which makes for a cheap random number
+    let random = std::collections::hash_map::RandomState::new().hash_one(0u8);
+    delay.mul_f64(0.5 + (random % 1000) as f64 / 2000.0)
+}
+
+// Whether the file has lost its name to another file, the way editors save: writing a temporary file and renaming it over the
+// original one. A file that has only been renamed, like by log rotation, still has a name
+#[cfg(unix)]
+fn is_saved_over(file: &File, path: &Path) -> bool {
+    use std::fs::OpenOptions;
+    use std::os::unix::fs::MetadataExt;
+
+    file.metadata().is_ok_and(|metadata| metadata.nlink() == 0)
+        && OpenOptions::new()
+            .read(true)
+            .open(path)
+            .is_ok_and(|new_file| !is_same_file(file, &new_file).unwrap_or(true))
+}
+
+// Windows doesn't let anybody rename a file over one that is open
+#[cfg(not(unix))]
+fn is_saved_over(_file: &File, _path: &Path) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use anyhow::anyhow;
+    use monitor::HotwatchMonitor;
+    use tail::OverflowPolicy;
+
+    fn to_lines(lines: &[(u64, &str)]) -> Vec<Line> {
+        lines
+            .iter()
+            .map(|(number, line)| (*number, line.to_string()))
+            .collect()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_watch_notices_saved_over_file() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let path = directory.path().join("saved.txt");
+        std::fs::write(&path, "old\n")?;
+        let file = File::open(&path)?;
+
+        let mut file_monitor = HotwatchMonitor::new(Duration::from_millis(10), None, None)?;
+        file_monitor.watch(&path)?;
+
+        // Like an editor saving: the new contents go into a new file, which then takes the name of the old one
+        let temporary = directory.path().join(".saved.txt.swp");
+        std::fs::write(&temporary, "old\nnew\n")?;
+        std::fs::rename(&temporary, &path)?;
+
+        let clock = Instant::now();
+        while !file_monitor.is_replaced() && clock.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(file_monitor.is_replaced());
+        assert!(is_saved_over(&file, &path));
+
+        // Following by descriptor continues with the new file, from its beginning
+        let new_file = File::open(&path)?;
+        assert!(!is_saved_over(&new_file, &path));
+        let lines = read_new_lines(
+            new_file,
+            &mut ReadCursor::default(),
+            ReadingDirection::TopToBottom,
+            &mut LineNumbering::new(None, OverflowPolicy::Saturate),
+            None,
+            LineFormat::default(),
+        )?;
+        assert_eq!(lines, to_lines(&[(1, "old\n"), (2, "new\n")]));
+
+        // A file that has been rotated away still has a name, so it's still followed
+        let rotated = directory.path().join("saved.txt.1");
+        let file = File::open(&path)?;
+        std::fs::rename(&path, &rotated)?;
+        std::fs::write(&path, "newer\n")?;
+        assert!(!is_saved_over(&file, &path));
+
+        Ok(())
+    }
+
+    fn no_backoff() -> Backoff {
+        Backoff::new(Duration::ZERO, Duration::ZERO)
+    }
+
+    #[test]
+    fn test_wait_for_file() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let path = directory.path().join("late.log");
+        let interrupted = AtomicBool::new(false);
+
+        let writer = {
+            let path = path.clone();
+            thread::spawn(move || -> io::Result<()> {
+                thread::sleep(Duration::from_millis(200));
+                std::fs::write(path, "first\nsecond\n")
+            })
+        };
+
+        let mut wait_count = 0;
+        let file = wait_for_file(
+            &path,
+            OpenMode::Shared,
+            &interrupted,
+            &mut no_backoff(),
+            || {
+                wait_count += 1;
+                thread::sleep(Duration::from_millis(10));
+            },
+        );
+        writer.join().unwrap()?;
+        assert!(wait_count > 0);
+
+        // Tailing starts with the file that showed up
+        let lines = read_lines_seekable(
+            file.expect("The file should have been opened"),
+            Position::FromEnd(0),
+            Position::FromEnd(10),
+            ReadingDirection::BottomToTop,
+            LineFormat::default(),
+        )?;
+        assert_eq!(lines, to_lines(&[(2, "second\n"), (1, "first\n")]));
+
+        // A directory in the way might still make room for the file
+        let path = directory.path().join("rotated");
+        std::fs::create_dir(&path)?;
+        let writer = {
+            let path = path.clone();
+            thread::spawn(move || -> io::Result<()> {
+                thread::sleep(Duration::from_millis(200));
+                std::fs::remove_dir(&path)?;
+                std::fs::write(path, "third\n")
+            })
+        };
+        assert!(matches!(
+            validate_path(path.to_str().unwrap(), true, OpenMode::Shared),
+            Err(FileError::Directory { .. })
+        ));
+        let file = wait_for_file(
+            &path,
+            OpenMode::Shared,
+            &interrupted,
+            &mut no_backoff(),
+            || {
+                thread::sleep(Duration::from_millis(10));
+            },
+        );
+        writer.join().unwrap()?;
+        let lines = read_lines_seekable(
+            file.expect("The file should have been opened"),
+            Position::FromEnd(0),
+            Position::FromEnd(10),
+            ReadingDirection::BottomToTop,
+            LineFormat::default(),
+        )?;
+        assert_eq!(lines, to_lines(&[(1, "third\n")]));
+
+        // Ctrl+C ends the wait
+        let missing = directory.path().join("never.log");
+        let file = wait_for_file(
+            &missing,
+            OpenMode::Shared,
+            &interrupted,
+            &mut no_backoff(),
+            || interrupted.store(true, Ordering::SeqCst),
+        );
+        assert!(file.is_none());
+
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_file_kind() -> Result<()> {
+        let mut file = tempfile::tempfile()?;
+        assert_eq!(file_kind(&mut file)?, FileKind::Regular);
+        file.write_all(b"line\n")?;
+        assert_eq!(file_kind(&mut file)?, FileKind::Regular);
+
+        assert_eq!(file_kind(&mut File::open("/dev/null")?)?, FileKind::Device);
+
+        // Peeking at the first byte doesn't take it away
+        let mut version = File::open("/proc/version")?;
+        assert_eq!(version.metadata()?.len(), 0);
+        assert_eq!(file_kind(&mut version)?, FileKind::Generated);
+        let mut contents = String::new();
+        version.read_to_string(&mut contents)?;
+        assert!(contents.starts_with("Linux"));
+
+        Ok(())
+    }
+
+    // Fails to read a few times, like a file that another process has locked for a moment
+    struct FlakyReader {
+        inner: io::Cursor<&'static [u8]>,
+        failures_left: u32,
+    }
+
+    impl Read for FlakyReader {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            if self.failures_left > 0 {
+                self.failures_left -= 1;
+                return Err(io::Error::from(io::ErrorKind::PermissionDenied));
+            }
+            self.inner.read(buffer)
+        }
+    }
+
+    impl Seek for FlakyReader {
+        fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+            self.inner.seek(position)
+        }
+    }
+
+    #[test]
+    fn test_transient_retry() -> Result<()> {
+        let mut watched_file = WatchedFile {
+            name: PathBuf::from("flaky.log"),
+            path: PathBuf::from("flaky.log"),
+            link: None,
+            file: tempfile::tempfile()?,
+            file_monitor: Box::new(monitor::PollingMonitor::new()),
+            line_format: LineFormat::default(),
+            line_numbering: LineNumbering::new(None, OverflowPolicy::Saturate),
+            cursor: ReadCursor::default(),
+            waiting_for_file: false,
+            retry_backoff: Backoff::new(Duration::ZERO, Duration::ZERO),
+            transient_retry: TransientRetry::new(3),
+            reread: false,
+            kind: FileKind::Regular,
+        };
+
+        // Follows the file frame by frame, like the follow loop does, until the lines have been read or it's given up on.
+        // Returns the lines and how many reads it took
+        let follow = |watched_file: &mut WatchedFile, failures: u32| -> Result<(Vec<Line>, u32)> {
+            let mut reader = FlakyReader {
+                inner: io::Cursor::new(b"first\nsecond\n"),
+                failures_left: failures,
+            };
+            watched_file.cursor = ReadCursor::default();
+            let mut lines = vec![];
+            let mut reads = 0;
+            let mut now = Instant::now();
+            while lines.is_empty() {
+                follow_with_retry(watched_file, now, |watched_file| {
+                    reads += 1;
+                    lines = read_new_lines(
+                        &mut reader,
+                        &mut watched_file.cursor,
+                        ReadingDirection::TopToBottom,
+                        &mut watched_file.line_numbering,
+                        None,
+                        watched_file.line_format,
+                    )?;
+                    Ok(true)
+                })?;
+
+                if let Some(next_attempt) = watched_file.transient_retry.next_attempt {
+                    assert!(watched_file.reread);
+                    assert!(next_attempt - now >= TRANSIENT_RETRY_DELAY / 2);
+                    // Frames in between leave the file alone
+                    assert!(follow_with_retry(watched_file, now, |_| unreachable!())?);
+                    watched_file.reread = false;
+                    now = next_attempt;
+                }
+            }
+            Ok((lines, reads))
+        };
+
+        let (lines, reads) = follow(&mut watched_file, 3)?;
+        assert_eq!(
+            lines,
+            [(1, "first\n".to_string()), (2, "second\n".to_string())]
+        );
+        assert_eq!(reads, 4);
+
+        // Succeeding starts the count over, but too many failures in a row are given up on
+        assert_eq!(follow(&mut watched_file, 2)?.1, 3);
+        assert!(follow(&mut watched_file, 4).is_err());
+        watched_file.transient_retry = TransientRetry::new(0);
+        assert!(follow(&mut watched_file, 1).is_err());
+
+        assert!(!is_transient(&anyhow!(io::Error::from(
+            io::ErrorKind::NotFound
+        ))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_backoff_reduces_attempts() {
+        let window = Duration::from_millis(500);
+        let count_attempts = |backoff: &mut Backoff| {
+            let clock = Instant::now();
+            let mut attempts = 0;
+            while clock.elapsed() < window {
+                if backoff.is_due() {
+                    attempts += 1;
+                }
+                thread::sleep(Duration::from_millis(1));
+            }
+            attempts
+        };
+
+        // Once per frame, like without backing off
+        let constant = count_attempts(&mut Backoff::new(
+            Duration::from_millis(10),
+            Duration::from_millis(10),
+        ));
+        assert!(constant >= 20, "{} attempts", constant);
+
+        // 10, 20, 40, 80, 160 ms and so on add up to the window after 6 attempts
+        let mut backoff = Backoff::new(Duration::from_millis(10), Duration::from_secs(1));
+        let backed_off = count_attempts(&mut backoff);
+        assert!((5..=8).contains(&backed_off), "{} attempts", backed_off);
+
+        // Every step is told about once, until the maximum has been reached
+        let mut steps = 0;
+        let mut backoff = Backoff::new(Duration::ZERO, Duration::ZERO);
+        for _ in 0..3 {
+            backoff.is_due();
+            steps += backoff.take_grown() as u32;
+        }
+        assert_eq!(steps, 0);
+
+        backoff = Backoff::new(Duration::from_millis(1), Duration::from_millis(4));
+        for _ in 0..20 {
+            if backoff.is_due() {
+                steps += backoff.take_grown() as u32;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(steps, 2); // 2 ms and 4 ms
+
+        // Starting over after the file has shown up again
+        backoff.reset();
+        assert_eq!(backoff.interval, Duration::from_millis(1));
+        assert!(backoff.is_due());
+    }
+}
@@ -0,0 +1,101 @@
+// Signal handling for the follow loop. The handlers only flip flags, which the loop checks on every frame. Ctrl+C also wakes
+// the loop up, in case it is waiting for changes
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use anyhow::Result;
+use tail::monitor::Wakeup;
+
+// Ctrl+C asks the follow loop to stop. If it doesn't get around to that, a second Ctrl+C ends the program right away
+pub fn register_interrupt(interrupted: &Arc<AtomicBool>, wakeup: &Wakeup) -> Result<()> {
+    let interrupted = Arc::clone(interrupted);
+    let wakeup = wakeup.clone();
+
+    ctrlc::set_handler(move || {
+        if interrupted.swap(true, Ordering::SeqCst) {
+            std::process::exit(130); // The usual exit code for being terminated by Ctrl+C
+        }
+        wakeup.notify();
+    })?;
+
+    Ok(())
+}
+
+// SIGUSR1 lets log rotators tell us to switch over to the new file at the right moment
+#[cfg(unix)]
+pub fn register_reopen(reopen_requested: &Arc<AtomicBool>) -> Result<()> {
+    signal_hook::flag::register(signal_hook::consts::SIGUSR1, Arc::clone(reopen_requested))?;
+    Ok(())
+}
+
+// There is no SIGUSR1 on Windows, so the flag is simply never set
+#[cfg(not(unix))]
+pub fn register_reopen(_reopen_requested: &Arc<AtomicBool>) -> Result<()> {
+    Ok(())
+}
+
+// Signal 0 isn't actually sent, it only checks whether the process could be signaled. A process that we aren't allowed to signal still exists
+#[cfg(unix)]
+pub fn is_process_alive(pid: u32) -> bool {
+    if unsafe { libc::kill(pid as libc::pid_t, 0) } == 0 {
+        return true;
+    }
+    std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+// Without a way to check, the process is assumed to be alive, so following goes on as usual
+#[cfg(not(unix))]
+pub fn is_process_alive(_pid: u32) -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_interrupt_sets_flag() -> Result<()> {
+        let interrupted = Arc::new(AtomicBool::new(false));
+        let wakeup = Wakeup::new();
+        register_interrupt(&interrupted, &wakeup)?;
+
+        signal_hook::low_level::raise(signal_hook::consts::SIGINT)?;
+
+        // The handler runs on its own thread, so give it a moment
+        assert!(wakeup.wait(Some(std::time::Duration::from_secs(1))));
+        assert!(interrupted.load(Ordering::SeqCst));
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_reopen_signal_sets_flag() -> Result<()> {
+        let reopen_requested = Arc::new(AtomicBool::new(false));
+        register_reopen(&reopen_requested)?;
+
+        signal_hook::low_level::raise(signal_hook::consts::SIGUSR1)?;
+
+        assert!(reopen_requested.load(Ordering::Relaxed));
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_is_process_alive() -> Result<()> {
+        assert!(is_process_alive(std::process::id()));
+
+        let mut child = std::process::Command::new("sleep").arg("10").spawn()?;
+        assert!(is_process_alive(child.id()));
+        child.kill()?;
+        child.wait()?;
+
+        // Once reaped, the PID of the child might already belong to another process, so check one that no system hands out
+        assert!(!is_process_alive(i32::MAX as u32));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,643 @@
+// With --tui, lines go into a scrollback buffer that can be paged through, instead of straight to stdout
+
+use std::{
+    collections::VecDeque,
+    io,
+    time::{Duration, Instant},
+};
+
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout},
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::Paragraph,
+    DefaultTerminal, Frame,
+};
+use regex::Regex;
+
+const TAB_WIDTH: usize = 8;
+
+// Everything about what is shown, without a terminal, so that it can be tested
+#[derive(Debug)]
+pub struct Scrollback {
+    rows: VecDeque<String>,      // Oldest first
+    arrivals: VecDeque<Instant>, // When each row arrived
+    show_ages: bool,
+    capacity: usize,
+    offset: usize, // How many rows the view is scrolled up from the last one
+    pinned: bool,  // New rows scroll into view, like following in less
+    height: usize, // Of the view at the last draw, for paging
+    search: Search,
+    message: Option<String>, // Shown in the status bar until the next key
+    finished: bool,
+    changed: bool,
+}
+
+impl Scrollback {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            rows: VecDeque::new(),
+            arrivals: VecDeque::new(),
+            show_ages: false,
+            capacity,
+            offset: 0,
+            pinned: true,
+            height: 1,
+            search: Search::default(),
+            message: None,
+            finished: false,
+            changed: true, // Even an empty view should be drawn once
+        }
+    }
+
+    // While scrolled up, the view stays on the rows it shows, so new rows only push the bottom further away
+    pub fn push(&mut self, rows: impl IntoIterator<Item = String>) {
+        let old_length = self.rows.len();
+        self.rows.extend(rows.into_iter().map(|row| to_row(&row)));
+        let added = self.rows.len() - old_length;
+        if added == 0 {
+            return;
+        }
+        self.arrivals
+            .extend(std::iter::repeat_n(Instant::now(), added));
+
+        while self.rows.len() > self.capacity {
+            self.rows.pop_front();
+            self.arrivals.pop_front();
+        }
+        if !self.pinned {
+            self.offset += added;
+        }
+        self.clamp_offset();
+        self.changed = true;
+    }
+
+    // Returns whether the viewer should be closed
+    pub fn handle_key(&mut self, key: KeyEvent) -> bool {
+        let page = self.height.saturating_sub(1).max(1); // One row stays in view, to keep track of where we are
+        self.changed = true;
+        self.message = None;
+
+        // While typing a pattern, keys are part of it
+        if let Some(pattern) = &mut self.search.prompt {
+            match key.code {
+                KeyCode::Char(character) => pattern.push(character),
+                // Taking back more than has been typed gives up on searching
+                KeyCode::Backspace if pattern.pop().is_none() => self.search.prompt = None,
+                KeyCode::Enter => self.start_search(),
+                KeyCode::Esc => self.search.prompt = None,
+                _ => {}
+            }
+            return false;
+        }
+
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return true,
+            // Raw mode keeps Ctrl+C from becoming a signal
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return true,
+            KeyCode::Up | KeyCode::Char('k') => self.scroll_up(1),
+            KeyCode::Down | KeyCode::Char('j') => self.scroll_down(1),
+            KeyCode::PageUp | KeyCode::Char('b') => self.scroll_up(page),
+            KeyCode::PageDown | KeyCode::Char(' ') => self.scroll_down(page),
+            KeyCode::Home | KeyCode::Char('g') => self.scroll_up(self.rows.len()),
+            KeyCode::End | KeyCode::Char('G') => self.pin(true),
+            KeyCode::Char('f') => self.pin(!self.pinned),
+            KeyCode::Char('/') => self.search.prompt = Some(String::new()),
+            KeyCode::Char('n') => self.jump_to_match(Direction::Next),
+            KeyCode::Char('N') => self.jump_to_match(Direction::Previous),
+            KeyCode::Char('u') => self.search.regex = None,
+            _ => {}
+        }
+
+        false
+    }
+
+    // Like less, searching starts at the top of the view, so a match there counts, too
+    fn start_search(&mut self) {
+        let pattern = self.search.prompt.take().unwrap_or_default();
+        match Regex::new(&pattern) {
+            Ok(regex) => {
+                self.search.regex = Some(regex);
+                match self.search.find(&self.rows, self.top(), Direction::Next) {
+                    Some(index) => self.scroll_to(index),
+                    None => self.message = Some("Pattern not found".to_string()),
+                }
+            }
+            Err(error) => self.message = Some(error.to_string()),
+        }
+    }
+
+    fn jump_to_match(&mut self, direction: Direction) {
+        if self.search.regex.is_none() {
+            self.message = Some("No previous search, type / to search".to_string());
+            return;
+        }
+
+        let from = match direction {
+            Direction::Next => self.top() + 1,
+            Direction::Previous => self.top(),
+        };
+        match self.search.find(&self.rows, from, direction) {
+            Some(index) => self.scroll_to(index),
+            None => self.message = Some("Pattern not found".to_string()),
+        }
+    }
+
+    // Puts the row at the top of the view, or as close to it as the rows after it allow
+    fn scroll_to(&mut self, index: usize) {
+        self.offset = self.rows.len().saturating_sub(index + self.height);
+        self.pinned = false;
+        self.clamp_offset();
+    }
+
+    fn top(&self) -> usize {
+        (self.rows.len() - self.offset).saturating_sub(self.height)
+    }
+
+    // Scrolling up leaves the bottom behind, so it also stops following
+    fn scroll_up(&mut self, count: usize) {
+        self.offset = self.offset.saturating_add(count);
+        self.pinned = false;
+        self.clamp_offset();
+    }
+
+    fn scroll_down(&mut self, count: usize) {
+        self.offset = self.offset.saturating_sub(count);
+    }
+
+    fn pin(&mut self, pinned: bool) {
+        self.pinned = pinned;
+        if pinned {
+            self.offset = 0;
+        }
+    }
+
+    // The first row can't be scrolled down past the top of the view
+    fn clamp_offset(&mut self) {
+        self.offset = self.offset.min(self.rows.len().saturating_sub(self.height));
+    }
+
+    fn set_height(&mut self, height: usize) {
+        if height != self.height {
+            self.height = height.max(1);
+            self.clamp_offset();
+        }
+    }
+
+    // The rows that fit into the view, oldest first
+    pub fn visible(&self) -> impl Iterator<Item = &String> {
+        self.rows.range(self.visible_range())
+    }
+
+    fn visible_range(&self) -> std::ops::Range<usize> {
+        let end = self.rows.len() - self.offset;
+        let start = end.saturating_sub(self.height);
+        start..end
+    }
+
+    // With --age, how long ago the visible rows arrived
+    fn visible_ages(&self) -> impl Iterator<Item = Option<String>> + '_ {
+        let show_ages = self.show_ages;
+        self.arrivals
+            .range(self.visible_range())
+            .map(move |arrival| {
+                show_ages.then(|| format!("[{} ago] ", crate::format_age(arrival.elapsed())))
+            })
+    }
+
+    pub fn status(&self) -> String {
+        if let Some(pattern) = &self.search.prompt {
+            return format!("/{}", pattern);
+        }
+
+        let end = self.rows.len() - self.offset;
+        let start = (end.saturating_sub(self.height) + 1).min(end);
+        let state = if self.finished {
+            "stopped following"
+        } else if self.pinned {
+            "following"
+        } else {
+            "paused"
+        };
+
+        match &self.message {
+            Some(message) => format!(
+                " {}-{} of {} | {} | {}",
+                start,
+                end,
+                self.rows.len(),
+                state,
+                message
+            ),
+            None => format!(
+                " {}-{} of {} | {} | q quit, f follow, / search, arrows/PgUp/PgDn/Home/End scroll",
+                start,
+                end,
+                self.rows.len(),
+                state
+            ),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Direction {
+    Next,     // Towards newer rows
+    Previous, // Towards older rows
+}
+
+// The pattern last searched for, and the one being typed. Kept apart from drawing, so that matches can be found without a terminal
+#[derive(Debug, Default)]
+struct Search {
+    regex: Option<Regex>,
+    prompt: Option<String>,
+}
+
+impl Search {
+    // The first matching row from the given one on, or the last one before it
+    fn find(&self, rows: &VecDeque<String>, from: usize, direction: Direction) -> Option<usize> {
+        let regex = self.regex.as_ref()?;
+        match direction {
+            Direction::Next => (from..rows.len()).find(|&index| regex.is_match(&rows[index])),
+            Direction::Previous => (0..from.min(rows.len()))
+                .rev()
+                .find(|&index| regex.is_match(&rows[index])),
+        }
+    }
+
+    // The row split up into the parts before, in and after matches. Empty matches are skipped, since there would be nothing to see
+    fn highlight<'a>(&self, row: &'a str) -> Line<'a> {
+        let regex = match &self.regex {
+            Some(regex) => regex,
+            None => return Line::raw(row),
+        };
+
+        let mut spans = vec![];
+        let mut end_of_last_match = 0;
+        for found in regex
+            .find_iter(row)
+            .filter(|found| !found.as_str().is_empty())
+        {
+            spans.push(Span::raw(&row[end_of_last_match..found.start()]));
+            spans.push(Span::styled(
+                found.as_str(),
+                Style::default().add_modifier(Modifier::REVERSED),
+            ));
+            end_of_last_match = found.end();
+        }
+        spans.push(Span::raw(&row[end_of_last_match..]));
+
+        Line::from(spans)
+    }
+}
+
+// Terminal cells can't hold tabs or other control characters, so tabs are expanded and the rest is dropped
+fn to_row(line: &str) -> String {
+    let mut row = String::with_capacity(line.len());
+    let mut column = 0;
+
+    for character in line.chars() {
+        if character == '\t' {
+            let width = TAB_WIDTH - column % TAB_WIDTH;
+            row.extend(std::iter::repeat_n(' ', width));
+            column += width;
+        } else if !character.is_control() {
+            row.push(character);
+            column += 1;
+        }
+    }
+
+    row
+}
+
+// The terminal is only taken over once there is something to draw, so that waiting for files can still be cut short with Ctrl+C
+pub struct Viewer {
+    terminal: Option<DefaultTerminal>,
+    pub scrollback: Scrollback,
+    drawn: Option<Instant>,
+}
+
+impl Viewer {
+    pub fn new(capacity: usize, show_ages: bool) -> Self {
+        let mut scrollback = Scrollback::new(capacity);
+        scrollback.show_ages = show_ages;
+        Self {
+            terminal: None,
+            scrollback,
+            drawn: None,
+        }
+    }
+
+    // Handles every key pressed since the last frame, without waiting for more. Returns whether the viewer should be closed
+    pub fn handle_events(&mut self) -> io::Result<bool> {
+        if self.terminal.is_none() {
+            return Ok(false);
+        }
+
+        while event::poll(Duration::ZERO)? {
+            if self.handle_event(event::read()?) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn handle_event(&mut self, event: Event) -> bool {
+        match event {
+            // Some platforms also report releasing a key
+            Event::Key(key) if key.kind == KeyEventKind::Press => self.scrollback.handle_key(key),
+            Event::Resize(_, _) => {
+                self.scrollback.changed = true;
+                false
+            }
+            _ => false,
+        }
+    }
+
+    pub fn redraw(&mut self) -> io::Result<()> {
+        let terminal = match &mut self.terminal {
+            Some(terminal) => terminal,
+            None => self.terminal.insert(ratatui::try_init()?),
+        };
+        let aged = self.scrollback.show_ages
+            && self
+                .drawn
+                .is_some_and(|drawn| drawn.elapsed() >= crate::AGE_REFRESH_INTERVAL);
+        if !std::mem::take(&mut self.scrollback.changed) && !aged {
+            return Ok(());
+        }
+        self.drawn = Some(Instant::now());
+
+        let scrollback = &mut self.scrollback;
+        terminal.draw(|frame| draw(frame, scrollback))?;
+        Ok(())
+    }
+
+    // Once there is nothing more to follow, the lines are still shown until the viewer is closed
+    pub fn wait_for_quit(&mut self) -> io::Result<()> {
+        if self.terminal.is_none() {
+            return Ok(());
+        }
+
+        self.scrollback.finished = true;
+        self.scrollback.changed = true;
+        loop {
+            self.redraw()?;
+            if self.handle_event(event::read()?) {
+                return Ok(());
+            }
+        }
+    }
+
+    // Gives the terminal back the way it was. Closing twice does nothing
+    pub fn close(&mut self) -> io::Result<()> {
+        match self.terminal.take() {
+            Some(_) => ratatui::try_restore(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Viewer {
+    fn drop(&mut self) {
+        let _ = self.close(); // Nothing to be done about it anymore
+    }
+}
+
+fn draw(frame: &mut Frame, scrollback: &mut Scrollback) {
+    let [view, status_bar] =
+        Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+    scrollback.set_height(view.height as usize);
+
+    let rows: Vec<Line> = scrollback
+        .visible()
+        .zip(scrollback.visible_ages())
+        .map(|(row, age)| {
+            let mut line = scrollback.search.highlight(row);
+            if let Some(age) = age {
+                line.spans.insert(
+                    0,
+                    Span::styled(age, Style::default().add_modifier(Modifier::DIM)),
+                );
+            }
+            line
+        })
+        .collect();
+    frame.render_widget(Paragraph::new(rows), view);
+    frame.render_widget(
+        Paragraph::new(scrollback.status())
+            .style(Style::default().add_modifier(Modifier::REVERSED)),
+        status_bar,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(range: std::ops::RangeInclusive<usize>) -> Vec<String> {
+        range.map(|i| format!("line {}", i)).collect()
+    }
+
+    fn visible(scrollback: &Scrollback) -> Vec<String> {
+        scrollback.visible().cloned().collect()
+    }
+
+    fn press(scrollback: &mut Scrollback, code: KeyCode) -> bool {
+        scrollback.handle_key(KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    #[test]
+    fn test_scrollback_follows_while_pinned() {
+        let mut scrollback = Scrollback::new(100);
+        scrollback.set_height(3);
+
+        scrollback.push(rows(1..=5));
+        assert_eq!(visible(&scrollback), rows(3..=5));
+
+        scrollback.push(rows(6..=7));
+        assert_eq!(visible(&scrollback), rows(5..=7));
+    }
+
+    #[test]
+    fn test_scrollback_stays_put_while_scrolled_up() {
+        let mut scrollback = Scrollback::new(100);
+        scrollback.set_height(3);
+        scrollback.push(rows(1..=10));
+
+        press(&mut scrollback, KeyCode::Up);
+        assert_eq!(visible(&scrollback), rows(7..=9));
+
+        // New lines don't yank the view to the bottom
+        scrollback.push(rows(11..=12));
+        assert_eq!(visible(&scrollback), rows(7..=9));
+
+        press(&mut scrollback, KeyCode::PageUp);
+        assert_eq!(visible(&scrollback), rows(5..=7));
+        press(&mut scrollback, KeyCode::Home);
+        assert_eq!(visible(&scrollback), rows(1..=3));
+        press(&mut scrollback, KeyCode::Up);
+        assert_eq!(visible(&scrollback), rows(1..=3));
+
+        // Scrolling back down to the bottom doesn't follow again by itself
+        press(&mut scrollback, KeyCode::PageDown);
+        assert_eq!(visible(&scrollback), rows(3..=5));
+        for _ in 0..20 {
+            press(&mut scrollback, KeyCode::Down);
+        }
+        assert_eq!(visible(&scrollback), rows(10..=12));
+        scrollback.push(rows(13..=13));
+        assert_eq!(visible(&scrollback), rows(10..=12));
+
+        press(&mut scrollback, KeyCode::End);
+        assert_eq!(visible(&scrollback), rows(11..=13));
+        scrollback.push(rows(14..=14));
+        assert_eq!(visible(&scrollback), rows(12..=14));
+    }
+
+    #[test]
+    fn test_scrollback_toggle_follow() {
+        let mut scrollback = Scrollback::new(100);
+        scrollback.set_height(2);
+        scrollback.push(rows(1..=4));
+
+        press(&mut scrollback, KeyCode::Char('f'));
+        assert!(scrollback.status().contains("paused"));
+        scrollback.push(rows(5..=6));
+        assert_eq!(visible(&scrollback), rows(3..=4));
+
+        press(&mut scrollback, KeyCode::Char('f'));
+        assert!(scrollback.status().contains("following"));
+        assert_eq!(visible(&scrollback), rows(5..=6));
+    }
+
+    #[test]
+    fn test_scrollback_capacity() {
+        let mut scrollback = Scrollback::new(5);
+        scrollback.set_height(2);
+        scrollback.push(rows(1..=8));
+        assert!(scrollback.status().starts_with(" 4-5 of 5 |"));
+
+        // Scrolled up as far as it goes, the view moves along with the oldest rows being dropped
+        press(&mut scrollback, KeyCode::Home);
+        assert_eq!(visible(&scrollback), rows(4..=5));
+        scrollback.push(rows(9..=10));
+        assert_eq!(visible(&scrollback), rows(6..=7));
+    }
+
+    #[test]
+    fn test_scrollback_ages() {
+        let mut scrollback = Scrollback::new(3);
+        scrollback.set_height(2);
+        scrollback.push(rows(1..=4));
+        assert_eq!(scrollback.visible_ages().collect::<Vec<_>>(), [None, None]);
+
+        // Arrival times are dropped along with their rows
+        scrollback.show_ages = true;
+        assert_eq!(scrollback.arrivals.len(), 3);
+        assert_eq!(
+            scrollback.visible_ages().collect::<Vec<_>>(),
+            [Some("[0s ago] ".to_string()), Some("[0s ago] ".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_scrollback_quit() {
+        let mut scrollback = Scrollback::new(5);
+        assert!(!press(&mut scrollback, KeyCode::Char('x')));
+        assert!(press(&mut scrollback, KeyCode::Char('q')));
+        assert!(scrollback.handle_key(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL)));
+    }
+
+    fn search(scrollback: &mut Scrollback, pattern: &str) {
+        press(scrollback, KeyCode::Char('/'));
+        for character in pattern.chars() {
+            press(scrollback, KeyCode::Char(character));
+        }
+        press(scrollback, KeyCode::Enter);
+    }
+
+    #[test]
+    fn test_search_over_scrollback() {
+        let mut scrollback = Scrollback::new(100);
+        scrollback.set_height(2);
+        scrollback.push(rows(1..=30));
+
+        // The matches are all above the view
+        search(&mut scrollback, "line [12]?5$");
+        assert!(scrollback.status().contains("Pattern not found"));
+        press(&mut scrollback, KeyCode::Home);
+
+        search(&mut scrollback, "line [12]?5$");
+        assert_eq!(visible(&scrollback), rows(5..=6));
+        press(&mut scrollback, KeyCode::Char('n'));
+        assert_eq!(visible(&scrollback), rows(15..=16));
+        press(&mut scrollback, KeyCode::Char('n'));
+        assert_eq!(visible(&scrollback), rows(25..=26));
+        press(&mut scrollback, KeyCode::Char('n'));
+        assert_eq!(visible(&scrollback), rows(25..=26));
+        assert!(scrollback.status().contains("Pattern not found"));
+        press(&mut scrollback, KeyCode::Char('N'));
+        assert_eq!(visible(&scrollback), rows(15..=16));
+
+        // Following isn't picked up again by searching
+        scrollback.push(rows(31..=35));
+        assert_eq!(visible(&scrollback), rows(15..=16));
+    }
+
+    #[test]
+    fn test_search_prompt() {
+        let mut scrollback = Scrollback::new(100);
+        scrollback.set_height(2);
+        scrollback.push(rows(1..=10));
+
+        press(&mut scrollback, KeyCode::Char('n'));
+        assert!(scrollback.status().contains("No previous search"));
+
+        // Keys that would scroll or quit are part of the pattern
+        press(&mut scrollback, KeyCode::Char('/'));
+        press(&mut scrollback, KeyCode::Char('q'));
+        assert!(!press(&mut scrollback, KeyCode::Char('x')));
+        assert_eq!(scrollback.status(), "/qx");
+        press(&mut scrollback, KeyCode::Backspace);
+        assert_eq!(scrollback.status(), "/q");
+        press(&mut scrollback, KeyCode::Esc);
+        assert!(scrollback.search.prompt.is_none());
+        assert!(scrollback.search.regex.is_none());
+
+        search(&mut scrollback, "(");
+        assert!(scrollback.status().contains("regex parse error"));
+        assert!(scrollback.search.regex.is_none());
+    }
+
+    #[test]
+    fn test_search_highlight() {
+        let mut search = Search::default();
+        assert_eq!(search.highlight("line 12"), Line::raw("line 12"));
+
+        search.regex = Some(Regex::new("1|x*").unwrap());
+        assert_eq!(
+            search.highlight("line 121"),
+            Line::from(vec![
+                Span::raw("line "),
+                Span::styled("1", Style::default().add_modifier(Modifier::REVERSED)),
+                Span::raw("2"),
+                Span::styled("1", Style::default().add_modifier(Modifier::REVERSED)),
+                Span::raw(""),
+            ])
+        );
+
+        // Until the highlight is cleared
+        let mut scrollback = Scrollback::new(100);
+        scrollback.search = search;
+        press(&mut scrollback, KeyCode::Char('u'));
+        assert!(scrollback.search.regex.is_none());
+    }
+
+    #[test]
+    fn test_to_row() {
+        assert_eq!(to_row("12:\tline\r"), "12:     line");
+        assert_eq!(to_row("\tx"), "        x");
+    }
+}
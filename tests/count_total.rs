@@ -0,0 +1,25 @@
+// --count-total counts all lines of the input, no matter how many of them are printed
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+#[test]
+fn test_count_total_stdin_head() -> anyhow::Result<()> {
+    let mut tail = Command::new(env!("CARGO_BIN_EXE_tail"))
+        .args(["--count-total", "--head", "-n", "2", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let input = (1..=10)
+        .map(|number| format!("{}\n", number))
+        .collect::<String>();
+    tail.stdin.take().unwrap().write_all(input.as_bytes())?;
+
+    // Reading stops after the first lines, but the rest still has to be counted
+    let output = tail.wait_with_output()?;
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8(output.stdout)?, "1:\t1\n2:\t2\n");
+    assert_eq!(String::from_utf8(output.stderr)?, "-: 10 lines\n");
+    Ok(())
+}
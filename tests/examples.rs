@@ -0,0 +1,18 @@
+// --examples shows how to use tail, without tailing anything
+
+use std::process::Command;
+
+#[test]
+fn test_examples() -> anyhow::Result<()> {
+    let output = Command::new(env!("CARGO_BIN_EXE_tail"))
+        .arg("--examples")
+        .output()?;
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stderr.is_empty());
+
+    let examples = String::from_utf8(output.stdout)?;
+    for expected in ["tail -n 50 app.log", "-f --grep ERROR", "--merge", "--also"] {
+        assert!(examples.contains(expected), "{} is missing", expected);
+    }
+    Ok(())
+}
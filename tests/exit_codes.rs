@@ -0,0 +1,87 @@
+// The exit codes listed in the help, checked by running the binary
+
+use std::process::{Command, Output};
+
+fn tail(arguments: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_tail"))
+        .args(arguments)
+        .output()
+        .expect("the binary should start")
+}
+
+#[test]
+fn test_success() -> anyhow::Result<()> {
+    let file = tempfile::NamedTempFile::new()?;
+    std::fs::write(file.path(), "first\nsecond\n")?;
+
+    let output = tail(&["-n", "1", file.path().to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8(output.stdout)?, "2:\tsecond\n");
+
+    // Stopping after the timeout is just as fine
+    let output = tail(&["-f", "--timeout", "0.2", file.path().to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0));
+
+    // So is asking for help
+    assert_eq!(tail(&["--help"]).status.code(), Some(0));
+    Ok(())
+}
+
+#[test]
+fn test_missing_file() -> anyhow::Result<()> {
+    let directory = tempfile::tempdir()?;
+    let missing = directory.path().join("missing.log");
+
+    let output = tail(&[missing.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    Ok(())
+}
+
+#[test]
+fn test_invalid_arguments() -> anyhow::Result<()> {
+    let file = tempfile::NamedTempFile::new()?;
+    let path = file.path().to_str().unwrap();
+
+    // Caught while parsing
+    assert_eq!(tail(&["--no-such-option", path]).status.code(), Some(2));
+    assert_eq!(tail(&["-n", "many", path]).status.code(), Some(2));
+    assert_eq!(tail(&["--reopen-signal", path]).status.code(), Some(2));
+    // Too long for a duration, rather than a panic
+    let forever = format!("1{}", "0".repeat(300));
+    assert_eq!(
+        tail(&["-f", "--timeout", &forever, path]).status.code(),
+        Some(2)
+    );
+    // Caught afterwards
+    assert_eq!(tail(&["--age", path]).status.code(), Some(2));
+    Ok(())
+}
+
+#[test]
+fn test_until_not_found() -> anyhow::Result<()> {
+    let file = tempfile::NamedTempFile::new()?;
+    std::fs::write(file.path(), "starting\n")?;
+    let path = file.path().to_str().unwrap();
+
+    let output = tail(&["-f", "--timeout", "0.2", "--until", "READY", path]);
+    assert_eq!(output.status.code(), Some(3));
+
+    // Found right away
+    std::fs::write(file.path(), "starting\nREADY\n")?;
+    let output = tail(&["-f", "--timeout", "5", "--until", "READY", path]);
+    assert_eq!(output.status.code(), Some(0));
+
+    // An exit code of its own takes precedence
+    let output = tail(&[
+        "-f",
+        "--timeout",
+        "0.2",
+        "--until",
+        "NEVER",
+        "--timeout-exit-code",
+        "7",
+        path,
+    ]);
+    assert_eq!(output.status.code(), Some(7));
+    Ok(())
+}
@@ -0,0 +1,151 @@
+// Following a file that keeps growing, checked by running the binary
+
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+    thread,
+    time::Duration,
+};
+
+#[test]
+fn test_follow_without_last_lines() -> anyhow::Result<()> {
+    let mut file = tempfile::NamedTempFile::new()?;
+    write!(file, "one\ntwo\nthree\n")?;
+    file.flush()?;
+
+    let child = Command::new(env!("CARGO_BIN_EXE_tail"))
+        .args(["-n", "0", "-f", "--timeout", "10", "--until", "four"])
+        .arg(file.path())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    // Give it time to read the file before it grows
+    thread::sleep(Duration::from_secs(1));
+    writeln!(file, "four")?;
+    file.flush()?;
+
+    // None of the lines that have been there before, and the new one is numbered like in the file
+    let output = child.wait_with_output()?;
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8(output.stdout)?, "4:\tfour\n");
+    Ok(())
+}
+
+#[test]
+fn test_follow_stdin_also() -> anyhow::Result<()> {
+    let mut file = tempfile::NamedTempFile::new()?;
+    writeln!(file, "from the file")?;
+    file.flush()?;
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_tail"))
+        .args([
+            "-f",
+            "--timeout",
+            "3",
+            "--until",
+            "more from the file",
+            "--also",
+        ])
+        .arg(file.path())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    // Standard input ends before the file is done, which doesn't stop following the file
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"from stdin\nmore from stdin\n")?;
+    thread::sleep(Duration::from_secs(1));
+    writeln!(file, "more from the file")?;
+    file.flush()?;
+
+    let output = child.wait_with_output()?;
+    assert_eq!(output.status.code(), Some(0));
+    let file_name = file.path().to_string_lossy();
+    assert_eq!(
+        String::from_utf8(output.stdout)?,
+        format!(
+            "{0}: 1:\tfrom the file\n-: 1:\tfrom stdin\n-: 2:\tmore from stdin\n{0}: 2:\tmore from the file\n",
+            file_name
+        )
+    );
+    Ok(())
+}
+
+#[test]
+fn test_stats_interval() -> anyhow::Result<()> {
+    let mut file = tempfile::NamedTempFile::new()?;
+    write!(file, "one\ntwo\nthree\n")?;
+    file.flush()?;
+
+    let output = Command::new(env!("CARGO_BIN_EXE_tail"))
+        .args(["-n", "3", "-f", "--timeout", "2.5", "--stats-interval", "1"])
+        .arg(file.path())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()?;
+    assert_eq!(output.status.code(), Some(0));
+
+    // E.g. "tail: stats: 3.0 lines/s, 14.0 bytes/s, 3 lines in total, running for 1s"
+    let stderr = String::from_utf8(output.stderr)?;
+    let reports = stderr
+        .lines()
+        .filter_map(|line| line.strip_prefix("tail: stats: "))
+        .collect::<Vec<_>>();
+    assert!(!reports.is_empty(), "No stats in {:?}", stderr);
+    for report in reports {
+        let numbers = report
+            .split(|character: char| !character.is_ascii_digit() && character != '.')
+            .filter_map(|number| number.parse::<f64>().ok())
+            .collect::<Vec<_>>();
+        let [lines_per_second, bytes_per_second, total, running] = numbers[..] else {
+            panic!("Unexpected stats {:?}", report);
+        };
+        assert!((0.0..=3.0).contains(&lines_per_second), "{}", report);
+        assert!((0.0..=14.0).contains(&bytes_per_second), "{}", report);
+        assert_eq!(total, 3.0);
+        assert!((1.0..=3.0).contains(&running), "{}", report);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_follow_across_rotation() -> anyhow::Result<()> {
+    let directory = tempfile::tempdir()?;
+    let path = directory.path().join("app.log");
+    let mut file = std::fs::File::create(&path)?;
+    writeln!(file, "first")?;
+
+    let child = Command::new(env!("CARGO_BIN_EXE_tail"))
+        .args([
+            "-F",
+            "--delay",
+            "500",
+            "--timeout",
+            "10",
+            "--until",
+            "after",
+        ])
+        .arg(&path)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    thread::sleep(Duration::from_secs(1));
+
+    // The last line and the rotation are announced together, once the old file has been renamed away already
+    writeln!(file, "before")?;
+    std::fs::rename(&path, directory.path().join("app.log.1"))?;
+    writeln!(std::fs::File::create(&path)?, "after")?;
+
+    let output = child.wait_with_output()?;
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8(output.stdout)?,
+        "1:\tfirst\n2:\tbefore\n1:\tafter\n"
+    );
+    Ok(())
+}
@@ -0,0 +1,40 @@
+// --print-config shows how the arguments have been understood, without tailing anything
+
+use std::process::Command;
+
+#[test]
+fn test_print_config() -> anyhow::Result<()> {
+    let output = Command::new(env!("CARGO_BIN_EXE_tail"))
+        .args([
+            "-f",
+            "--delay",
+            "250",
+            "--rate",
+            "10",
+            "--print-config",
+            "app.log",
+        ])
+        .output()?;
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+
+    // Following turns the default of 10 lines into 1, and the file doesn't have to exist
+    assert_eq!(
+        String::from_utf8(output.stderr)?,
+        concat!(
+            "files: [\"app.log\"]\n",
+            "n: 1 lines\n",
+            "start: FromEnd(0)\n",
+            "stop: FromEnd(1)\n",
+            "direction: BottomToTop\n",
+            "rate: 10.000 Hz (100ms per frame)\n",
+            "delay: 250ms\n",
+            "follow: descriptor\n",
+            "retry: false\n",
+            "format: Text\n",
+            "delimiter: '\\n'\n",
+            "encoding: UTF-8\n",
+        )
+    );
+    Ok(())
+}
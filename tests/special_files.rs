@@ -0,0 +1,40 @@
+// Files without a size to go by, like devices and the ones in /proc, are read like pipes
+#![cfg(target_os = "linux")]
+
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+#[test]
+fn test_generated_file() -> anyhow::Result<()> {
+    let version = std::fs::read_to_string("/proc/version")?;
+
+    let output = Command::new(env!("CARGO_BIN_EXE_tail"))
+        .args(["-c", "10", "/proc/version"])
+        .output()?;
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8(output.stdout)?,
+        version[version.len() - 10..]
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_tail"))
+        .args(["-n", "1", "--no-line-numbers", "/proc/version"])
+        .output()?;
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8(output.stdout)?, version);
+    Ok(())
+}
+
+#[test]
+fn test_device() -> anyhow::Result<()> {
+    // /dev/null has nothing to say right away, but it's still followed, in case it has later on
+    let started = Instant::now();
+    let output = Command::new(env!("CARGO_BIN_EXE_tail"))
+        .args(["-f", "--timeout", "0.5", "/dev/null"])
+        .output()?;
+    assert!(started.elapsed() >= Duration::from_millis(500));
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8(output.stderr)?.contains("stopping after the timeout"));
+    Ok(())
+}